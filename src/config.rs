@@ -0,0 +1,417 @@
+//! Protocols and credentials described by configuration.
+//!
+//! Requires feature `config`.
+//!
+//! A [`ProtocolConfig`] can be deserialized from any format supported by `serde`. For example,
+//! in TOML:
+//!
+//! ```toml
+//! [upstreams.api]
+//! type = "bearer"
+//! token = { env = "API_TOKEN" }
+//!
+//! [upstreams.legacy]
+//! type = "basic"
+//! username = "service"
+//! password = { file = "/run/secrets/legacy-password" }
+//! ```
+//!
+//! Calling [`ProtocolConfig::build`] reads the secrets and creates the credentials. The returned
+//! [`AuthenticationFactory`] creates a protocol for each request, for the HTTP library in use.
+
+use std::borrow::Cow;
+#[cfg(feature = "loop")]
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+#[cfg(feature = "loop")]
+use crate::credential::HttpRealmCredentials;
+#[cfg(all(feature = "jwt", feature = "step"))]
+use crate::credential::{JsonWebTokenCredential, JwtPrivateKey};
+use crate::credential::{TokenCredential, UsernamePasswordCredential};
+use crate::AuthenticError;
+#[cfg(feature = "loop")]
+use crate::{HttpNegotiation, HttpScheme};
+
+/// Source of a secret value.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretConfig {
+    /// The secret itself.
+    Value(String),
+    /// Name of an environment variable containing the secret.
+    Env(String),
+    /// Path of a file containing the secret. A trailing newline is removed.
+    File(PathBuf),
+}
+
+impl SecretConfig {
+    /// Read the secret.
+    pub fn resolve(&self) -> Result<String, AuthenticError> {
+        match self {
+            Self::Value(value) => Ok(value.clone()),
+            Self::Env(name) => std::env::var(name).map_err(|err| {
+                AuthenticError::CredentialUnavailable(format!(
+                    "Environment variable {}: {}",
+                    name, err
+                ))
+            }),
+            Self::File(path) => {
+                let mut value = std::fs::read_to_string(path)?;
+                if value.ends_with('\n') {
+                    value.pop();
+                    if value.ends_with('\r') {
+                        value.pop();
+                    }
+                }
+                Ok(value)
+            }
+        }
+    }
+}
+
+/// Source of a token for header or Bearer authentication.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenConfig {
+    /// The token itself.
+    Value(String),
+    /// Name of an environment variable containing the token.
+    Env(String),
+    /// Path of a file containing the token. A trailing newline is removed.
+    File(PathBuf),
+    /// JWTs created by a [`JsonWebTokenCredential`].
+    ///
+    /// Requires features `jwt` and `step`.
+    #[cfg(all(feature = "jwt", feature = "step"))]
+    Jwt(JwtConfig),
+}
+
+/// Configuration of a [`JsonWebTokenCredential`].
+///
+/// Requires features `jwt` and `step`.
+#[cfg(all(feature = "jwt", feature = "step"))]
+#[derive(Clone, Debug, Deserialize)]
+pub struct JwtConfig {
+    /// Signing algorithm. Defaults to `HS256`.
+    #[serde(default = "default_jwt_algorithm")]
+    pub algorithm: jsonwebtoken::Algorithm,
+    /// Secret for HMAC algorithms, or private key for other algorithms, in any format supported
+    /// by [`JwtPrivateKey`].
+    pub key: SecretConfig,
+    /// Key ID to set in the `kid` header.
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// Lifetime of each token in seconds, from 1 second to 1 year. Defaults to 10 minutes.
+    #[serde(default = "default_jwt_expiration")]
+    pub expiration: u64,
+    #[serde(default)]
+    pub issuer: Option<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// Custom claims to add to each token.
+    #[serde(default)]
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+fn default_jwt_algorithm() -> jsonwebtoken::Algorithm {
+    jsonwebtoken::Algorithm::HS256
+}
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+fn default_jwt_expiration() -> u64 {
+    600
+}
+
+/// Longest configurable token lifetime in seconds.
+#[cfg(all(feature = "jwt", feature = "step"))]
+const MAX_JWT_EXPIRATION: u64 = 365 * 24 * 60 * 60;
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+impl JwtConfig {
+    fn build(&self) -> Result<JsonWebTokenCredential, AuthenticError> {
+        use jsonwebtoken::{Algorithm, EncodingKey};
+
+        if !(1..=MAX_JWT_EXPIRATION).contains(&self.expiration) {
+            return Err(AuthenticError::Configuration(format!(
+                "JWT expiration must be from 1 to {} seconds, not {}",
+                MAX_JWT_EXPIRATION, self.expiration
+            )));
+        }
+        let key = self.key.resolve()?;
+        let (mut header, key) = match self.algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => (
+                jsonwebtoken::Header::new(self.algorithm),
+                EncodingKey::from_secret(key.as_bytes()),
+            ),
+            // Private keys can be in any format supported by `JwtPrivateKey`.
+            _ => JwtPrivateKey::from_bytes(key.as_bytes())?
+                .with_algorithm(self.algorithm)?
+                .into_header_and_key(),
+        };
+        if self.key_id.is_some() {
+            header.kid = self.key_id.clone();
+        }
+        let mut credential = JsonWebTokenCredential::new(
+            header,
+            key,
+            std::time::Duration::from_secs(self.expiration),
+        );
+        if let Some(issuer) = &self.issuer {
+            credential = credential.with_issuer(issuer.clone());
+        }
+        if let Some(subject) = &self.subject {
+            credential = credential.with_subject(subject.clone());
+        }
+        if let Some(audience) = &self.audience {
+            credential = credential.with_audience(audience.clone());
+        }
+        for (name, value) in &self.claims {
+            credential = credential.with_claim(name.clone(), value.clone())?;
+        }
+        Ok(credential)
+    }
+}
+
+impl TokenConfig {
+    fn build(&self) -> Result<TokenSource, AuthenticError> {
+        let secret = match self {
+            Self::Value(value) => SecretConfig::Value(value.clone()),
+            Self::Env(name) => SecretConfig::Env(name.clone()),
+            Self::File(path) => SecretConfig::File(path.clone()),
+            #[cfg(all(feature = "jwt", feature = "step"))]
+            Self::Jwt(jwt) => return Ok(TokenSource::Jwt(Arc::new(jwt.build()?))),
+        };
+        Ok(TokenSource::Token(Arc::new(TokenCredential::new(
+            secret.resolve()?.into_bytes(),
+        ))))
+    }
+}
+
+/// A username and password.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UsernamePasswordConfig {
+    pub username: String,
+    pub password: SecretConfig,
+}
+
+impl UsernamePasswordConfig {
+    fn build(&self) -> Result<Arc<UsernamePasswordCredential>, AuthenticError> {
+        Ok(Arc::new(UsernamePasswordCredential::new(
+            self.username.clone(),
+            self.password.resolve()?,
+        )))
+    }
+}
+
+/// An authentication protocol and its credential.
+///
+/// The protocol is selected by the `type` field.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProtocolConfig {
+    /// No authentication.
+    None,
+    /// A token in the header `name`.
+    Header { name: String, token: TokenConfig },
+    /// A token in the `Authorization` header, using the scheme `Bearer` unless `scheme` is set.
+    Bearer {
+        token: TokenConfig,
+        #[serde(default)]
+        scheme: Option<String>,
+    },
+    /// HTTP Basic authentication, sent with every request.
+    Basic {
+        username: String,
+        password: SecretConfig,
+    },
+    /// HTTP authentication in response to a challenge, with a credential for each realm.
+    ///
+    /// Requires feature `loop`.
+    #[cfg(feature = "loop")]
+    Http {
+        realms: HashMap<String, UsernamePasswordConfig>,
+        /// Schemes that may be used, most preferred first.
+        #[serde(default)]
+        schemes: Option<Vec<HttpScheme>>,
+        /// Weakest scheme that may be used.
+        #[serde(default)]
+        minimum: Option<HttpScheme>,
+        /// Weakest scheme that may be used over plain `http`.
+        #[serde(default)]
+        insecure_minimum: Option<HttpScheme>,
+    },
+}
+
+impl ProtocolConfig {
+    /// Create the credentials for the protocol, reading any secrets.
+    pub fn build(&self) -> Result<AuthenticationFactory, AuthenticError> {
+        let built = match self {
+            Self::None => Built::None,
+            Self::Header { name, token } => Built::Header(name.clone().into(), token.build()?),
+            Self::Bearer { token, scheme } => {
+                Built::Bearer(token.build()?, scheme.clone().map(Cow::Owned))
+            }
+            Self::Basic { username, password } => Built::Basic(
+                UsernamePasswordConfig {
+                    username: username.clone(),
+                    password: password.clone(),
+                }
+                .build()?,
+            ),
+            #[cfg(feature = "loop")]
+            Self::Http {
+                realms,
+                schemes,
+                minimum,
+                insecure_minimum,
+            } => {
+                let mut realm_credentials = HashMap::new();
+                for (realm, config) in realms {
+                    realm_credentials.insert(Cow::Owned(realm.clone()), config.build()?);
+                }
+                let mut negotiation = HttpNegotiation::new();
+                if let Some(schemes) = schemes {
+                    negotiation = negotiation.with_preference(schemes.iter().copied());
+                }
+                if let Some(minimum) = minimum {
+                    negotiation = negotiation.with_minimum(*minimum);
+                }
+                if let Some(minimum) = insecure_minimum {
+                    negotiation = negotiation.with_insecure_minimum(*minimum);
+                }
+                Built::Http(
+                    Arc::new(HttpRealmCredentials::new(realm_credentials)),
+                    negotiation,
+                )
+            }
+        };
+        Ok(AuthenticationFactory { built })
+    }
+}
+
+// The built credentials are only read when creating protocols for an HTTP library.
+#[cfg_attr(
+    not(any(
+        feature = "hyper",
+        feature = "reqwest-async",
+        feature = "reqwest-blocking"
+    )),
+    allow(dead_code)
+)]
+enum TokenSource {
+    Token(Arc<TokenCredential>),
+    #[cfg(all(feature = "jwt", feature = "step"))]
+    Jwt(Arc<JsonWebTokenCredential>),
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "hyper",
+        feature = "reqwest-async",
+        feature = "reqwest-blocking"
+    )),
+    allow(dead_code)
+)]
+enum Built {
+    None,
+    Header(Cow<'static, str>, TokenSource),
+    Bearer(TokenSource, Option<Cow<'static, str>>),
+    Basic(Arc<UsernamePasswordCredential>),
+    #[cfg(feature = "loop")]
+    Http(
+        Arc<HttpRealmCredentials<UsernamePasswordCredential>>,
+        HttpNegotiation,
+    ),
+}
+
+/// Creates protocols using credentials built from a [`ProtocolConfig`].
+///
+/// Credentials are shared by all protocols created by the factory. Create a new protocol for
+/// each request.
+pub struct AuthenticationFactory {
+    #[cfg_attr(
+        not(any(
+            feature = "hyper",
+            feature = "reqwest-async",
+            feature = "reqwest-blocking"
+        )),
+        allow(dead_code)
+    )]
+    built: Built,
+}
+
+// Each HTTP library module has protocols with the same names, so the same code creates them.
+#[cfg(any(
+    feature = "hyper",
+    feature = "reqwest-async",
+    feature = "reqwest-blocking"
+))]
+macro_rules! create_protocol {
+    ($built:expr, $module:path) => {{
+        use $module as module;
+
+        let protocol: Box<module::DynAuthentication> = match $built {
+            Built::None => Box::new(module::NoAuthentication::new()),
+            Built::Header(name, TokenSource::Token(credential)) => Box::new(
+                module::HeaderAuthentication::new(name.clone(), credential.clone()),
+            ),
+            #[cfg(all(feature = "jwt", feature = "step"))]
+            Built::Header(name, TokenSource::Jwt(credential)) => Box::new(
+                module::HeaderAuthentication::new(name.clone(), credential.clone()),
+            ),
+            Built::Bearer(TokenSource::Token(credential), scheme) => {
+                let protocol = module::BearerAuthentication::new(credential.clone());
+                match scheme {
+                    Some(scheme) => Box::new(protocol.with_auth_scheme(scheme.clone())),
+                    None => Box::new(protocol),
+                }
+            }
+            #[cfg(all(feature = "jwt", feature = "step"))]
+            Built::Bearer(TokenSource::Jwt(credential), scheme) => {
+                let protocol = module::BearerAuthentication::new(credential.clone());
+                match scheme {
+                    Some(scheme) => Box::new(protocol.with_auth_scheme(scheme.clone())),
+                    None => Box::new(protocol),
+                }
+            }
+            Built::Basic(credential) => {
+                Box::new(module::BasicAuthentication::new(credential.clone()))
+            }
+            #[cfg(feature = "loop")]
+            Built::Http(credential, negotiation) => {
+                Box::new(module::HttpAuthentication::with_negotiation(
+                    credential.clone(),
+                    negotiation.clone(),
+                ))
+            }
+        };
+        protocol
+    }};
+}
+
+impl AuthenticationFactory {
+    /// Create a protocol for use with `hyper`.
+    #[cfg(feature = "hyper")]
+    pub fn hyper(&self) -> Box<crate::hyper::DynAuthentication> {
+        create_protocol!(&self.built, crate::hyper)
+    }
+
+    /// Create a protocol for use with async `reqwest`.
+    #[cfg(feature = "reqwest-async")]
+    pub fn reqwest(&self) -> Box<crate::reqwest::DynAuthentication> {
+        create_protocol!(&self.built, crate::reqwest)
+    }
+
+    /// Create a protocol for use with blocking `reqwest`.
+    #[cfg(feature = "reqwest-blocking")]
+    pub fn reqwest_blocking(&self) -> Box<crate::reqwest::blocking::DynAuthentication> {
+        create_protocol!(&self.built, crate::reqwest::blocking)
+    }
+}
@@ -0,0 +1,248 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{AuthenticError, AuthenticationStep};
+
+use super::{AuthenticationCredential, RenewalNotifier};
+
+/// Time after which an unanswered request is abandoned, and other sources may make requests.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A credential source in a chain, converting its fetched value to the type of the chain.
+trait ChainSource<Fetch>: Send + Sync {
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError>;
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>);
+
+    fn fetch(&self) -> Result<Fetch, AuthenticError>;
+}
+
+struct MappedSource<Credential, Map> {
+    credential: Arc<Credential>,
+    map: Map,
+}
+
+impl<Credential, Map, Fetch> ChainSource<Fetch> for MappedSource<Credential, Map>
+where
+    Credential: AuthenticationCredential + Send + Sync,
+    Map: Fn(Credential::Fetch) -> Fetch + Send + Sync,
+{
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        self.credential.auth_step()
+    }
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        self.credential.auth_respond(response)
+    }
+
+    fn fetch(&self) -> Result<Fetch, AuthenticError> {
+        self.credential.fetch().map(&self.map)
+    }
+}
+
+/// A source with an outstanding request.
+struct PendingRequest {
+    source: usize,
+    requested: Instant,
+}
+
+/// Credential trying several sources in order, using the first that is available.
+///
+/// For example, a chain can use a token from the environment if one is set, and otherwise a
+/// token from a configuration file. The chain remembers the source that was used, and only
+/// tries the sources again, in order, when that source fails.
+///
+/// Sources that need requests, such as a token endpoint, are tried by
+/// [`AuthenticationCredential::auth_step`]. The steps of a source are completed, and its
+/// credential fetched, before the chain moves to the next source. A source that is already
+/// available may be fetched twice, once to check it in `auth_step` and again by `fetch`.
+///
+/// One source at a time has an outstanding request, so that each response is passed to the
+/// source that made the request. While it is outstanding, `auth_step` only steps that source,
+/// and callers that have no credential wait for the response.
+///
+/// If every source fails, the error is the first retryable error from a source, or otherwise
+/// the error from the last source tried.
+///
+/// Sources with a different `Fetch` type to the chain are added using
+/// [`CredentialChain::with_source_map`].
+pub struct CredentialChain<Fetch> {
+    sources: Vec<Box<dyn ChainSource<Fetch>>>,
+    // Index of the source that last succeeded.
+    current: AtomicUsize,
+    // Source with an outstanding request, if any.
+    pending: Mutex<Option<PendingRequest>>,
+    // Wakes callers waiting for the outstanding request to complete.
+    responded: RenewalNotifier,
+}
+
+impl<Fetch> CredentialChain<Fetch>
+where
+    Fetch: 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            current: AtomicUsize::new(0),
+            pending: Mutex::new(None),
+            responded: RenewalNotifier::new(),
+        }
+    }
+
+    /// Add a source to try after the sources already added.
+    #[must_use]
+    pub fn with_source<Credential>(self, credential: Arc<Credential>) -> Self
+    where
+        Credential: AuthenticationCredential<Fetch = Fetch> + Send + Sync + 'static,
+    {
+        self.with_source_map(credential, |fetched| fetched)
+    }
+
+    /// Add a source to try after the sources already added, converting its fetched value using
+    /// `map`.
+    #[must_use]
+    pub fn with_source_map<Credential, Map>(mut self, credential: Arc<Credential>, map: Map) -> Self
+    where
+        Credential: AuthenticationCredential + Send + Sync + 'static,
+        Map: Fn(Credential::Fetch) -> Fetch + Send + Sync + 'static,
+    {
+        self.sources
+            .push(Box::new(MappedSource { credential, map }));
+        self
+    }
+
+    /// The position of the source currently in use, in the order the sources were added.
+    pub fn current_source(&self) -> usize {
+        self.current.load(Ordering::Acquire)
+    }
+
+    /// Call `operation` on the current source. If it fails, call it on the other sources in
+    /// order, and make the first that succeeds the current source.
+    fn first_success<T>(
+        &self,
+        operation: impl Fn(usize, &dyn ChainSource<Fetch>) -> Result<T, AuthenticError>,
+    ) -> Result<T, AuthenticError> {
+        let current = self.current.load(Ordering::Acquire);
+        let source = match self.sources.get(current) {
+            Some(source) => source,
+            None => {
+                return Err(AuthenticError::CredentialUnavailable(
+                    "No credential sources in chain".to_owned(),
+                ))
+            }
+        };
+        let mut error = match operation(current, source.as_ref()) {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        for (index, source) in self.sources.iter().enumerate() {
+            if index == current {
+                continue;
+            }
+            match operation(index, source.as_ref()) {
+                Ok(value) => {
+                    self.current.store(index, Ordering::Release);
+                    return Ok(value);
+                }
+                // Keep the first retryable error, so that callers know to try again.
+                Err(err) => {
+                    if !error.is_retryable() {
+                        error = err;
+                    }
+                }
+            }
+        }
+        Err(error)
+    }
+}
+
+impl<Fetch> Default for CredentialChain<Fetch>
+where
+    Fetch: 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Fetch> AuthenticationCredential for CredentialChain<Fetch>
+where
+    Fetch: 'static,
+{
+    type Fetch = Fetch;
+
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        if self.sources.is_empty() {
+            return Ok(None);
+        }
+        // Hold the lock while stepping, so that only one source makes a request at a time.
+        let mut pending = self
+            .pending
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        if let Some(request) = &mut *pending {
+            let elapsed = request.requested.elapsed();
+            if elapsed < REQUEST_TIMEOUT {
+                let source = &self.sources[request.source];
+                return match source.auth_step()? {
+                    Some(step) => {
+                        if let AuthenticationStep::Request(_) = step {
+                            request.requested = Instant::now();
+                        }
+                        Ok(Some(step))
+                    }
+                    None => match source.fetch() {
+                        Ok(_) => Ok(None),
+                        Err(_) => Ok(Some(AuthenticationStep::WaitForRenewal(
+                            self.responded.waiter(REQUEST_TIMEOUT - elapsed),
+                        ))),
+                    },
+                };
+            }
+            // The request was abandoned.
+            *pending = None;
+        }
+        // A source remains current while it returns steps, and is only replaced if its steps
+        // fail or, once they complete, it cannot provide a credential.
+        let requester = Cell::new(None);
+        let step = self.first_success(|index, source| match source.auth_step()? {
+            Some(step) => {
+                if let AuthenticationStep::Request(_) = step {
+                    requester.set(Some(index));
+                }
+                Ok(Some(step))
+            }
+            None => source.fetch().map(|_| None),
+        })?;
+        *pending = requester.get().map(|source| PendingRequest {
+            source,
+            requested: Instant::now(),
+        });
+        Ok(step)
+    }
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        // The response is to the source that returned the request, which may no longer be the
+        // current source. A response to an abandoned request is dropped.
+        let pending = match self.pending.lock() {
+            Ok(mut pending) => pending.take(),
+            Err(_) => None,
+        };
+        if let Some(pending) = pending {
+            self.sources[pending.source].auth_respond(response);
+        }
+        self.responded.notify();
+    }
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        self.first_success(|_, source| source.fetch())
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time for credentials that expire.
+///
+/// Credentials use the [`SystemClock`] by default. A [`ManualClock`] allows tests to control
+/// renewal and expiry without waiting, or to simulate clock skew.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// Clock returning the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock that only changes when it is explicitly set or advanced.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    /// Create a clock set to a specific time.
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Set the time.
+    ///
+    /// The time can be moved backwards, to simulate clock adjustments.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(|poison| poison.into_inner()) = now;
+    }
+
+    /// Move the time forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(|poison| poison.into_inner()) += duration;
+    }
+}
+
+impl Default for ManualClock {
+    /// Create a clock set to the current system time.
+    fn default() -> Self {
+        Self::new(SystemTime::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|poison| poison.into_inner())
+    }
+}
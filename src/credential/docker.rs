@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use base64::Engine;
+
+use crate::AuthenticError;
+
+use super::{AuthenticationCredential, FetchedUsernamePassword};
+
+/// Username used by Docker credential helpers to indicate that the secret is an identity token.
+pub const DOCKER_IDENTITY_TOKEN_USERNAME: &str = "<token>";
+
+/// Key used by Docker for the Docker Hub registry.
+const DOCKER_HUB_KEY: &str = "https://index.docker.io/v1/";
+
+#[derive(Default, serde::Deserialize)]
+struct DockerConfigFile {
+    #[serde(default)]
+    auths: HashMap<String, DockerAuthEntry>,
+    #[serde(default, rename = "credsStore")]
+    creds_store: Option<String>,
+    #[serde(default, rename = "credHelpers")]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Default, serde::Deserialize)]
+struct DockerAuthEntry {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    identitytoken: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct DockerHelperOutput {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+/// Registry logins read from a Docker `config.json` file.
+///
+/// Logins are found in the `auths` section of the file, or by running the credential helpers
+/// named by `credsStore` and `credHelpers`. This allows the logins created by `docker login` to
+/// be used.
+pub struct DockerConfig {
+    config: DockerConfigFile,
+    helper_dir: Option<PathBuf>,
+}
+
+impl DockerConfig {
+    /// Read the Docker configuration for the current user.
+    ///
+    /// The file is read from the directory named by the `DOCKER_CONFIG` environment variable, or
+    /// from `~/.docker/config.json`. A missing file results in an empty configuration.
+    pub fn load() -> Result<Self, AuthenticError> {
+        let dir = match std::env::var_os("DOCKER_CONFIG") {
+            Some(dir) => PathBuf::from(dir),
+            None => match std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+                Some(home) => Path::new(&home).join(".docker"),
+                None => return Ok(Self::empty()),
+            },
+        };
+        let path = dir.join("config.json");
+        if path.exists() {
+            Self::from_path(path)
+        } else {
+            Ok(Self::empty())
+        }
+    }
+
+    /// Read a Docker configuration file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, AuthenticError> {
+        let contents = std::fs::read(path)?;
+        Self::from_slice(&contents)
+    }
+
+    /// Parse the contents of a Docker configuration file.
+    pub fn from_slice(contents: &[u8]) -> Result<Self, AuthenticError> {
+        let config = serde_json::from_slice(contents)
+            .map_err(|err| AuthenticError::Configuration(err.to_string()))?;
+        Ok(Self {
+            config,
+            helper_dir: None,
+        })
+    }
+
+    fn empty() -> Self {
+        Self {
+            config: DockerConfigFile::default(),
+            helper_dir: None,
+        }
+    }
+
+    /// Run credential helpers from `dir`, instead of searching for them on the `PATH`.
+    #[must_use]
+    pub fn with_helper_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.helper_dir = Some(dir.into());
+        self
+    }
+
+    /// Get the login for a registry host, such as `ghcr.io` or `docker.io`.
+    ///
+    /// A credential helper configured for the host in `credHelpers` is used first, followed by
+    /// the `credsStore` helper, and then the `auths` entries in the file.
+    ///
+    /// Returns `Ok(None)` if no login is available for the registry.
+    pub fn credential(
+        &self,
+        registry: &str,
+    ) -> Result<Option<Arc<DockerRegistryCredential>>, AuthenticError> {
+        let host = registry_host(registry);
+        let server_url = if host == registry_host(DOCKER_HUB_KEY) {
+            DOCKER_HUB_KEY
+        } else {
+            host
+        };
+
+        let helper = find_entry(&self.config.cred_helpers, registry, server_url, host)
+            .or(self.config.creds_store.as_ref());
+        if let Some(helper) = helper {
+            if let Some(credential) = run_helper(self.helper_dir.as_deref(), helper, server_url)? {
+                return Ok(Some(Arc::new(credential)));
+            }
+        }
+
+        match find_entry(&self.config.auths, registry, server_url, host) {
+            Some(entry) => Ok(DockerRegistryCredential::from_entry(entry)?.map(Arc::new)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Find the entry for a registry, preferring a key that matches the registry name exactly, then
+/// the canonical server URL, and then the first key, in sorted order, for the same host.
+fn find_entry<'a, Value>(
+    entries: &'a HashMap<String, Value>,
+    registry: &str,
+    server_url: &str,
+    host: &str,
+) -> Option<&'a Value> {
+    entries
+        .get(registry)
+        .or_else(|| entries.get(server_url))
+        .or_else(|| {
+            entries
+                .iter()
+                .filter(|(key, _)| registry_host(key) == host)
+                .min_by_key(|(key, _)| key.as_str())
+                .map(|(_, value)| value)
+        })
+}
+
+/// Reduce a registry name or URL to the host name used to compare registries.
+fn registry_host(registry: &str) -> &str {
+    let registry = registry
+        .strip_prefix("https://")
+        .or_else(|| registry.strip_prefix("http://"))
+        .unwrap_or(registry);
+    let host = registry.split('/').next().unwrap_or(registry);
+    match host {
+        "docker.io" | "registry-1.docker.io" => "index.docker.io",
+        host => host,
+    }
+}
+
+fn run_helper(
+    helper_dir: Option<&Path>,
+    helper: &str,
+    server_url: &str,
+) -> Result<Option<DockerRegistryCredential>, AuthenticError> {
+    let program = format!("docker-credential-{}", helper);
+    let mut command = match helper_dir {
+        Some(dir) => Command::new(dir.join(&program)),
+        None => Command::new(&program),
+    };
+    let mut child = command
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| AuthenticError::CredentialUnavailable(format!("{}: {}", program, err)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(server_url.as_bytes()).map_err(|err| {
+            AuthenticError::CredentialUnavailable(format!("{}: {}", program, err))
+        })?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| AuthenticError::CredentialUnavailable(format!("{}: {}", program, err)))?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stdout);
+        if message.contains("credentials not found") {
+            return Ok(None);
+        }
+        return Err(AuthenticError::CredentialUnavailable(format!(
+            "{}: {}",
+            program,
+            message.trim()
+        )));
+    }
+    let output: DockerHelperOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|err| AuthenticError::CredentialUnavailable(format!("{}: {}", program, err)))?;
+    Ok(Some(DockerRegistryCredential::new(
+        output.username,
+        output.secret,
+    )))
+}
+
+/// An implementation of [`FetchedUsernamePassword`] returned from [`DockerRegistryCredential`].
+pub struct FetchedDockerRegistryCredential {
+    username: String,
+    password: String,
+}
+
+impl FetchedDockerRegistryCredential {
+    /// Get the identity token, if the login uses one instead of a password.
+    pub fn identity_token(&self) -> Option<&str> {
+        if self.username == DOCKER_IDENTITY_TOKEN_USERNAME {
+            Some(&self.password)
+        } else {
+            None
+        }
+    }
+}
+
+/// A registry login returned from [`DockerConfig`].
+///
+/// Identity tokens are represented, as for Docker credential helpers, using the username
+/// [`DOCKER_IDENTITY_TOKEN_USERNAME`] with the token as the password.
+pub struct DockerRegistryCredential {
+    current: Arc<FetchedDockerRegistryCredential>,
+}
+
+impl DockerRegistryCredential {
+    fn new(username: String, password: String) -> Self {
+        Self {
+            current: Arc::new(FetchedDockerRegistryCredential { username, password }),
+        }
+    }
+
+    /// Create a credential from an `auths` entry.
+    ///
+    /// Returns `Ok(None)` for an empty entry, which `docker login` writes when the login is held
+    /// by a credential helper.
+    fn from_entry(entry: &DockerAuthEntry) -> Result<Option<Self>, AuthenticError> {
+        if let Some(token) = &entry.identitytoken {
+            return Ok(Some(Self::new(
+                DOCKER_IDENTITY_TOKEN_USERNAME.to_owned(),
+                token.clone(),
+            )));
+        }
+        if let Some(auth) = &entry.auth {
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(auth.trim())
+                .map_err(|err| AuthenticError::Configuration(err.to_string()))?;
+            let decoded = String::from_utf8(decoded)
+                .map_err(|err| AuthenticError::Configuration(err.to_string()))?;
+            return match decoded.split_once(':') {
+                Some((username, password)) => {
+                    Ok(Some(Self::new(username.to_owned(), password.to_owned())))
+                }
+                None => Err(AuthenticError::Configuration(
+                    "Docker auth entry is not in username:password format".to_owned(),
+                )),
+            };
+        }
+        match (&entry.username, &entry.password) {
+            (Some(username), Some(password)) => {
+                Ok(Some(Self::new(username.clone(), password.clone())))
+            }
+            (None, None) => Ok(None),
+            _ => Err(AuthenticError::Configuration(
+                "Docker auth entry contains no credentials".to_owned(),
+            )),
+        }
+    }
+}
+
+impl AuthenticationCredential for DockerRegistryCredential {
+    type Fetch = Arc<FetchedDockerRegistryCredential>;
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        Ok(self.current.clone())
+    }
+}
+
+impl FetchedUsernamePassword for Arc<FetchedDockerRegistryCredential> {
+    fn username(&self) -> &str {
+        &self.username
+    }
+    fn password(&self) -> &str {
+        &self.password
+    }
+}
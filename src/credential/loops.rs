@@ -2,23 +2,234 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::AuthenticError;
+use arc_swap::ArcSwap;
 
-use super::AuthenticationCredential;
+use crate::{AuthenticError, HttpScheme};
+
+use super::{AuthenticationCredential, Subscription, UpdateSubscribers};
+
+#[cfg(feature = "oci")]
+mod oci;
+
+#[cfg(feature = "oci")]
+pub use oci::*;
+
+/// Selects the credential to respond to a HTTP authentication challenge.
+///
+/// Implementations are provided for a `HashMap` of realm names, [`RealmPatterns`],
+/// [`RealmFallback`] and [`RealmCallback`].
+pub trait RealmResolver<Credential>: Send + Sync {
+    /// Get the credential for `realm`, in a challenge using `scheme` from `origin`.
+    ///
+    /// The origin is the scheme, host and port of the request, such as `https://example.com`,
+    /// or `None` if the request URI is relative.
+    ///
+    /// Returns `None` if no credential is available for the realm.
+    fn resolve(
+        &self,
+        origin: Option<&str>,
+        realm: &str,
+        scheme: HttpScheme,
+    ) -> Option<Arc<Credential>>;
+}
+
+impl<Credential> RealmResolver<Credential> for HashMap<Cow<'static, str>, Arc<Credential>>
+where
+    Credential: Send + Sync,
+{
+    fn resolve(
+        &self,
+        _origin: Option<&str>,
+        realm: &str,
+        _scheme: HttpScheme,
+    ) -> Option<Arc<Credential>> {
+        self.get(realm).cloned()
+    }
+}
+
+/// A realm pattern in [`RealmPatterns`].
+enum RealmPattern {
+    Glob(Cow<'static, str>),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl RealmPattern {
+    fn is_match(&self, realm: &str) -> bool {
+        match self {
+            Self::Glob(pattern) => glob_match(pattern, realm),
+            #[cfg(feature = "regex")]
+            Self::Regex(regex) => regex.is_match(realm),
+        }
+    }
+}
+
+/// Realm resolver matching realms against glob patterns or regular expressions.
+///
+/// In a glob pattern, `*` matches any sequence of characters and `?` matches any single
+/// character. Patterns are tried in the order they were added.
+pub struct RealmPatterns<Credential> {
+    patterns: Vec<(RealmPattern, Arc<Credential>)>,
+}
+
+impl<Credential> RealmPatterns<Credential> {
+    pub fn new() -> Self {
+        Self {
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Use `credential` for realms matching the glob `pattern`.
+    #[must_use]
+    pub fn with_pattern(
+        mut self,
+        pattern: impl Into<Cow<'static, str>>,
+        credential: Arc<Credential>,
+    ) -> Self {
+        self.patterns
+            .push((RealmPattern::Glob(pattern.into()), credential));
+        self
+    }
+
+    /// Use `credential` for realms matching the regular expression `regex`.
+    ///
+    /// The expression can match any part of the realm. Use `^` and `$` to match the whole realm.
+    ///
+    /// Requires feature `regex`.
+    #[cfg(feature = "regex")]
+    #[must_use]
+    pub fn with_regex(mut self, regex: regex::Regex, credential: Arc<Credential>) -> Self {
+        self.patterns.push((RealmPattern::Regex(regex), credential));
+        self
+    }
+}
+
+impl<Credential> Default for RealmPatterns<Credential> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Credential> RealmResolver<Credential> for RealmPatterns<Credential>
+where
+    Credential: Send + Sync,
+{
+    fn resolve(
+        &self,
+        _origin: Option<&str>,
+        realm: &str,
+        _scheme: HttpScheme,
+    ) -> Option<Arc<Credential>> {
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(realm))
+            .map(|(_, credential)| credential.clone())
+    }
+}
+
+/// Match `text` against a glob `pattern` containing `*` and `?` wildcards.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern, and the text position it currently matches up to.
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last `*` match one more character.
+            star = Some((star_p, star_t + 1));
+            p = star_p + 1;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Realm resolver using a fallback credential for realms not found by another resolver.
+pub struct RealmFallback<Resolver, Credential> {
+    resolver: Resolver,
+    fallback: Arc<Credential>,
+}
+
+impl<Resolver, Credential> RealmFallback<Resolver, Credential> {
+    pub fn new(resolver: Resolver, fallback: Arc<Credential>) -> Self {
+        Self { resolver, fallback }
+    }
+}
+
+impl<Resolver, Credential> RealmResolver<Credential> for RealmFallback<Resolver, Credential>
+where
+    Resolver: RealmResolver<Credential>,
+    Credential: Send + Sync,
+{
+    fn resolve(
+        &self,
+        origin: Option<&str>,
+        realm: &str,
+        scheme: HttpScheme,
+    ) -> Option<Arc<Credential>> {
+        self.resolver
+            .resolve(origin, realm, scheme)
+            .or_else(|| Some(self.fallback.clone()))
+    }
+}
+
+/// Realm resolver calling a function for each challenge.
+///
+/// The function is called when a challenge is received, so it can look up or prompt for
+/// credentials that cannot be listed in advance.
+pub struct RealmCallback<Callback> {
+    callback: Callback,
+}
+
+impl<Callback> RealmCallback<Callback> {
+    pub fn new(callback: Callback) -> Self {
+        Self { callback }
+    }
+}
+
+impl<Callback, Credential> RealmResolver<Credential> for RealmCallback<Callback>
+where
+    Callback: Fn(Option<&str>, &str, HttpScheme) -> Option<Arc<Credential>> + Send + Sync,
+{
+    fn resolve(
+        &self,
+        origin: Option<&str>,
+        realm: &str,
+        scheme: HttpScheme,
+    ) -> Option<Arc<Credential>> {
+        (self.callback)(origin, realm, scheme)
+    }
+}
 
 pub struct FetchedHttpRealmCredentials<Credential> {
-    realm_credentials: HashMap<Cow<'static, str>, Arc<Credential>>,
+    resolver: Box<dyn RealmResolver<Credential>>,
 }
 
-/// Map of realms to another type of credential.
+/// Credentials for HTTP realms, selected using a [`RealmResolver`].
 ///
 /// For HTTP authentication, this selects the correct credential for the realm
 /// returned by the `www-authenticate` header.
+///
+/// The realms can be replaced while in use by calling [`HttpRealmCredentials::update`] or
+/// [`HttpRealmCredentials::update_resolver`].
 pub struct HttpRealmCredentials<Credential> {
-    current: Arc<FetchedHttpRealmCredentials<Credential>>,
+    current: ArcSwap<FetchedHttpRealmCredentials<Credential>>,
+    subscribers: UpdateSubscribers<Arc<FetchedHttpRealmCredentials<Credential>>>,
 }
 
-impl<Credential> HttpRealmCredentials<Credential> {
+impl<Credential> HttpRealmCredentials<Credential>
+where
+    Credential: Send + Sync + 'static,
+{
     /// Create a set of credentials mapped to HTTP realms.
     ///
     /// When a `www-authenticate` header is returned from a HTTP request, the realm will
@@ -26,26 +237,75 @@ impl<Credential> HttpRealmCredentials<Credential> {
     ///
     /// Takes a HashMap mapping realm names to another credential type. For example, for HTTP Basic
     /// authentication each realm maps to a [`super::UsernamePasswordCredential`].
+    ///
+    /// The credential type must be `Send + Sync + 'static`, so that the realms can be replaced
+    /// while in use by other threads.
     pub fn new(realm_credentials: HashMap<Cow<'static, str>, Arc<Credential>>) -> Self {
+        Self::from_resolver(realm_credentials)
+    }
+
+    /// Create a set of credentials selected by `resolver` when a challenge is received.
+    pub fn from_resolver(resolver: impl RealmResolver<Credential> + 'static) -> Self {
         Self {
-            current: Arc::new(FetchedHttpRealmCredentials { realm_credentials }),
+            current: ArcSwap::from_pointee(FetchedHttpRealmCredentials {
+                resolver: Box::new(resolver),
+            }),
+            subscribers: UpdateSubscribers::new(),
         }
     }
+
+    /// Replace the realms used by later calls to `fetch`.
+    ///
+    /// Operations that have already fetched the credential continue to use the previous realms.
+    pub fn update(&self, realm_credentials: HashMap<Cow<'static, str>, Arc<Credential>>) {
+        self.update_resolver(realm_credentials);
+    }
+
+    /// Replace the resolver used by later calls to `fetch`.
+    pub fn update_resolver(&self, resolver: impl RealmResolver<Credential> + 'static) {
+        let fetched = Arc::new(FetchedHttpRealmCredentials {
+            resolver: Box::new(resolver),
+        });
+        self.current.store(fetched.clone());
+        self.subscribers.notify(&fetched);
+    }
+
+    /// Call `callback` with the new realms after each update, until the returned subscription is
+    /// dropped.
+    pub fn subscribe(
+        &self,
+        callback: impl Fn(&Arc<FetchedHttpRealmCredentials<Credential>>) + Send + Sync + 'static,
+    ) -> Subscription {
+        self.subscribers.subscribe(callback)
+    }
 }
 
 impl<Credential> AuthenticationCredential for HttpRealmCredentials<Credential> {
     type Fetch = Arc<FetchedHttpRealmCredentials<Credential>>;
 
     fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
-        Ok(self.current.clone())
+        Ok(self.current.load_full())
     }
 }
 
 impl<Credential> FetchedHttpRealmCredentials<Credential> {
-    /// Get the correct credential for a specified realm.
+    /// Get the credential for a realm, as for a Basic challenge with an unknown origin.
+    ///
+    /// Returns `None` if no credential is available for the realm.
+    #[deprecated(note = "Use `resolve`, which also receives the request origin and scheme")]
+    pub fn credential(&self, realm: &str) -> Option<Arc<Credential>> {
+        self.resolve(None, realm, HttpScheme::Basic)
+    }
+
+    /// Get the correct credential for a challenge.
     ///
-    /// Returns `None` if no credential has been specified for the realm.
-    pub fn credential(&self, realm: &str) -> Option<&Arc<Credential>> {
-        self.realm_credentials.get(realm)
+    /// Returns `None` if no credential is available for the realm.
+    pub fn resolve(
+        &self,
+        origin: Option<&str>,
+        realm: &str,
+        scheme: HttpScheme,
+    ) -> Option<Arc<Credential>> {
+        self.resolver.resolve(origin, realm, scheme)
     }
 }
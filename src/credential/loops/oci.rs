@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+#[cfg(any(feature = "hyper", feature = "reqwest"))]
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::credential::{
+    AuthenticationCredential, Clock, RenewalPolicy, SystemClock, UsernamePasswordCredential,
+};
+use crate::{AuthenticError, AuthenticationStep};
+
+/// Lifetime assumed for registry tokens that are returned without an `expires_in` field.
+#[cfg(any(feature = "hyper", feature = "reqwest"))]
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Login username indicating that the password is an identity token, as used by Docker.
+#[cfg(any(feature = "hyper", feature = "reqwest"))]
+const IDENTITY_TOKEN_USERNAME: &str = "<token>";
+
+/// Client identifier sent when exchanging an identity token.
+#[cfg(any(feature = "hyper", feature = "reqwest"))]
+const CLIENT_ID: &str = "authentic";
+
+/// A Bearer challenge returned in the `www-authenticate` header by an OCI registry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OciChallenge {
+    realm: String,
+    service: Option<String>,
+    scope: Option<String>,
+}
+
+impl OciChallenge {
+    /// Find the first Bearer challenge in a set of `www-authenticate` headers.
+    ///
+    /// Returns `Ok(None)` if the headers do not contain a Bearer challenge with a realm.
+    pub fn from_headers(headers: &http::HeaderMap) -> Result<Option<Self>, AuthenticError> {
+        for value in headers.get_all(http::header::WWW_AUTHENTICATE) {
+            let value = value
+                .to_str()
+                .map_err(|err| AuthenticError::ChallengeParse(err.to_string()))?;
+            let challenges = http_auth::parse_challenges(value)
+                .map_err(|err| AuthenticError::ChallengeParse(err.to_string()))?;
+            for challenge in challenges {
+                if !challenge.scheme.eq_ignore_ascii_case("Bearer") {
+                    continue;
+                }
+                let mut realm = None;
+                let mut service = None;
+                let mut scope = None;
+                for (name, value) in &challenge.params {
+                    if name.eq_ignore_ascii_case("realm") {
+                        realm = Some(value.to_unescaped());
+                    } else if name.eq_ignore_ascii_case("service") {
+                        service = Some(value.to_unescaped());
+                    } else if name.eq_ignore_ascii_case("scope") {
+                        scope = Some(value.to_unescaped());
+                    }
+                }
+                if let Some(realm) = realm {
+                    return Ok(Some(Self {
+                        realm,
+                        service,
+                        scope,
+                    }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The URL of the token server.
+    pub fn realm(&self) -> &str {
+        &self.realm
+    }
+
+    /// The name of the registry service, if provided.
+    pub fn service(&self) -> Option<&str> {
+        self.service.as_deref()
+    }
+
+    /// The requested scope, if provided, such as `repository:samalba/my-app:pull`.
+    pub fn scope(&self) -> Option<&str> {
+        self.scope.as_deref()
+    }
+
+    /// Create the request to get a token from the token server.
+    ///
+    /// If `login` is provided, the request uses HTTP Basic authentication. Otherwise an anonymous
+    /// token is requested.
+    ///
+    /// If the login username is `<token>`, the password is an identity token, which is exchanged
+    /// for a registry token using an OAuth2 refresh token grant.
+    #[cfg(any(feature = "hyper", feature = "reqwest"))]
+    pub(crate) fn token_request<Fetch>(
+        &self,
+        login: Option<&Fetch>,
+    ) -> Result<http::Request<Vec<u8>>, AuthenticError>
+    where
+        Fetch: crate::credential::FetchedUsernamePassword,
+    {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(service) = &self.service {
+            query.append_pair("service", service);
+        }
+        if let Some(scope) = &self.scope {
+            // Multiple scopes may be requested, separated by spaces.
+            for scope in scope.split(' ').filter(|scope| !scope.is_empty()) {
+                query.append_pair("scope", scope);
+            }
+        }
+        let builder = match login {
+            Some(login) if login.username() == IDENTITY_TOKEN_USERNAME => {
+                let body = query
+                    .append_pair("grant_type", "refresh_token")
+                    .append_pair("client_id", CLIENT_ID)
+                    .append_pair("refresh_token", login.password())
+                    .finish();
+                return http::Request::post(&self.realm)
+                    .header(
+                        http::header::CONTENT_TYPE,
+                        "application/x-www-form-urlencoded",
+                    )
+                    .body(body.into_bytes())
+                    .map_err(AuthenticError::from);
+            }
+            Some(login) => {
+                let value =
+                    http_auth::basic::encode_credentials(login.username(), login.password());
+                let mut header_value = http::HeaderValue::try_from(value)?;
+                header_value.set_sensitive(true);
+                http::Request::get(self.token_uri(&query.finish()))
+                    .header(http::header::AUTHORIZATION, header_value)
+            }
+            None => http::Request::get(self.token_uri(&query.finish())),
+        };
+        Ok(builder.body(Vec::new())?)
+    }
+
+    #[cfg(any(feature = "hyper", feature = "reqwest"))]
+    fn token_uri(&self, query: &str) -> String {
+        if query.is_empty() {
+            self.realm.clone()
+        } else if self.realm.contains('?') {
+            format!("{}&{}", self.realm, query)
+        } else {
+            format!("{}?{}", self.realm, query)
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.realm,
+            self.service.as_deref().unwrap_or_default(),
+            self.scope.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "reqwest"))]
+#[derive(serde::Deserialize)]
+struct OciTokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+/// A registry token returned from [`OciRegistryCredentials`].
+pub struct FetchedOciToken {
+    token: String,
+    renew: SystemTime,
+}
+
+impl FetchedOciToken {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+/// Credentials for an OCI (Docker) registry.
+///
+/// Registries respond to unauthenticated requests with a Bearer challenge naming a token server
+/// and the scope required for the request. This credential holds the optional login for the token
+/// server and caches the returned tokens for each scope, so that later requests for the same scope
+/// do not need to contact the token server.
+pub struct OciRegistryCredentials<Credential = UsernamePasswordCredential> {
+    login: Option<Arc<Credential>>,
+    tokens: Mutex<HashMap<String, Arc<FetchedOciToken>>>,
+    renewal: RenewalPolicy,
+    clock: Arc<dyn Clock>,
+}
+
+impl OciRegistryCredentials {
+    /// Create registry credentials that request anonymous tokens.
+    pub fn anonymous() -> Self {
+        Self {
+            login: None,
+            tokens: Mutex::new(HashMap::new()),
+            renewal: RenewalPolicy::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl<Credential> OciRegistryCredentials<Credential> {
+    /// Create registry credentials that use HTTP Basic authentication with the token server.
+    pub fn new(login: Arc<Credential>) -> Self {
+        Self {
+            login: Some(login),
+            tokens: Mutex::new(HashMap::new()),
+            renewal: RenewalPolicy::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Set when cached tokens are renewed.
+    #[must_use]
+    pub fn with_renewal_policy(mut self, renewal: RenewalPolicy) -> Self {
+        self.renewal = renewal;
+        self
+    }
+
+    /// Use a different clock to decide when cached tokens must be renewed.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Get a cached token for a challenge.
+    ///
+    /// Returns `None` if no token has been cached, or the cached token is due to be renewed.
+    pub fn token(&self, challenge: &OciChallenge) -> Option<Arc<FetchedOciToken>> {
+        let tokens = self.tokens.lock().ok()?;
+        tokens
+            .get(&challenge.cache_key())
+            .filter(|token| self.clock.now() < token.renew)
+            .cloned()
+    }
+
+    /// Remove a cached token that has been rejected by the registry.
+    #[cfg(any(feature = "hyper", feature = "reqwest"))]
+    pub(crate) fn remove_token(&self, challenge: &OciChallenge) {
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.remove(&challenge.cache_key());
+        }
+    }
+
+    /// Parse the response from the token server and cache the token.
+    #[cfg(any(feature = "hyper", feature = "reqwest"))]
+    pub(crate) fn store_token(
+        &self,
+        challenge: &OciChallenge,
+        response: http::Response<Vec<u8>>,
+    ) -> Result<Arc<FetchedOciToken>, AuthenticError> {
+        if !response.status().is_success() {
+            return Err(AuthenticError::token_endpoint(
+                response.status(),
+                response.body(),
+            ));
+        }
+        let body: OciTokenResponse = serde_json::from_slice(response.body())
+            .map_err(|err| AuthenticError::TokenResponse(err.to_string()))?;
+        let token = body
+            .token
+            .or(body.access_token)
+            .ok_or_else(|| AuthenticError::TokenResponse("No token returned".to_owned()))?;
+        let lifetime = body
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+        let now = self.clock.now();
+        let expiry = now
+            .checked_add(lifetime)
+            .ok_or_else(|| AuthenticError::TokenResponse("Invalid expires_in".to_owned()))?;
+        let fetched = Arc::new(FetchedOciToken {
+            token,
+            renew: self.renewal.renew_time(now, expiry),
+        });
+        self.tokens
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?
+            .insert(challenge.cache_key(), fetched.clone());
+        Ok(fetched)
+    }
+}
+
+impl<Credential> AuthenticationCredential for OciRegistryCredentials<Credential>
+where
+    Credential: AuthenticationCredential,
+{
+    type Fetch = Option<<Credential as AuthenticationCredential>::Fetch>;
+
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        match &self.login {
+            Some(login) => login.auth_step(),
+            None => Ok(None),
+        }
+    }
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        if let Some(login) = &self.login {
+            login.auth_respond(response);
+        }
+    }
+
+    /// Get the login for the token server, or `None` for anonymous access.
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        self.login.as_ref().map(|login| login.fetch()).transpose()
+    }
+}
@@ -1,28 +1,56 @@
-use std::time::Duration;
-
-use crate::AuthenticError;
+use crate::{AuthenticError, AuthenticationStep};
 
+mod chain;
+mod clock;
+#[cfg(feature = "docker")]
+mod docker;
 #[cfg(feature = "loop")]
 mod loops;
+#[cfg(feature = "refresh")]
+mod refresh;
+mod renewal;
 mod simple;
-#[cfg(feature = "step")]
+#[cfg(all(feature = "step", any(feature = "jwt", feature = "oauth2")))]
 mod step;
+mod update;
 
+pub use chain::*;
+pub use clock::*;
+#[cfg(feature = "docker")]
+pub use docker::*;
 #[cfg(feature = "loop")]
 pub use loops::*;
+#[cfg(feature = "refresh")]
+pub use refresh::*;
+pub use renewal::*;
 pub use simple::*;
-#[cfg(feature = "step")]
+#[cfg(all(feature = "step", any(feature = "jwt", feature = "oauth2")))]
 pub use step::*;
+pub use update::*;
 
 pub trait AuthenticationCredential {
     type Fetch;
 
     /// Called to perform any processing required for a credential.
     ///
-    /// Returns `Ok(Duration::ZERO)` if no further processing is required.  Returns a non-zero
-    /// duration if the caller should wait for the duration and call this method again.
-    fn auth_step(&self) -> Result<Duration, AuthenticError> {
-        Ok(Duration::ZERO)
+    /// Returns `Ok(None)` if no further processing is required.  Returns `WaitFor` if the caller
+    /// should wait for the duration and call this method again.  Returns `WaitForRenewal` if the
+    /// caller should wait for another caller to renew the credential, and call this method again.
+    /// Returns `Request` if the caller should send the request, pass the response to
+    /// [`AuthenticationCredential::auth_respond`], and call this method again.
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        Ok(None)
+    }
+
+    /// Called with the response to a request returned from `auth_step`.
+    ///
+    /// Errors are reported by the next call to `auth_step`.
+    fn auth_respond(
+        &self,
+        #[allow(unused_variables)] response: Result<http::Response<Vec<u8>>, AuthenticError>,
+    ) {
     }
 
     /// Get an object containing the credentials to use for an operation.
@@ -41,7 +69,24 @@ pub trait FetchedToken {
     fn token(&self) -> &[u8];
 }
 
+/// Allows sources with different types to be combined, for example in a [`CredentialChain`].
+impl FetchedToken for Box<dyn FetchedToken + Send + Sync> {
+    fn token(&self) -> &[u8] {
+        (**self).token()
+    }
+}
+
 pub trait FetchedUsernamePassword {
     fn username(&self) -> &str;
     fn password(&self) -> &str;
 }
+
+/// Allows sources with different types to be combined, for example in a [`CredentialChain`].
+impl FetchedUsernamePassword for Box<dyn FetchedUsernamePassword + Send + Sync> {
+    fn username(&self) -> &str {
+        (**self).username()
+    }
+    fn password(&self) -> &str {
+        (**self).password()
+    }
+}
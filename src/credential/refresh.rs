@@ -0,0 +1,258 @@
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::credential::RenewableCredential;
+use crate::{AuthenticError, AuthenticationStep};
+
+/// Default time before the renew time at which the refresher renews a token.
+const DEFAULT_LEAD: Duration = Duration::from_secs(30);
+
+/// Default time to wait after a failed refresh before trying again.
+const DEFAULT_RETRY: Duration = Duration::from_secs(10);
+
+/// Number of unread outcomes kept by a refresher. Later outcomes are dropped until the owner
+/// reads them.
+const OUTCOME_CAPACITY: usize = 16;
+
+/// Outcome of each refresh made by a [`CredentialRefresher`].
+pub type RefreshOutcome = Result<(), AuthenticError>;
+
+/// Renews a credential in the background, before requests find that it is due to be renewed.
+///
+/// The refresher runs on a thread, using [`CredentialRefresher::spawn_thread`], or a tokio task,
+/// using [`CredentialRefresher::spawn_task`] (requires the `tokio` feature). Credentials that make
+/// requests to renew a token use the function passed to these methods to send the request.
+///
+/// Requests continue to renew the credential themselves if the refresher fails or falls behind.
+pub struct CredentialRefresher<Credential> {
+    credential: Arc<Credential>,
+    lead: Duration,
+    retry: Duration,
+}
+
+impl<Credential> CredentialRefresher<Credential>
+where
+    Credential: RenewableCredential + Send + Sync + 'static,
+{
+    pub fn new(credential: Arc<Credential>) -> Self {
+        Self {
+            credential,
+            lead: DEFAULT_LEAD,
+            retry: DEFAULT_RETRY,
+        }
+    }
+
+    /// Renew tokens `lead` before they are due to be renewed. Defaults to 30 seconds.
+    ///
+    /// The lead should be shorter than the time between renewals, or the token is renewed
+    /// at every retry interval.
+    #[must_use]
+    pub fn with_lead(mut self, lead: Duration) -> Self {
+        self.lead = lead;
+        self
+    }
+
+    /// Wait for `retry` after a failed refresh before trying again. Defaults to 10 seconds.
+    #[must_use]
+    pub fn with_retry_interval(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Time to wait before the next refresh.
+    fn delay(&self, outcome: &RefreshOutcome) -> Duration {
+        match outcome {
+            Ok(()) => self
+                .credential
+                .renew_in()
+                .map(|renew_in| renew_in.checked_sub(self.lead).unwrap_or_default())
+                // If the token is still due, another caller is renewing it.
+                .filter(|delay| !delay.is_zero())
+                .unwrap_or(self.retry),
+            Err(_) => self.retry,
+        }
+    }
+
+    /// Run the refresher on a new thread.
+    ///
+    /// The `execute` function sends requests made by the credential and returns the response.
+    pub fn spawn_thread<Execute>(self, execute: Execute) -> RefreshThread
+    where
+        Execute: FnMut(http::Request<Vec<u8>>) -> Result<http::Response<Vec<u8>>, AuthenticError>
+            + Send
+            + 'static,
+    {
+        let (sender, outcomes) = mpsc::sync_channel(OUTCOME_CAPACITY);
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut execute = execute;
+            loop {
+                let outcome = self.refresh_blocking(&mut execute);
+                let delay = self.delay(&outcome);
+                // The owner of the handle does not have to read the outcomes.
+                let _ = sender.try_send(outcome);
+                let (stopped, condvar) = &*thread_stop;
+                let stopped = stopped.lock().unwrap_or_else(|poison| poison.into_inner());
+                let (stopped, _) = condvar
+                    .wait_timeout_while(stopped, delay, |stopped| !*stopped)
+                    .unwrap_or_else(|poison| poison.into_inner());
+                if *stopped {
+                    break;
+                }
+            }
+        });
+        RefreshThread {
+            stop,
+            thread: Some(thread),
+            outcomes,
+        }
+    }
+
+    fn refresh_blocking<Execute>(&self, execute: &mut Execute) -> RefreshOutcome
+    where
+        Execute: FnMut(http::Request<Vec<u8>>) -> Result<http::Response<Vec<u8>>, AuthenticError>,
+    {
+        while let Some(auth_step) = self.credential.refresh_step(self.lead)? {
+            match auth_step {
+                AuthenticationStep::Request(request) => {
+                    let response = execute(request);
+                    self.credential.auth_respond(response);
+                }
+                AuthenticationStep::WaitFor(duration) => {
+                    std::thread::sleep(duration);
+                }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.wait();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the refresher on a new tokio task.
+    ///
+    /// The `execute` function sends requests made by the credential and returns the response.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn spawn_task<Execute, Response>(self, execute: Execute) -> RefreshTask
+    where
+        Execute: FnMut(http::Request<Vec<u8>>) -> Response + Send + 'static,
+        Response:
+            std::future::Future<Output = Result<http::Response<Vec<u8>>, AuthenticError>> + Send,
+    {
+        let (sender, outcomes) = tokio::sync::mpsc::channel(OUTCOME_CAPACITY);
+        let (stop, mut stopped) = tokio::sync::watch::channel(false);
+        let task = tokio::spawn(async move {
+            let mut execute = execute;
+            loop {
+                let outcome = self.refresh_async(&mut execute).await;
+                let delay = self.delay(&outcome);
+                // The owner of the handle does not have to read the outcomes.
+                let _ = sender.try_send(outcome);
+                // Stop when signalled, or when the handle is dropped.
+                if tokio::time::timeout(delay, stopped.changed()).await.is_ok() {
+                    break;
+                }
+            }
+        });
+        RefreshTask {
+            stop,
+            task,
+            outcomes,
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn refresh_async<Execute, Response>(&self, execute: &mut Execute) -> RefreshOutcome
+    where
+        Execute: FnMut(http::Request<Vec<u8>>) -> Response,
+        Response: std::future::Future<Output = Result<http::Response<Vec<u8>>, AuthenticError>>,
+    {
+        while let Some(auth_step) = self.credential.refresh_step(self.lead)? {
+            match auth_step {
+                AuthenticationStep::Request(request) => {
+                    let response = execute(request).await;
+                    self.credential.auth_respond(response);
+                }
+                AuthenticationStep::WaitFor(duration) => {
+                    tokio::time::sleep(duration).await;
+                }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    let _ = tokio::time::timeout(waiter.timeout(), waiter).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handle for a refresher running on a thread.
+///
+/// Dropping the handle stops the refresher, blocking until a refresh in progress completes and
+/// the thread exits.
+pub struct RefreshThread {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+    outcomes: mpsc::Receiver<RefreshOutcome>,
+}
+
+impl RefreshThread {
+    /// Receiver for the outcome of each refresh.
+    ///
+    /// Up to 16 unread outcomes are kept, and later outcomes are dropped until they are read.
+    pub fn outcomes(&self) -> &mpsc::Receiver<RefreshOutcome> {
+        &self.outcomes
+    }
+
+    /// Stop the refresher and wait for the thread to exit.
+    ///
+    /// A token request in progress completes before the thread exits, so that callers waiting
+    /// for the renewal are not left waiting.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for RefreshThread {
+    fn drop(&mut self) {
+        let (stopped, condvar) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|poison| poison.into_inner()) = true;
+        condvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Handle for a refresher running on a tokio task.
+///
+/// Dropping the handle stops the refresher. A refresh in progress continues on the task after the
+/// handle is dropped; use [`RefreshTask::stop`] to wait for the task to exit.
+#[cfg(feature = "tokio")]
+pub struct RefreshTask {
+    stop: tokio::sync::watch::Sender<bool>,
+    task: tokio::task::JoinHandle<()>,
+    outcomes: tokio::sync::mpsc::Receiver<RefreshOutcome>,
+}
+
+#[cfg(feature = "tokio")]
+impl RefreshTask {
+    /// Receiver for the outcome of each refresh.
+    ///
+    /// Up to 16 unread outcomes are kept, and later outcomes are dropped until they are read.
+    pub fn outcomes(&mut self) -> &mut tokio::sync::mpsc::Receiver<RefreshOutcome> {
+        &mut self.outcomes
+    }
+
+    /// Stop the refresher.
+    ///
+    /// A token request in progress completes before the task exits, so that callers waiting for
+    /// the renewal are not left waiting. The returned handle completes when the task exits.
+    pub fn stop(self) -> tokio::task::JoinHandle<()> {
+        let _ = self.stop.send(true);
+        self.task
+    }
+}
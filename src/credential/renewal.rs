@@ -0,0 +1,285 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::credential::AuthenticationCredential;
+use crate::{AuthenticError, AuthenticationStep};
+
+/// A credential holding a token that is renewed before it expires.
+///
+/// Allows the token to be renewed before it is due, for example by a background refresher, so
+/// that requests do not wait for the renewal.
+pub trait RenewableCredential: AuthenticationCredential {
+    /// Get the time until the current token is due to be renewed.
+    ///
+    /// Returns `None` if no token has been created, and zero if the token is due for renewal.
+    fn renew_in(&self) -> Option<Duration>;
+
+    /// Perform the same processing as [`AuthenticationCredential::auth_step`], but renew the
+    /// token if it is due to be renewed within `lead`.
+    fn refresh_step(
+        &self,
+        lead: Duration,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError>;
+}
+
+/// Policy deciding when a renewable credential renews its token.
+///
+/// The renew time is the earlier of a fraction of the token lifetime and a fixed margin before
+/// the token expires, brought forward by a random jitter. The default policy renews tokens after
+/// half their lifetime, with no jitter. A failed renewal is retried after 10 seconds.
+///
+/// A larger fraction reduces the number of requests to a token endpoint. Jitter prevents many
+/// processes started at the same time from renewing their tokens together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenewalPolicy {
+    fraction: f64,
+    margin: Duration,
+    jitter: Duration,
+    retry: Duration,
+}
+
+impl RenewalPolicy {
+    pub fn new() -> Self {
+        Self {
+            fraction: 0.5,
+            margin: Duration::ZERO,
+            jitter: Duration::ZERO,
+            retry: Duration::from_secs(10),
+        }
+    }
+
+    /// Renew tokens after a fraction of their lifetime, greater than 0.0 and at most 1.0.
+    ///
+    /// Fractions above 1.0 are treated as 1.0. Returns an error if `fraction` is zero, negative
+    /// or NaN, as the token would be renewed on every request.
+    pub fn with_fraction(mut self, fraction: f64) -> Result<Self, AuthenticError> {
+        if fraction.is_nan() || fraction <= 0.0 {
+            return Err(AuthenticError::Configuration(format!(
+                "Renewal fraction must be greater than 0.0, got {}",
+                fraction
+            )));
+        }
+        self.fraction = fraction.min(1.0);
+        Ok(self)
+    }
+
+    /// Renew tokens at least `margin` before they expire.
+    #[must_use]
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Renew tokens up to `jitter` earlier, chosen randomly for each token.
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Wait `retry` after a failed renewal before trying again.
+    ///
+    /// Callers continue to use the current token while it is valid.
+    #[must_use]
+    pub fn with_retry_interval(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Get the time to retry a renewal that failed at `failed`, for a token that expires at
+    /// `expiry`, if there is a token.
+    ///
+    /// A renewal is retried no later than the expiry of a current token that is still valid.
+    pub fn retry_time(&self, failed: SystemTime, expiry: Option<SystemTime>) -> SystemTime {
+        let retry = failed + self.retry;
+        match expiry {
+            Some(expiry) if expiry > failed => retry.min(expiry),
+            _ => retry,
+        }
+    }
+
+    /// Get the time to renew a token issued at `issued` that expires at `expiry`.
+    ///
+    /// The renew time is never earlier than `issued`.
+    pub fn renew_time(&self, issued: SystemTime, expiry: SystemTime) -> SystemTime {
+        let lifetime = expiry.duration_since(issued).unwrap_or_default();
+        let renew_after = lifetime
+            .mul_f64(self.fraction)
+            .min(lifetime.checked_sub(self.margin).unwrap_or_default());
+        let jitter = if self.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            // Each `RandomState` is randomly seeded, avoiding a dependency on a random number
+            // generator for a value that does not need to be secure.
+            let random = RandomState::new().build_hasher().finish();
+            self.jitter.mul_f64(random as f64 / u64::MAX as f64)
+        };
+        issued + renew_after.checked_sub(jitter).unwrap_or_default()
+    }
+}
+
+impl Default for RenewalPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct NotifierState {
+    // Incremented on each notification.
+    generation: u64,
+    // Identifier for the next waiter.
+    next_waiter: u64,
+    // Async waiters to wake on the next notification.
+    wakers: Vec<(u64, Waker)>,
+}
+
+struct NotifierInner {
+    state: Mutex<NotifierState>,
+    condvar: Condvar,
+}
+
+impl NotifierInner {
+    fn lock(&self) -> MutexGuard<'_, NotifierState> {
+        // The state is always consistent, so ignore poisoning.
+        self.state
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+}
+
+/// Wakes callers waiting for a credential to be renewed.
+///
+/// A renewable credential holds a notifier. When a caller finds that the current token has
+/// expired and another caller is renewing it, the credential returns a [`RenewalWaiter`] in an
+/// [`crate::AuthenticationStep::WaitForRenewal`] step. The renewing caller calls
+/// [`RenewalNotifier::notify`] after storing the new token, waking all waiters at once instead
+/// of having them poll.
+#[derive(Clone)]
+pub struct RenewalNotifier {
+    inner: Arc<NotifierInner>,
+}
+
+impl RenewalNotifier {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(NotifierInner {
+                state: Mutex::new(NotifierState {
+                    generation: 0,
+                    next_waiter: 0,
+                    wakers: Vec::new(),
+                }),
+                condvar: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Create a waiter that completes on the next notification.
+    ///
+    /// To avoid missing a notification, create the waiter first, and then check again whether
+    /// the renewal has completed before returning the waiter.
+    ///
+    /// The caller should stop waiting after `timeout`, and retry in case the renewal was
+    /// abandoned.
+    pub fn waiter(&self, timeout: Duration) -> RenewalWaiter {
+        let mut state = self.inner.lock();
+        let id = state.next_waiter;
+        state.next_waiter = state.next_waiter.wrapping_add(1);
+        RenewalWaiter {
+            inner: self.inner.clone(),
+            generation: state.generation,
+            id,
+            timeout,
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    /// Wake all waiters.
+    ///
+    /// Call this after a renewal completes, whether it succeeded or failed.
+    pub fn notify(&self) {
+        let wakers = {
+            let mut state = self.inner.lock();
+            state.generation = state.generation.wrapping_add(1);
+            std::mem::take(&mut state.wakers)
+        };
+        self.inner.condvar.notify_all();
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for RenewalNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Waits for a credential renewal by another caller to complete.
+///
+/// For blocking code, call [`RenewalWaiter::wait`], which returns when the renewal completes, or
+/// when the timeout expires. For async code, `.await` the waiter using the timeout of the async
+/// runtime, for example `tokio::time::timeout(waiter.timeout(), waiter).await`. The waiter does
+/// not start a timer itself, so without a timeout it waits until the next renewal completes.
+/// Once the wait returns, the caller should call `step()` again.
+pub struct RenewalWaiter {
+    inner: Arc<NotifierInner>,
+    generation: u64,
+    // Identifies the waker of this waiter in the notifier.
+    id: u64,
+    timeout: Duration,
+    deadline: Instant,
+}
+
+impl RenewalWaiter {
+    /// The maximum time that the renewal is expected to take.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Block the current thread until the renewal completes, or the timeout expires.
+    pub fn wait(self) {
+        let state = self.inner.lock();
+        let _ = self
+            .inner
+            .condvar
+            .wait_timeout_while(
+                state,
+                self.deadline.saturating_duration_since(Instant::now()),
+                |state| state.generation == self.generation,
+            )
+            .unwrap_or_else(|poison| poison.into_inner());
+    }
+}
+
+impl Future for RenewalWaiter {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.inner.lock();
+        if state.generation != self.generation || Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        match state.wakers.iter_mut().find(|(id, _)| *id == self.id) {
+            Some((_, waker)) => {
+                if !waker.will_wake(cx.waker()) {
+                    *waker = cx.waker().clone();
+                }
+            }
+            None => state.wakers.push((self.id, cx.waker().clone())),
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for RenewalWaiter {
+    fn drop(&mut self) {
+        let mut state = self.inner.lock();
+        state.wakers.retain(|(id, _)| *id != self.id);
+    }
+}
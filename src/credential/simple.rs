@@ -1,9 +1,14 @@
 use std::borrow::Cow;
 use std::sync::Arc;
 
+use arc_swap::ArcSwap;
+
 use crate::AuthenticError;
 
-use super::{AuthenticationCredential, FetchedToken, FetchedUsernamePassword};
+use super::{
+    AuthenticationCredential, FetchedToken, FetchedUsernamePassword, Subscription,
+    UpdateSubscribers,
+};
 
 /// An implementation of [`FetchedToken`] returned from [`TokenCredential`].
 pub struct FetchedTokenCredential {
@@ -11,25 +16,57 @@ pub struct FetchedTokenCredential {
 }
 
 /// Credential wrapping a token to be used as an API key header or for Bearer authentication.
+///
+/// The token can be replaced while in use by calling [`TokenCredential::update`] or
+/// [`TokenCredential::rotate`].
 pub struct TokenCredential {
-    current: Arc<FetchedTokenCredential>,
+    current: ArcSwap<FetchedTokenCredential>,
+    subscribers: UpdateSubscribers<Arc<FetchedTokenCredential>>,
 }
 
 impl TokenCredential {
     pub fn new(token: impl Into<Cow<'static, [u8]>>) -> Self {
         Self {
-            current: Arc::new(FetchedTokenCredential {
+            current: ArcSwap::from_pointee(FetchedTokenCredential {
                 token: token.into(),
             }),
+            subscribers: UpdateSubscribers::new(),
         }
     }
+
+    /// Replace the token used by later calls to `fetch`.
+    ///
+    /// Operations that have already fetched the credential continue to use the previous token.
+    pub fn update(&self, token: impl Into<Cow<'static, [u8]>>) {
+        let fetched = Arc::new(FetchedTokenCredential {
+            token: token.into(),
+        });
+        self.current.store(fetched.clone());
+        self.subscribers.notify(&fetched);
+    }
+
+    /// Replace the token used by later calls to `fetch` with a rotated token.
+    ///
+    /// A token has no other values to keep, so this is the same as [`TokenCredential::update`].
+    pub fn rotate(&self, token: impl Into<Cow<'static, [u8]>>) {
+        self.update(token);
+    }
+
+    /// Call `callback` with the new token after each update, until the returned subscription is
+    /// dropped.
+    pub fn subscribe(
+        &self,
+        callback: impl Fn(&Arc<FetchedTokenCredential>) + Send + Sync + 'static,
+    ) -> Subscription {
+        self.subscribers.subscribe(callback)
+    }
 }
 
 impl AuthenticationCredential for TokenCredential {
     type Fetch = Arc<FetchedTokenCredential>;
 
     fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
-        Ok(self.current.clone())
+        Ok(self.current.load_full())
     }
 }
 
@@ -46,8 +83,12 @@ pub struct FetchedUsernamePasswordCredential {
 }
 
 /// Credential wrapping a username and password.
+///
+/// The credentials can be replaced while in use by calling [`UsernamePasswordCredential::update`]
+/// or [`UsernamePasswordCredential::rotate`].
 pub struct UsernamePasswordCredential {
-    current: Arc<FetchedUsernamePasswordCredential>,
+    current: ArcSwap<FetchedUsernamePasswordCredential>,
+    subscribers: UpdateSubscribers<Arc<FetchedUsernamePasswordCredential>>,
 }
 
 impl UsernamePasswordCredential {
@@ -56,19 +97,64 @@ impl UsernamePasswordCredential {
         password: impl Into<Cow<'static, str>>,
     ) -> Self {
         Self {
-            current: Arc::new(FetchedUsernamePasswordCredential {
+            current: ArcSwap::from_pointee(FetchedUsernamePasswordCredential {
                 username: username.into(),
                 password: password.into(),
             }),
+            subscribers: UpdateSubscribers::new(),
+        }
+    }
+
+    /// Replace the username and password used by later calls to `fetch`.
+    ///
+    /// Operations that have already fetched the credential continue to use the previous values.
+    pub fn update(
+        &self,
+        username: impl Into<Cow<'static, str>>,
+        password: impl Into<Cow<'static, str>>,
+    ) {
+        let fetched = Arc::new(FetchedUsernamePasswordCredential {
+            username: username.into(),
+            password: password.into(),
+        });
+        self.current.store(fetched.clone());
+        self.subscribers.notify(&fetched);
+    }
+
+    /// Replace the password used by later calls to `fetch`, keeping the current username.
+    pub fn rotate(&self, password: impl Into<Cow<'static, str>>) {
+        let password = password.into();
+        let mut fetched = None;
+        // The closure is called again if another update replaces the current value first, so
+        // the last value created is the one stored.
+        self.current.rcu(|current| {
+            let replacement = Arc::new(FetchedUsernamePasswordCredential {
+                username: current.username.clone(),
+                password: password.clone(),
+            });
+            fetched = Some(replacement.clone());
+            replacement
+        });
+        if let Some(fetched) = fetched {
+            self.subscribers.notify(&fetched);
         }
     }
+
+    /// Call `callback` with the new credentials after each update, until the returned
+    /// subscription is dropped.
+    pub fn subscribe(
+        &self,
+        callback: impl Fn(&Arc<FetchedUsernamePasswordCredential>) + Send + Sync + 'static,
+    ) -> Subscription {
+        self.subscribers.subscribe(callback)
+    }
 }
 
 impl AuthenticationCredential for UsernamePasswordCredential {
     type Fetch = Arc<FetchedUsernamePasswordCredential>;
 
     fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
-        Ok(self.current.clone())
+        Ok(self.current.load_full())
     }
 }
 
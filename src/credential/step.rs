@@ -1,5 +1,29 @@
+#[cfg(feature = "github")]
+mod github;
+#[cfg(feature = "jwt")]
+mod jwks;
 #[cfg(feature = "jwt")]
 mod jwt;
+#[cfg(feature = "jwt")]
+mod jwt_audience;
+#[cfg(feature = "jwt")]
+mod jwt_key;
+#[cfg(feature = "oauth2")]
+mod oauth2;
+#[cfg(any(feature = "jwt", feature = "oauth2"))]
+mod renew;
 
+#[cfg(feature = "github")]
+pub use github::*;
+#[cfg(feature = "jwt")]
+pub use jwks::*;
 #[cfg(feature = "jwt")]
 pub use jwt::*;
+#[cfg(feature = "jwt")]
+pub use jwt_audience::*;
+#[cfg(feature = "jwt")]
+pub use jwt_key::*;
+#[cfg(feature = "oauth2")]
+pub use oauth2::*;
+#[cfg(any(feature = "jwt", feature = "oauth2"))]
+pub(crate) use renew::*;
@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::RenewRequests;
+use crate::credential::{
+    AuthenticationCredential, Clock, FetchedToken, JsonWebTokenCredential, RenewableCredential,
+    RenewalPolicy, SystemClock,
+};
+use crate::{AuthenticError, AuthenticationStep};
+
+/// An implementation of [`FetchedToken`] returned from [`GitHubAppInstallationCredential`].
+pub struct FetchedGitHubAppInstallationCredential {
+    token: Vec<u8>,
+    renew: SystemTime,
+    expiry: SystemTime,
+}
+
+#[derive(serde::Deserialize)]
+struct AccessTokenResponse {
+    token: String,
+    expires_at: String,
+}
+
+/// Credential for a GitHub App installation.
+///
+/// Uses a JWT signed with the GitHub App private key to request an installation access token.
+/// The installation access token is renewed before it expires.
+///
+/// The JWT credential must be created with the App ID as the issuer, using
+/// [`JsonWebTokenCredential::with_issuer`], and use the `RS256` algorithm.
+pub struct GitHubAppInstallationCredential {
+    current: arc_swap::ArcSwapOption<FetchedGitHubAppInstallationCredential>,
+    renewing: RenewRequests,
+    app: Arc<JsonWebTokenCredential>,
+    installation_id: u64,
+    base_url: Cow<'static, str>,
+    renewal: RenewalPolicy,
+    clock: Arc<dyn Clock>,
+}
+
+impl GitHubAppInstallationCredential {
+    /// Create a credential for an installation of a GitHub App.
+    pub fn new(app: Arc<JsonWebTokenCredential>, installation_id: u64) -> Self {
+        Self {
+            current: arc_swap::ArcSwapOption::from(None),
+            renewing: RenewRequests::new(()),
+            app,
+            installation_id,
+            base_url: "https://api.github.com".into(),
+            renewal: RenewalPolicy::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Use a different clock to decide when to renew the token.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set when the installation access token is renewed.
+    ///
+    /// Installation access tokens are valid for one hour. By default, they are renewed after half
+    /// their lifetime.
+    #[must_use]
+    pub fn with_renewal_policy(mut self, renewal: RenewalPolicy) -> Self {
+        self.renewal = renewal;
+        self
+    }
+
+    /// Change the default `https://api.github.com` API URL.
+    ///
+    /// GitHub Enterprise Server uses an API URL such as `https://github.example.com/api/v3`.
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: impl Into<Cow<'static, str>>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    fn token_request(&self) -> Result<http::Request<Vec<u8>>, AuthenticError> {
+        let jwt = self.app.fetch()?;
+        let mut authorization = b"Bearer ".to_vec();
+        authorization.extend(jwt.token());
+        let mut header_value = http::HeaderValue::try_from(authorization)?;
+        header_value.set_sensitive(true);
+        Ok(http::Request::post(format!(
+            "{}/app/installations/{}/access_tokens",
+            self.base_url.trim_end_matches('/'),
+            self.installation_id
+        ))
+        .header(http::header::ACCEPT, "application/vnd.github+json")
+        .header(http::header::USER_AGENT, "authentic")
+        .header(http::header::AUTHORIZATION, header_value)
+        .body(Vec::new())?)
+    }
+
+    fn parse_response(
+        &self,
+        response: Result<http::Response<Vec<u8>>, AuthenticError>,
+    ) -> Result<FetchedGitHubAppInstallationCredential, AuthenticError> {
+        let response = response?;
+        if !response.status().is_success() {
+            return Err(AuthenticError::token_endpoint(
+                response.status(),
+                response.body(),
+            ));
+        }
+        let body: AccessTokenResponse = serde_json::from_slice(response.body())
+            .map_err(|err| AuthenticError::TokenResponse(err.to_string()))?;
+        let expiry = parse_rfc3339(&body.expires_at)?;
+        Ok(FetchedGitHubAppInstallationCredential {
+            token: body.token.into_bytes(),
+            renew: self.renewal.renew_time(self.clock.now(), expiry),
+            expiry,
+        })
+    }
+}
+
+impl AuthenticationCredential for GitHubAppInstallationCredential {
+    type Fetch = Arc<FetchedGitHubAppInstallationCredential>;
+
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        self.refresh_step(Duration::ZERO)
+    }
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        match self.parse_response(response) {
+            Ok(fetched) => {
+                let renew = fetched.renew;
+                self.renewing.succeeded(renew, |_| {
+                    self.current.store(Some(Arc::new(fetched)));
+                });
+            }
+            Err(err) => {
+                // Back off, keeping the current token while it is valid.
+                let expiry = self.current.load().as_ref().map(|current| current.expiry);
+                let retry = self.renewal.retry_time(self.clock.now(), expiry);
+                self.renewing.failed(retry, err, |_| {});
+            }
+        }
+    }
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        self.current.load_full().ok_or_else(|| {
+            AuthenticError::CredentialUnavailable("No token has been requested".to_owned())
+        })
+    }
+}
+
+impl RenewableCredential for GitHubAppInstallationCredential {
+    fn renew_in(&self) -> Option<Duration> {
+        self.current.load().as_ref().map(|current| {
+            current
+                .renew
+                .duration_since(self.clock.now())
+                .unwrap_or_default()
+        })
+    }
+
+    fn refresh_step(
+        &self,
+        lead: Duration,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        let now = self.clock.now();
+        // Time used to decide whether the token is due to be renewed.
+        let due = now + lead;
+        let current_is_valid = {
+            let guard = self.current.load();
+            if let Some(current) = &*guard {
+                if due < current.renew {
+                    // Current token is valid and too early to renew.
+                    return Ok(None);
+                } else {
+                    now < current.expiry
+                }
+            } else {
+                false
+            }
+        };
+        // The request requires a valid app JWT.
+        if let Some(step) = self.app.auth_step()? {
+            return Ok(Some(step));
+        }
+        // The renew time is checked again while holding the lock, in case another caller renewed
+        // the token after this caller loaded the current token.
+        self.renewing.step(
+            now,
+            current_is_valid,
+            |state| due >= state.renew,
+            |_| self.token_request(),
+        )
+    }
+}
+
+impl FetchedToken for Arc<FetchedGitHubAppInstallationCredential> {
+    fn token(&self) -> &[u8] {
+        &self.token
+    }
+}
+
+/// Parse a UTC timestamp in the format `2016-07-11T22:14:10Z`, as returned by GitHub.
+fn parse_rfc3339(timestamp: &str) -> Result<SystemTime, AuthenticError> {
+    let invalid = || AuthenticError::TokenResponse(format!("Invalid timestamp {:?}", timestamp));
+    let bytes = timestamp.as_bytes();
+    if bytes.len() != 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || bytes[10] != b'T'
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+        || bytes[19] != b'Z'
+    {
+        return Err(invalid());
+    }
+    // Each field is all ASCII digits, so it has no sign.
+    let field = |start: usize, end: usize| -> Result<i64, AuthenticError> {
+        let digits = &bytes[start..end];
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return Err(invalid());
+        }
+        Ok(digits
+            .iter()
+            .fold(0, |value, digit| value * 10 + i64::from(digit - b'0')))
+    };
+    let (year, month, day) = (field(0, 4)?, field(5, 7)?, field(8, 10)?);
+    let (hour, minute, second) = (field(11, 13)?, field(14, 16)?, field(17, 19)?);
+    // A second of 60 is a leap second.
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(invalid());
+    }
+    // Days since the Unix epoch, using the civil-from-days algorithm by Howard Hinnant.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    u64::try_from(seconds)
+        .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+        .map_err(|_| invalid())
+}
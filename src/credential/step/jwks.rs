@@ -0,0 +1,297 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use jsonwebtoken::jwk::{Jwk, PublicKeyUse};
+use jsonwebtoken::DecodingKey;
+
+use super::RenewRequests;
+use crate::credential::{AuthenticationCredential, Clock, RenewableCredential, SystemClock};
+use crate::{AuthenticError, AuthenticationStep};
+
+/// Keys returned from [`JwksCache`].
+pub struct FetchedJwks {
+    keys: HashMap<String, DecodingKey>,
+    // Keys with no `kid`, used only for tokens with no `kid`.
+    anonymous: Vec<DecodingKey>,
+}
+
+impl FetchedJwks {
+    /// Get the key for a token with the key ID `kid`.
+    ///
+    /// A token with no key ID can only use a key set containing a single key with no key ID.
+    pub fn key(&self, kid: Option<&str>) -> Option<&DecodingKey> {
+        match kid {
+            Some(kid) => self.keys.get(kid),
+            None if self.anonymous.len() == 1 => self.anonymous.first(),
+            None => None,
+        }
+    }
+
+    /// The number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.keys.len() + self.anonymous.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct JwksDocument {
+    keys: Vec<serde_json::Value>,
+}
+
+struct FetchState {
+    // Time the current key set was fetched.
+    fetched: SystemTime,
+    // Whether a token used a key not in the current key set.
+    unknown_key: bool,
+}
+
+/// Cache of the JSON Web Key Set (JWKS) published by a token issuer, for validating tokens.
+///
+/// The key set is fetched using `auth_step` and `auth_respond`, or using
+/// [`JwksCache::refresh_with`]. It is fetched again when the `max-age` of its `Cache-Control`
+/// header has passed, or when a token uses a key ID that is not in the set. A `max-age` longer
+/// than a day is treated as a day.
+///
+/// To prevent tokens with unknown key IDs causing excessive requests, the key set is not fetched
+/// again within the minimum refresh interval, which defaults to 1 minute.
+///
+/// The cache is a [`RenewableCredential`], so a `CredentialRefresher` can keep the key set fresh
+/// in the background.
+pub struct JwksCache {
+    current: arc_swap::ArcSwapOption<FetchedJwks>,
+    // The renew time is the time after which the key set is fetched again.
+    requests: RenewRequests<FetchState>,
+    url: Cow<'static, str>,
+    default_max_age: Duration,
+    min_refresh: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl JwksCache {
+    /// Create a cache for the key set at `url`.
+    pub fn new(url: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            current: arc_swap::ArcSwapOption::from(None),
+            requests: RenewRequests::new(FetchState {
+                fetched: SystemTime::UNIX_EPOCH,
+                unknown_key: false,
+            }),
+            url: url.into(),
+            default_max_age: Duration::from_secs(60 * 60),
+            min_refresh: Duration::from_secs(60),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Use a different clock to decide when to fetch the key set.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set how long to keep a key set returned without a `Cache-Control` `max-age`.
+    ///
+    /// The default is 1 hour.
+    #[must_use]
+    pub fn with_default_max_age(mut self, max_age: Duration) -> Self {
+        self.default_max_age = max_age;
+        self
+    }
+
+    /// Set the minimum time between fetches of the key set.
+    #[must_use]
+    pub fn with_min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh = interval;
+        self
+    }
+
+    /// Record that a token used a key ID that is not in the current key set.
+    ///
+    /// The key set is fetched again by the next `auth_step` after the minimum refresh interval.
+    pub fn unknown_key(&self) {
+        if let Ok(mut state) = self.requests.lock() {
+            state.extra.unknown_key = true;
+        }
+    }
+
+    /// Fetch the key set if required, sending requests using `send`.
+    ///
+    /// This allows the key set to be fetched with any asynchronous HTTP client.
+    pub async fn refresh_with<SendRequest, ResponseFuture>(
+        &self,
+        send: SendRequest,
+    ) -> Result<(), AuthenticError>
+    where
+        SendRequest: Fn(http::Request<Vec<u8>>) -> ResponseFuture,
+        ResponseFuture: Future<Output = Result<http::Response<Vec<u8>>, AuthenticError>>,
+    {
+        while let Some(step) = self.auth_step()? {
+            match step {
+                AuthenticationStep::Request(request) => {
+                    let response = send(request).await;
+                    self.auth_respond(response);
+                }
+                AuthenticationStep::WaitForRenewal(waiter) => waiter.await,
+                // The cache does not ask callers to wait for a time.
+                AuthenticationStep::WaitFor(_) => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_response(
+        &self,
+        response: Result<http::Response<Vec<u8>>, AuthenticError>,
+    ) -> Result<(FetchedJwks, Duration), AuthenticError> {
+        let response = response?;
+        if !response.status().is_success() {
+            return Err(AuthenticError::token_endpoint(
+                response.status(),
+                response.body(),
+            ));
+        }
+        let document: JwksDocument = serde_json::from_slice(response.body())
+            .map_err(|err| AuthenticError::TokenResponse(err.to_string()))?;
+        let mut fetched = FetchedJwks {
+            keys: HashMap::new(),
+            anonymous: Vec::new(),
+        };
+        for key in document.keys {
+            // Keys of unsupported types, or for encryption, are ignored.
+            let jwk: Jwk = match serde_json::from_value(key) {
+                Ok(jwk) => jwk,
+                Err(_) => continue,
+            };
+            if matches!(jwk.common.public_key_use, Some(PublicKeyUse::Encryption)) {
+                continue;
+            }
+            let decoding_key = match DecodingKey::from_jwk(&jwk) {
+                Ok(decoding_key) => decoding_key,
+                Err(_) => continue,
+            };
+            match jwk.common.key_id {
+                Some(kid) => {
+                    fetched.keys.insert(kid, decoding_key);
+                }
+                None => fetched.anonymous.push(decoding_key),
+            }
+        }
+        let max_age = max_age(response.headers()).unwrap_or(self.default_max_age);
+        Ok((fetched, max_age))
+    }
+}
+
+/// Longest time to keep a key set, whatever `max-age` the server returns.
+const MAX_AGE_LIMIT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Get the `max-age` from a `Cache-Control` header, limited to [`MAX_AGE_LIMIT`]. Responses that
+/// must not be cached have a `max-age` of zero.
+fn max_age(headers: &http::HeaderMap) -> Option<Duration> {
+    let mut max_age = None;
+    for value in headers.get_all(http::header::CACHE_CONTROL) {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        for directive in value.split(',') {
+            let directive = directive.trim();
+            if directive.eq_ignore_ascii_case("no-cache")
+                || directive.eq_ignore_ascii_case("no-store")
+            {
+                return Some(Duration::ZERO);
+            }
+            let mut parts = directive.splitn(2, '=');
+            if let (Some(name), Some(seconds)) = (parts.next(), parts.next()) {
+                if name.trim().eq_ignore_ascii_case("max-age") {
+                    if let Ok(seconds) = seconds.trim().trim_matches('"').parse() {
+                        max_age = Some(Duration::from_secs(seconds).min(MAX_AGE_LIMIT));
+                    }
+                }
+            }
+        }
+    }
+    max_age
+}
+
+impl AuthenticationCredential for JwksCache {
+    type Fetch = Arc<FetchedJwks>;
+
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        self.refresh_step(Duration::ZERO)
+    }
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        let now = self.clock.now();
+        match self.parse_response(response) {
+            Ok((fetched, max_age)) => {
+                // Keep the key set for at least the minimum refresh interval.
+                let refresh = now
+                    .checked_add(max_age.max(self.min_refresh))
+                    .or_else(|| now.checked_add(self.default_max_age))
+                    .unwrap_or(now);
+                self.requests.succeeded(refresh, |state| {
+                    state.fetched = now;
+                    self.current.store(Some(Arc::new(fetched)));
+                });
+            }
+            Err(err) => {
+                // Retry after the minimum refresh interval.
+                let retry = now.checked_add(self.min_refresh).unwrap_or(now);
+                self.requests.failed(retry, err, |state| {
+                    state.fetched = now;
+                });
+            }
+        }
+    }
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        self.current.load_full().ok_or_else(|| {
+            AuthenticError::CredentialUnavailable("No key set has been fetched".to_owned())
+        })
+    }
+}
+
+impl RenewableCredential for JwksCache {
+    fn renew_in(&self) -> Option<Duration> {
+        self.current.load().as_ref()?;
+        let refresh = self.requests.lock().ok()?.renew;
+        Some(refresh.duration_since(self.clock.now()).unwrap_or_default())
+    }
+
+    fn refresh_step(
+        &self,
+        lead: Duration,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        let now = self.clock.now();
+        // The key set does not expire, so callers continue to use it after a failed request.
+        let has_current = self.current.load().is_some();
+        self.requests.step(
+            now,
+            has_current,
+            |state| {
+                let allowed = state.extra.fetched.checked_add(self.min_refresh);
+                !matches!(now.checked_add(lead), Some(due) if due < state.renew)
+                    || (state.extra.unknown_key
+                        && matches!(allowed, Some(allowed) if now >= allowed))
+            },
+            |state| {
+                let request = http::Request::get(self.url.as_ref())
+                    .header(http::header::ACCEPT, "application/json")
+                    .header(http::header::USER_AGENT, "authentic")
+                    .body(Vec::new())?;
+                state.unknown_key = false;
+                Ok(request)
+            },
+        )
+    }
+}
@@ -1,9 +1,19 @@
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use crate::credential::{AuthenticationCredential, FetchedToken};
-use crate::AuthenticError;
+use base64::Engine;
+
+use super::JwtPrivateKey;
+use crate::credential::{
+    AuthenticationCredential, Clock, FetchedToken, RenewableCredential, RenewalNotifier,
+    RenewalPolicy, SystemClock,
+};
+use crate::{AuthenticError, AuthenticationStep};
+
+/// Maximum time to wait for another caller to create a token.
+const RENEWAL_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// An implementation of [`FetchedToken`] returned from [`JsonWebTokenCredential`].
 pub struct FetchedJsonWebTokenCredential {
@@ -22,10 +32,18 @@ pub struct JsonWebTokenCredential {
     // Mutex to be held while renewing. Contains a copy of the renew time
     // to prevent race conditions.
     renewing: std::sync::Mutex<std::time::SystemTime>,
-    header: jsonwebtoken::Header,
-    key: jsonwebtoken::EncodingKey,
+    // Wakes callers waiting for the lock holder to renew the token.
+    renewed: RenewalNotifier,
+    signer: Signer,
     expiration: Duration,
     jwt_iss: Option<Cow<'static, str>>,
+    jwt_sub: Option<Cow<'static, str>>,
+    jwt_aud: Option<JWTAudience>,
+    not_before: Option<Duration>,
+    jwt_id: bool,
+    extra_claims: serde_json::Map<String, serde_json::Value>,
+    renewal: RenewalPolicy,
+    clock: Arc<dyn Clock>,
 }
 
 impl JsonWebTokenCredential {
@@ -34,112 +52,723 @@ impl JsonWebTokenCredential {
     /// The `header` and `key` parameters are set as for [`jsonwebtoken`].
     ///
     /// The `expiration` parameter controls how long the token will be valid. Endpoints may restrict
-    /// tokens to a maximum lifetime. Tokens are rotated after half the expiration time, to ensure
-    /// that they have a reasonable remaining time to be used.
+    /// tokens to a maximum lifetime. By default, tokens are rotated after half the expiration time,
+    /// to ensure that they have a reasonable remaining time to be used. Use
+    /// [`JsonWebTokenCredential::with_renewal_policy`] to change this.
     pub fn new(
         header: jsonwebtoken::Header,
         key: jsonwebtoken::EncodingKey,
         expiration: Duration,
     ) -> Self {
+        Self::with_signer(
+            Signer::Key(Arc::new(SigningKey {
+                header,
+                signer: KeySigner::Key(key),
+            })),
+            expiration,
+        )
+    }
+
+    /// Create a JWT credential signing tokens with `key`.
+    ///
+    /// The `alg` and `kid` headers are set from the key.
+    pub fn from_private_key(key: JwtPrivateKey, expiration: Duration) -> Self {
+        let (header, key) = key.into_header_and_key();
+        Self::new(header, key, expiration)
+    }
+
+    /// Create a JWT credential signing tokens with the private key in the file at `path`.
+    ///
+    /// See [`JwtPrivateKey`] for the supported formats.
+    pub fn from_key_file(
+        path: impl AsRef<std::path::Path>,
+        expiration: Duration,
+    ) -> Result<Self, AuthenticError> {
+        Ok(Self::from_private_key(
+            JwtPrivateKey::from_file(path)?,
+            expiration,
+        ))
+    }
+
+    /// Create a JWT credential signing tokens with the private key in `bytes`.
+    ///
+    /// See [`JwtPrivateKey`] for the supported formats.
+    pub fn from_key_bytes(bytes: &[u8], expiration: Duration) -> Result<Self, AuthenticError> {
+        Ok(Self::from_private_key(
+            JwtPrivateKey::from_bytes(bytes)?,
+            expiration,
+        ))
+    }
+
+    /// Create a JWT credential signing tokens using `signer`.
+    ///
+    /// The `header` is set as for [`jsonwebtoken`], except that its algorithm is set by the
+    /// signer.
+    pub fn from_signer(
+        header: jsonwebtoken::Header,
+        signer: Arc<dyn JwtSigner>,
+        expiration: Duration,
+    ) -> Self {
+        Self::with_signer(
+            Signer::Key(Arc::new(SigningKey {
+                header,
+                signer: KeySigner::External(signer),
+            })),
+            expiration,
+        )
+    }
+
+    /// Create a JWT credential signing tokens with the current key of `keys`.
+    ///
+    /// Each token is signed by the key that is current when the token is created, with the `kid`
+    /// header set to its key ID. Rotating the key set takes effect when the token is next renewed.
+    pub fn from_key_set(keys: Arc<JwtKeySet>, expiration: Duration) -> Self {
+        Self::with_signer(Signer::KeySet(keys), expiration)
+    }
+
+    fn with_signer(signer: Signer, expiration: Duration) -> Self {
         Self {
             current: arc_swap::ArcSwapOption::from(None),
             renewing: std::sync::Mutex::new(std::time::SystemTime::UNIX_EPOCH),
-            header,
-            key,
+            renewed: RenewalNotifier::new(),
+            signer,
             expiration,
             jwt_iss: None,
+            jwt_sub: None,
+            jwt_aud: None,
+            not_before: None,
+            jwt_id: false,
+            extra_claims: serde_json::Map::new(),
+            renewal: RenewalPolicy::default(),
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Use a different clock to set the token times and to decide when to renew the token.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set when tokens are rotated.
+    #[must_use]
+    pub fn with_renewal_policy(mut self, renewal: RenewalPolicy) -> Self {
+        self.renewal = renewal;
+        self
+    }
+
     #[must_use]
     pub fn with_issuer(mut self, issuer: impl Into<Cow<'static, str>>) -> Self {
         self.jwt_iss = Some(issuer.into());
         self
     }
+
+    /// Set the `sub` (subject) claim.
+    #[must_use]
+    pub fn with_subject(mut self, subject: impl Into<Cow<'static, str>>) -> Self {
+        self.jwt_sub = Some(subject.into());
+        self
+    }
+
+    /// Set the `aud` (audience) claim to a single string.
+    #[must_use]
+    pub fn with_audience(mut self, audience: impl Into<Cow<'static, str>>) -> Self {
+        self.jwt_aud = Some(JWTAudience::Single(audience.into()));
+        self
+    }
+
+    /// Set the `aud` (audience) claim to an array of strings.
+    #[must_use]
+    pub fn with_audiences<Audience>(mut self, audiences: impl IntoIterator<Item = Audience>) -> Self
+    where
+        Audience: Into<Cow<'static, str>>,
+    {
+        self.jwt_aud = Some(JWTAudience::Multiple(
+            audiences.into_iter().map(Into::into).collect(),
+        ));
+        self
+    }
+
+    /// Set the `nbf` (not before) claim.
+    ///
+    /// The token is valid from `leeway` before the time it was issued. A non-zero leeway allows
+    /// for clock differences between the client and the endpoint.
+    #[must_use]
+    pub fn with_not_before(mut self, leeway: Duration) -> Self {
+        self.not_before = Some(leeway);
+        self
+    }
+
+    /// Set the `jti` (JWT ID) claim to a new random value for each token.
+    #[must_use]
+    pub fn with_jwt_id(mut self) -> Self {
+        self.jwt_id = true;
+        self
+    }
+
+    /// Add a custom claim.
+    ///
+    /// Returns an error if the name is one of the registered claims set by this credential.
+    pub fn with_claim(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+    ) -> Result<Self, AuthenticError> {
+        let name = name.into();
+        if REGISTERED_CLAIMS.contains(&name.as_str()) {
+            return Err(AuthenticError::Configuration(format!(
+                "Claim {:?} is set by the credential",
+                name
+            )));
+        }
+        self.extra_claims.insert(name, value.into());
+        Ok(self)
+    }
+
+    /// Add custom claims from a type that serializes to a JSON object.
+    ///
+    /// Returns an error if the value does not serialize to an object, or contains one of the
+    /// registered claims set by this credential.
+    pub fn with_claims(self, claims: impl serde::Serialize) -> Result<Self, AuthenticError> {
+        match serde_json::to_value(claims) {
+            Ok(serde_json::Value::Object(claims)) => claims
+                .into_iter()
+                .try_fold(self, |credential, (name, value)| {
+                    credential.with_claim(name, value)
+                }),
+            Ok(_) => Err(AuthenticError::Configuration(
+                "Claims must serialize to a JSON object".to_owned(),
+            )),
+            Err(err) => Err(AuthenticError::Configuration(err.to_string())),
+        }
+    }
+}
+
+/// Signs JSON Web Tokens for [`JsonWebTokenCredential`].
+///
+/// Implement this trait to sign tokens with a key held outside the process, such as in a
+/// hardware security module, a cloud key management service or an agent.
+///
+/// Signing is synchronous. The signer is called from `auth_step` by the one caller that renews
+/// the token, while other callers continue to use the current token until it expires, or receive
+/// [`AuthenticationStep::WaitForRenewal`] once it has expired. A signer that blocks, such as a
+/// PKCS#11 call, therefore only delays the renewing caller. In async code, avoid blocking the
+/// executor by renewing the token in the background with `CredentialRefresher::spawn_thread`
+/// (requires the `refresh` feature), so that requests find a current token and do not call the
+/// signer.
+///
+/// Return [`AuthenticError::CredentialUnavailable`] if signing may succeed later, for example if
+/// the signing service cannot be reached, [`AuthenticError::Configuration`] if it will not, and
+/// [`AuthenticError::Signing`] for other signing failures. If signing fails while the current
+/// token is valid, the credential continues to use it, and signs again after the retry interval
+/// of its [`RenewalPolicy`].
+pub trait JwtSigner: Send + Sync {
+    /// The algorithm of the signatures, which is set as the `alg` header.
+    fn algorithm(&self) -> jsonwebtoken::Algorithm;
+
+    /// Sign `message`, returning the signature bytes.
+    ///
+    /// ECDSA signatures are the concatenated `r` and `s` values, as required by RFC 7518, and
+    /// not DER-encoded.
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, AuthenticError>;
+}
+
+/// [`JwtSigner`] using a key held in memory.
+pub struct EncodingKeySigner {
+    algorithm: jsonwebtoken::Algorithm,
+    key: jsonwebtoken::EncodingKey,
+}
+
+impl EncodingKeySigner {
+    pub fn new(algorithm: jsonwebtoken::Algorithm, key: jsonwebtoken::EncodingKey) -> Self {
+        Self { algorithm, key }
+    }
+}
+
+impl JwtSigner for EncodingKeySigner {
+    fn algorithm(&self) -> jsonwebtoken::Algorithm {
+        self.algorithm
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, AuthenticError> {
+        let signature = jsonwebtoken::crypto::sign(message, &self.key, self.algorithm)?;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|err| AuthenticError::Signing(err.to_string()))
+    }
+}
+
+enum KeySigner {
+    Key(jsonwebtoken::EncodingKey),
+    External(Arc<dyn JwtSigner>),
+}
+
+struct SigningKey {
+    header: jsonwebtoken::Header,
+    signer: KeySigner,
+}
+
+impl SigningKey {
+    fn encode(&self, claims: &JWTClaims) -> Result<String, AuthenticError> {
+        match &self.signer {
+            KeySigner::Key(key) => Ok(jsonwebtoken::encode(&self.header, claims, key)?),
+            KeySigner::External(signer) => {
+                let engine = &base64::engine::general_purpose::URL_SAFE_NO_PAD;
+                let mut header = self.header.clone();
+                header.alg = signer.algorithm();
+                let header = serde_json::to_vec(&header)
+                    .map_err(|err| AuthenticError::Signing(err.to_string()))?;
+                let claims = serde_json::to_vec(claims)
+                    .map_err(|err| AuthenticError::Signing(err.to_string()))?;
+                let message = format!("{}.{}", engine.encode(header), engine.encode(claims));
+                let signature = signer.sign(message.as_bytes())?;
+                Ok(format!("{}.{}", message, engine.encode(signature)))
+            }
+        }
+    }
+}
+
+enum Signer {
+    Key(Arc<SigningKey>),
+    KeySet(Arc<JwtKeySet>),
+}
+
+struct KeySetState {
+    keys: HashMap<String, Arc<SigningKey>>,
+    current: String,
+    // Rotations that have not yet taken effect, in order of time.
+    scheduled: Vec<(std::time::SystemTime, String)>,
+}
+
+impl KeySetState {
+    fn check_key(&self, kid: &str) -> Result<(), AuthenticError> {
+        if self.keys.contains_key(kid) {
+            Ok(())
+        } else {
+            Err(AuthenticError::Configuration(format!(
+                "Unknown key ID {:?}",
+                kid
+            )))
+        }
+    }
+
+    /// Make any scheduled rotations due at `now` take effect.
+    fn apply_scheduled(&mut self, now: std::time::SystemTime) {
+        let due = self
+            .scheduled
+            .iter()
+            .take_while(|(at, _)| *at <= now)
+            .count();
+        if let Some((_, kid)) = self.scheduled.drain(..due).next_back() {
+            self.current = kid;
+        }
+    }
+}
+
+/// Set of signing keys for [`JsonWebTokenCredential`], identified by key ID.
+///
+/// One key is current and signs new tokens. Other keys can be added in advance and the set
+/// rotated to them, either immediately using [`JwtKeySet::rotate_to`] or at a later time using
+/// [`JwtKeySet::schedule_rotation`]. A credential created using
+/// [`JsonWebTokenCredential::from_key_set`] uses the new key from its next renewal, so the key
+/// set can be shared and rotated without rebuilding the credential.
+///
+/// For rotation without downtime, publish the verification key for a new key ID before rotating
+/// to it, and remove the old key only after tokens signed by it have expired.
+pub struct JwtKeySet {
+    state: Mutex<KeySetState>,
 }
 
+impl JwtKeySet {
+    /// Create a key set with a single key, which is the current signing key.
+    ///
+    /// The `header` and `key` parameters are set as for [`jsonwebtoken`]. The `kid` header is set
+    /// to `kid`.
+    pub fn new(
+        kid: impl Into<String>,
+        mut header: jsonwebtoken::Header,
+        key: jsonwebtoken::EncodingKey,
+    ) -> Self {
+        let kid = kid.into();
+        header.kid = Some(kid.clone());
+        let mut keys = HashMap::new();
+        keys.insert(
+            kid.clone(),
+            Arc::new(SigningKey {
+                header,
+                signer: KeySigner::Key(key),
+            }),
+        );
+        Self {
+            state: Mutex::new(KeySetState {
+                keys,
+                current: kid,
+                scheduled: Vec::new(),
+            }),
+        }
+    }
+
+    /// Add a key that can later become the current signing key.
+    ///
+    /// Returns an error if the key ID is already in the set.
+    pub fn add_key(
+        &self,
+        kid: impl Into<String>,
+        header: jsonwebtoken::Header,
+        key: jsonwebtoken::EncodingKey,
+    ) -> Result<(), AuthenticError> {
+        self.insert(kid.into(), header, KeySigner::Key(key))
+    }
+
+    /// Add a key held by `signer` that can later become the current signing key.
+    ///
+    /// Returns an error if the key ID is already in the set.
+    pub fn add_signer(
+        &self,
+        kid: impl Into<String>,
+        header: jsonwebtoken::Header,
+        signer: Arc<dyn JwtSigner>,
+    ) -> Result<(), AuthenticError> {
+        self.insert(kid.into(), header, KeySigner::External(signer))
+    }
+
+    fn insert(
+        &self,
+        kid: String,
+        mut header: jsonwebtoken::Header,
+        signer: KeySigner,
+    ) -> Result<(), AuthenticError> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        if state.keys.contains_key(&kid) {
+            return Err(AuthenticError::Configuration(format!(
+                "Key ID {:?} is already in the key set",
+                kid
+            )));
+        }
+        header.kid = Some(kid.clone());
+        state
+            .keys
+            .insert(kid, Arc::new(SigningKey { header, signer }));
+        Ok(())
+    }
+
+    /// Remove a key that is no longer used.
+    ///
+    /// Returns an error if the key is unknown, current, or has a scheduled rotation.
+    pub fn remove_key(&self, kid: &str) -> Result<(), AuthenticError> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        state.check_key(kid)?;
+        if state.current == kid || state.scheduled.iter().any(|(_, next)| next == kid) {
+            return Err(AuthenticError::Configuration(format!(
+                "Key ID {:?} is in use",
+                kid
+            )));
+        }
+        state.keys.remove(kid);
+        Ok(())
+    }
+
+    /// Make `kid` the current signing key.
+    ///
+    /// Credentials use the key for tokens created after this call. Rotations scheduled for a
+    /// later time still take effect.
+    pub fn rotate_to(&self, kid: &str) -> Result<(), AuthenticError> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        state.check_key(kid)?;
+        state.current = kid.to_owned();
+        Ok(())
+    }
+
+    /// Make `kid` the current signing key at time `at`.
+    ///
+    /// The time is compared with the clock of the credential creating the token.
+    pub fn schedule_rotation(
+        &self,
+        kid: &str,
+        at: std::time::SystemTime,
+    ) -> Result<(), AuthenticError> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        state.check_key(kid)?;
+        let index = state
+            .scheduled
+            .iter()
+            .take_while(|(next, _)| *next <= at)
+            .count();
+        state.scheduled.insert(index, (at, kid.to_owned()));
+        Ok(())
+    }
+
+    /// The key ID of the key signing tokens at time `now`.
+    pub fn current_key_id(&self, now: std::time::SystemTime) -> Result<String, AuthenticError> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        state.apply_scheduled(now);
+        Ok(state.current.clone())
+    }
+
+    fn signing_key(&self, now: std::time::SystemTime) -> Result<Arc<SigningKey>, AuthenticError> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        state.apply_scheduled(now);
+        Ok(state.keys[&state.current].clone())
+    }
+}
+
+/// Claims set by [`JsonWebTokenCredential`], which cannot be added as custom claims.
+const REGISTERED_CLAIMS: &[&str] = &["iat", "exp", "nbf", "iss", "sub", "aud", "jti"];
+
 #[derive(Debug, serde::Serialize)]
-struct JWTClaims {
+#[serde(untagged)]
+enum JWTAudience {
+    Single(Cow<'static, str>),
+    Multiple(Vec<Cow<'static, str>>),
+}
+
+#[derive(Debug, serde::Serialize)]
+struct JWTClaims<'a> {
     iat: usize,
     exp: usize,
     #[serde(skip_serializing_if = "Option::is_none", default)]
-    iss: Option<Cow<'static, str>>,
+    nbf: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    iss: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    sub: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    aud: Option<&'a JWTAudience>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    jti: Option<String>,
+    #[serde(flatten)]
+    extra: &'a serde_json::Map<String, serde_json::Value>,
+}
+
+/// Create a random JWT ID.
+fn jwt_id() -> String {
+    let bytes: [u8; 16] = rand::random();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl JsonWebTokenCredential {
+    pub(crate) fn create_token(
+        &self,
+        now: std::time::SystemTime,
+    ) -> Result<FetchedJsonWebTokenCredential, AuthenticError> {
+        let exp = now.checked_add(self.expiration).ok_or_else(|| {
+            AuthenticError::Configuration(format!(
+                "Token expiration too large: {:?}",
+                self.expiration
+            ))
+        })?;
+        let nbf = match self.not_before {
+            Some(leeway) => Some(
+                now.checked_sub(leeway)
+                    .ok_or_else(|| {
+                        AuthenticError::Configuration(format!(
+                            "Not before leeway too large: {:?}",
+                            leeway
+                        ))
+                    })?
+                    .duration_since(std::time::SystemTime::UNIX_EPOCH)?
+                    .as_secs() as usize,
+            ),
+            None => None,
+        };
+        let claims = JWTClaims {
+            iat: now
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)?
+                .as_secs() as usize,
+            exp: exp
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)?
+                .as_secs() as usize,
+            nbf,
+            iss: self.jwt_iss.as_deref(),
+            sub: self.jwt_sub.as_deref(),
+            aud: self.jwt_aud.as_ref(),
+            jti: if self.jwt_id { Some(jwt_id()) } else { None },
+            extra: &self.extra_claims,
+        };
+        let token = match &self.signer {
+            Signer::Key(signing_key) => signing_key.encode(&claims)?,
+            Signer::KeySet(keys) => keys.signing_key(now)?.encode(&claims)?,
+        };
+        Ok(FetchedJsonWebTokenCredential {
+            token: token.into_bytes(),
+            renew: self.renewal.renew_time(now, exp),
+            expiry: exp,
+        })
+    }
+}
+
+impl JsonWebTokenCredential {
+    /// Fetch the current token, first creating a new token if it is due to be renewed.
+    ///
+    /// Unlike `auth_step`, this blocks while another caller renews the token, for callers that
+    /// cannot wait asynchronously. Creating a token does not make requests, so the wait is short.
+    pub(crate) fn fetch_renewed(
+        &self,
+    ) -> Result<Arc<FetchedJsonWebTokenCredential>, AuthenticError> {
+        let now = self.clock.now();
+        if let Some(current) = self.current.load_full() {
+            if now < current.renew {
+                return Ok(current);
+            }
+        }
+        let mut renew_time = self
+            .renewing
+            .lock()
+            .map_err(|_| AuthenticError::LockPoisoned)?;
+        let current = self.current.load_full();
+        if now < *renew_time {
+            // Another caller renewed the token while this caller waited for the lock.
+            if let Some(current) = current {
+                return Ok(current);
+            }
+        }
+        let result = match self.create_token(now) {
+            Ok(fetched) => {
+                let fetched = Arc::new(fetched);
+                self.current.store(Some(fetched.clone()));
+                *renew_time = fetched.renew;
+                Ok(fetched)
+            }
+            Err(err) => match current {
+                // Continue to use the current token until it expires.
+                Some(current) if now < current.expiry => {
+                    *renew_time = self.renewal.retry_time(now, Some(current.expiry));
+                    Ok(current)
+                }
+                _ => Err(err),
+            },
+        };
+        drop(renew_time);
+        self.renewed.notify();
+        result
+    }
 }
 
 #[cfg(feature = "jwt")]
 impl AuthenticationCredential for JsonWebTokenCredential {
-    fn auth_step(&self) -> Result<Duration, AuthenticError> {
-        let now = std::time::SystemTime::now();
-        let current_is_valid = {
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        self.refresh_step(Duration::ZERO)
+    }
+
+    type Fetch = Arc<FetchedJsonWebTokenCredential>;
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        self.current.load_full().ok_or_else(|| {
+            AuthenticError::CredentialUnavailable("No token has been created".to_owned())
+        })
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl RenewableCredential for JsonWebTokenCredential {
+    fn renew_in(&self) -> Option<Duration> {
+        self.current.load().as_ref().map(|current| {
+            current
+                .renew
+                .duration_since(self.clock.now())
+                .unwrap_or_default()
+        })
+    }
+
+    fn refresh_step(
+        &self,
+        lead: Duration,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        let now = self.clock.now();
+        // Time used to decide whether the token is due to be renewed.
+        let due = now + lead;
+        // Expiry of the current token, if it is still valid.
+        let valid_until = {
             let guard = self.current.load();
             if let Some(current) = &*guard {
-                if now < current.renew {
+                if due < current.renew {
                     // Current token is valid and too early to renew.
-                    return Ok(Duration::ZERO);
-                } else {
-                    now < current.expiry
+                    return Ok(None);
                 }
+                Some(current.expiry).filter(|expiry| now < *expiry)
             } else {
-                false
+                None
             }
         };
+        let current_is_valid = valid_until.is_some();
+        // Create the waiter before trying the lock, so that a renewal completing in between is
+        // not missed.
+        let waiter = if current_is_valid {
+            None
+        } else {
+            Some(self.renewed.waiter(RENEWAL_TIMEOUT))
+        };
         match self.renewing.try_lock() {
             // First caller after renewal time locks the mutex and refreshes the token.
             // Other callers fail to lock and continue with the current token if it is still valid,
-            // or wait a short time and retry until the lock holder has renewed the token.
+            // or wait until the lock holder has renewed the token.
             Ok(mut renew_time) => {
-                if now < *renew_time {
+                if due < *renew_time {
                     // Caller saw an old token while a previous thread was renewing the token, and
                     // acquired the mutex after the previous thread released it. Prevent the caller
                     // from needlessly renewing the token by checking the renew time again.
-                    return Ok(Duration::ZERO);
+                    return Ok(None);
                 }
-                let exp = now + self.expiration;
-                let claims = JWTClaims {
-                    iat: now
-                        .duration_since(std::time::SystemTime::UNIX_EPOCH)?
-                        .as_secs() as usize,
-                    exp: exp
-                        .duration_since(std::time::SystemTime::UNIX_EPOCH)?
-                        .as_secs() as usize,
-                    iss: self.jwt_iss.clone(),
+                let result = match self.create_token(now) {
+                    Ok(fetched) => {
+                        *renew_time = fetched.renew;
+                        self.current.store(Some(Arc::new(fetched)));
+                        Ok(None)
+                    }
+                    Err(_) if current_is_valid => {
+                        // Continue to use the current token, and try again after the retry
+                        // interval.
+                        *renew_time = self.renewal.retry_time(now, valid_until);
+                        Ok(None)
+                    }
+                    Err(err) => Err(err),
                 };
-                let token = jsonwebtoken::encode(&self.header, &claims, &self.key)?;
-                let renew = now + self.expiration / 2;
-                let fetched = FetchedJsonWebTokenCredential {
-                    token: token.into_bytes(),
-                    renew,
-                    expiry: exp,
-                };
-                self.current.store(Some(Arc::new(fetched)));
-                *renew_time = renew;
-                Ok(Duration::ZERO)
-            }
-            Err(std::sync::TryLockError::WouldBlock) => {
-                if current_is_valid {
-                    // Current token is still valid.
-                    Ok(Duration::ZERO)
-                } else {
-                    // Current token has expired. Wait for lock holder to refresh token
-                    Ok(Duration::from_millis(10))
-                }
-            }
-            Err(std::sync::TryLockError::Poisoned(poison)) => {
-                Err(AuthenticError::Other(poison.to_string()))
+                drop(renew_time);
+                // Wake waiting callers after releasing the lock, so that they can retry if
+                // renewal failed.
+                self.renewed.notify();
+                result
             }
+            Err(std::sync::TryLockError::WouldBlock) => match waiter {
+                // Current token is still valid.
+                None => Ok(None),
+                // Current token has expired. Wait for lock holder to refresh token.
+                Some(waiter) => Ok(Some(AuthenticationStep::WaitForRenewal(waiter))),
+            },
+            Err(std::sync::TryLockError::Poisoned(_)) => Err(AuthenticError::LockPoisoned),
         }
     }
+}
 
-    type Fetch = Arc<FetchedJsonWebTokenCredential>;
-
-    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
-        self.current
-            .load_full()
-            .ok_or_else(|| AuthenticError::Other("Unexpected None".to_owned()))
+#[cfg(feature = "oauth2")]
+impl FetchedJsonWebTokenCredential {
+    /// Take the token, for a caller that uses it once.
+    pub(crate) fn into_token(self) -> Vec<u8> {
+        self.token
     }
 }
 
-#[cfg(feature = "jwt")]
 impl FetchedToken for Arc<FetchedJsonWebTokenCredential> {
     fn token(&self) -> &[u8] {
         &self.token
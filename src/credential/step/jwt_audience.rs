@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{FetchedJsonWebTokenCredential, JsonWebTokenCredential};
+use crate::AuthenticError;
+
+type CreateCredential =
+    dyn Fn(&str) -> Result<JsonWebTokenCredential, AuthenticError> + Send + Sync;
+
+type ResolveAudience = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+struct AudienceCache {
+    // Credential for each audience, with the use count when it was last used.
+    credentials: HashMap<String, (Arc<JsonWebTokenCredential>, u64)>,
+    // Audience last used at each use count, in order of use.
+    recent: BTreeMap<u64, String>,
+    uses: u64,
+}
+
+impl AudienceCache {
+    /// Get the credential for `audience`, marking it as the most recently used.
+    fn get(&mut self, audience: &str) -> Option<Arc<JsonWebTokenCredential>> {
+        let (credential, last_used) = self.credentials.get_mut(audience)?;
+        self.uses += 1;
+        let previous = std::mem::replace(last_used, self.uses);
+        if let Some(audience) = self.recent.remove(&previous) {
+            self.recent.insert(self.uses, audience);
+        }
+        Some(credential.clone())
+    }
+
+    /// Add the credential for `audience`, removing the least recently used credential if the
+    /// cache holds `capacity` credentials.
+    fn insert(&mut self, audience: &str, credential: Arc<JsonWebTokenCredential>, capacity: usize) {
+        if self.credentials.len() >= capacity {
+            let least_recent = self.recent.keys().next().copied();
+            if let Some(audience) = least_recent.and_then(|uses| self.recent.remove(&uses)) {
+                self.credentials.remove(&audience);
+            }
+        }
+        self.uses += 1;
+        self.recent.insert(self.uses, audience.to_owned());
+        self.credentials
+            .insert(audience.to_owned(), (credential, self.uses));
+    }
+}
+
+/// JWT credentials for requests to several audiences, keeping a renewing token for each audience.
+///
+/// Some services require a token whose `aud` claim names the API being called. The audience for
+/// each request is chosen from the request URL, by default the origin of the URL followed by `/`,
+/// such as `https://service.example.com/`. The credential for each audience is created by a
+/// function passed to [`JwtAudienceCredentials::new`], which can also set the audience as
+/// another claim, such as a scope.
+///
+/// Credentials are kept for a limited number of audiences. When the limit is reached, the
+/// credential used least recently is removed.
+///
+/// Use with the `JwtAudienceAuthentication` protocol of each HTTP library module.
+pub struct JwtAudienceCredentials {
+    create: Box<CreateCredential>,
+    resolve: Box<ResolveAudience>,
+    cache: Mutex<AudienceCache>,
+    capacity: usize,
+}
+
+impl JwtAudienceCredentials {
+    /// Create credentials for up to `capacity` audiences.
+    ///
+    /// The `create` function is called with an audience to create the credential for the
+    /// audience, for example:
+    ///
+    /// ```ignore
+    /// JwtAudienceCredentials::new(10, move |audience| {
+    ///     Ok(JsonWebTokenCredential::new(header.clone(), key.clone(), expiration)
+    ///         .with_issuer(email.clone())
+    ///         .with_subject(email.clone())
+    ///         .with_audience(audience.to_owned()))
+    /// })
+    /// ```
+    pub fn new<Create>(capacity: usize, create: Create) -> Self
+    where
+        Create: Fn(&str) -> Result<JsonWebTokenCredential, AuthenticError> + Send + Sync + 'static,
+    {
+        Self {
+            create: Box::new(create),
+            resolve: Box::new(origin_audience),
+            cache: Mutex::new(AudienceCache {
+                credentials: HashMap::new(),
+                recent: BTreeMap::new(),
+                uses: 0,
+            }),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Choose the audience for a request URL using `resolve`.
+    ///
+    /// Requests for which `resolve` returns `None` fail with a configuration error.
+    #[must_use]
+    pub fn with_audience_resolver<Resolve>(mut self, resolve: Resolve) -> Self
+    where
+        Resolve: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.resolve = Box::new(resolve);
+        self
+    }
+
+    /// Get the audience for a request to `url`.
+    pub fn audience(&self, url: &str) -> Result<String, AuthenticError> {
+        (self.resolve)(url).ok_or_else(|| {
+            AuthenticError::Configuration(format!("No JWT audience for request URL {:?}", url))
+        })
+    }
+
+    /// Get the credential for `audience`, creating it if required.
+    ///
+    /// The `create` function is called without holding the cache lock, so that a slow `create`
+    /// does not delay requests to other audiences. Concurrent requests for a new audience may
+    /// each create a credential, of which only one is kept.
+    pub fn credential(
+        &self,
+        audience: &str,
+    ) -> Result<Arc<JsonWebTokenCredential>, AuthenticError> {
+        if let Some(credential) = self.lock()?.get(audience) {
+            return Ok(credential);
+        }
+        let created = Arc::new((self.create)(audience)?);
+        let mut cache = self.lock()?;
+        // Another caller may have added a credential for the audience in the meantime. Use it, so
+        // that callers share a token.
+        if let Some(credential) = cache.get(audience) {
+            return Ok(credential);
+        }
+        cache.insert(audience, created.clone(), self.capacity);
+        Ok(created)
+    }
+
+    /// Get a current token for a request to `url`, creating a new token if required.
+    pub fn token(&self, url: &str) -> Result<Arc<FetchedJsonWebTokenCredential>, AuthenticError> {
+        self.credential(&self.audience(url)?)?.fetch_renewed()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, AudienceCache>, AuthenticError> {
+        self.cache.lock().map_err(|_| AuthenticError::LockPoisoned)
+    }
+
+    /// The number of audiences with a credential.
+    pub fn len(&self) -> usize {
+        self.cache
+            .lock()
+            .map(|cache| cache.credentials.len())
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The default audience: the origin of the URL, followed by `/`.
+fn origin_audience(url: &str) -> Option<String> {
+    let uri = url.parse::<http::Uri>().ok()?;
+    Some(format!("{}://{}/", uri.scheme_str()?, uri.authority()?))
+}
@@ -0,0 +1,556 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use base64::Engine;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use pkcs8::der::asn1::{OctetStringRef, UintRef};
+use pkcs8::der::{Decode, Encode};
+use pkcs8::{AlgorithmIdentifierRef, EncryptedPrivateKeyInfo, ObjectIdentifier, PrivateKeyInfo};
+use sec1::{EcParameters, EcPrivateKey};
+
+use crate::AuthenticError;
+
+const OID_RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const OID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const OID_P256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const OID_P384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+const OID_ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Curve {
+    P256,
+    P384,
+}
+
+impl Curve {
+    fn from_oid(oid: ObjectIdentifier) -> Result<Self, AuthenticError> {
+        if oid == OID_P256 {
+            Ok(Self::P256)
+        } else if oid == OID_P384 {
+            Ok(Self::P384)
+        } else {
+            Err(AuthenticError::Configuration(
+                "Unsupported EC curve, expected P-256 or P-384".to_owned(),
+            ))
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Self, AuthenticError> {
+        match name {
+            "P-256" => Ok(Self::P256),
+            "P-384" => Ok(Self::P384),
+            _ => Err(AuthenticError::Configuration(format!(
+                "Unsupported EC curve {:?}, expected P-256 or P-384",
+                name
+            ))),
+        }
+    }
+
+    fn oid(self) -> ObjectIdentifier {
+        match self {
+            Self::P256 => OID_P256,
+            Self::P384 => OID_P384,
+        }
+    }
+
+    /// Size of a coordinate or private key in bytes.
+    fn size(self) -> usize {
+        match self {
+            Self::P256 => 32,
+            Self::P384 => 48,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyType {
+    Hmac,
+    Rsa,
+    Ec(Curve),
+    Ed25519,
+}
+
+impl KeyType {
+    fn default_algorithm(self) -> Algorithm {
+        match self {
+            Self::Hmac => Algorithm::HS256,
+            Self::Rsa => Algorithm::RS256,
+            Self::Ec(Curve::P256) => Algorithm::ES256,
+            Self::Ec(Curve::P384) => Algorithm::ES384,
+            Self::Ed25519 => Algorithm::EdDSA,
+        }
+    }
+
+    fn supports(self, algorithm: Algorithm) -> bool {
+        match self {
+            Self::Hmac => matches!(
+                algorithm,
+                Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+            ),
+            Self::Rsa => matches!(
+                algorithm,
+                Algorithm::RS256
+                    | Algorithm::RS384
+                    | Algorithm::RS512
+                    | Algorithm::PS256
+                    | Algorithm::PS384
+                    | Algorithm::PS512
+            ),
+            Self::Ec(_) | Self::Ed25519 => algorithm == self.default_algorithm(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hmac => "symmetric",
+            Self::Rsa => "RSA",
+            Self::Ec(Curve::P256) => "P-256 EC",
+            Self::Ec(Curve::P384) => "P-384 EC",
+            Self::Ed25519 => "Ed25519",
+        }
+    }
+}
+
+/// A private key for signing JSON Web Tokens, loaded from a file or from bytes.
+///
+/// The supported formats are:
+/// - JWK or JWKS JSON, for symmetric (`oct`), RSA, EC and Ed25519 (`OKP`) keys;
+/// - PKCS#8 `PRIVATE KEY`, for RSA, EC and Ed25519 keys;
+/// - PKCS#8 `ENCRYPTED PRIVATE KEY`, encrypted using PBES2, loaded using
+///   [`JwtPrivateKey::from_encrypted_pem`] or [`JwtPrivateKey::from_encrypted_der`];
+/// - PKCS#1 `RSA PRIVATE KEY`;
+/// - SEC1 `EC PRIVATE KEY`.
+///
+/// PKCS#8, PKCS#1 and SEC1 keys can be PEM or DER encoded. EC keys must use the P-256 or P-384
+/// curve.
+///
+/// The algorithm is taken from the JWK `alg` member if present. Otherwise it is inferred from the
+/// key: HS256 for symmetric keys, RS256 for RSA keys, ES256 or ES384 for EC keys, and EdDSA for
+/// Ed25519 keys. The key ID is taken from the JWK `kid` member. Use
+/// [`JwtPrivateKey::with_algorithm`] and [`JwtPrivateKey::with_key_id`] to change them.
+pub struct JwtPrivateKey {
+    key: EncodingKey,
+    key_type: KeyType,
+    algorithm: Algorithm,
+    kid: Option<String>,
+}
+
+impl JwtPrivateKey {
+    /// Load a key from the file at `path`, in any supported format.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AuthenticError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Load a key from `bytes`, in any supported format.
+    ///
+    /// JSON is loaded using [`JwtPrivateKey::from_jwk`], text containing a PEM block using
+    /// [`JwtPrivateKey::from_pem`], and anything else using [`JwtPrivateKey::from_der`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AuthenticError> {
+        let start = bytes
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(bytes.len());
+        if bytes[start..].starts_with(b"{") {
+            Self::from_jwk(bytes)
+        } else if contains(bytes, b"-----BEGIN ") {
+            Self::from_pem(bytes)
+        } else {
+            Self::from_der(bytes)
+        }
+    }
+
+    /// Load a PEM-encoded PKCS#8, PKCS#1 or SEC1 key.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AuthenticError> {
+        let (label, der) = decode_pem(pem)?;
+        match label {
+            "PRIVATE KEY" => Self::from_pkcs8(&der),
+            "RSA PRIVATE KEY" => Self::from_pkcs1(&der),
+            "EC PRIVATE KEY" => Self::from_sec1(&der),
+            "ENCRYPTED PRIVATE KEY" => Err(encrypted()),
+            label if label.ends_with("PUBLIC KEY") || label == "CERTIFICATE" => Err(
+                AuthenticError::Configuration(format!("PEM {} is not a private key", label)),
+            ),
+            label => Err(AuthenticError::Configuration(format!(
+                "Unsupported PEM label {:?}",
+                label
+            ))),
+        }
+    }
+
+    /// Load a DER-encoded PKCS#8, PKCS#1 or SEC1 key.
+    pub fn from_der(der: &[u8]) -> Result<Self, AuthenticError> {
+        if PrivateKeyInfo::from_der(der).is_ok() {
+            Self::from_pkcs8(der)
+        } else if pkcs1::RsaPrivateKey::from_der(der).is_ok() {
+            Self::from_pkcs1(der)
+        } else if EcPrivateKey::from_der(der).is_ok() {
+            Self::from_sec1(der)
+        } else if EncryptedPrivateKeyInfo::from_der(der).is_ok() {
+            Err(encrypted())
+        } else {
+            Err(AuthenticError::Configuration(
+                "Private key is not a DER-encoded PKCS#8, PKCS#1 or SEC1 key".to_owned(),
+            ))
+        }
+    }
+
+    /// Load a PEM-encoded PKCS#8 `ENCRYPTED PRIVATE KEY`, decrypting it using `password`.
+    pub fn from_encrypted_pem(
+        pem: &[u8],
+        password: impl AsRef<[u8]>,
+    ) -> Result<Self, AuthenticError> {
+        match decode_pem(pem)? {
+            ("ENCRYPTED PRIVATE KEY", der) => Self::from_encrypted_der(&der, password),
+            (label, _) => Err(AuthenticError::Configuration(format!(
+                "PEM {} is not an encrypted private key",
+                label
+            ))),
+        }
+    }
+
+    /// Load a DER-encoded PKCS#8 encrypted private key, decrypting it using `password`.
+    pub fn from_encrypted_der(
+        der: &[u8],
+        password: impl AsRef<[u8]>,
+    ) -> Result<Self, AuthenticError> {
+        let info = EncryptedPrivateKeyInfo::from_der(der).map_err(invalid_der)?;
+        let decrypted = info.decrypt(password).map_err(|err| {
+            AuthenticError::Configuration(format!("Cannot decrypt private key: {}", err))
+        })?;
+        Self::from_pkcs8(decrypted.as_bytes())
+    }
+
+    /// Load a key from a JWK, or from a JWKS containing a single private key.
+    pub fn from_jwk(json: &[u8]) -> Result<Self, AuthenticError> {
+        let value = parse_json(json)?;
+        match value.get("keys") {
+            Some(keys) => {
+                let mut private = jwks_keys(keys)?.filter(|key| is_private_jwk(key));
+                match (private.next(), private.next()) {
+                    (Some(key), None) => Self::from_jwk_value(key),
+                    (None, _) => Err(AuthenticError::Configuration(
+                        "JWKS contains no private keys".to_owned(),
+                    )),
+                    (Some(_), Some(_)) => Err(AuthenticError::Configuration(
+                        "JWKS contains several private keys, use JwtPrivateKey::from_jwks to \
+                         select one"
+                            .to_owned(),
+                    )),
+                }
+            }
+            None => Self::from_jwk_value(&value),
+        }
+    }
+
+    /// Load the key with key ID `kid` from a JWKS.
+    pub fn from_jwks(json: &[u8], kid: &str) -> Result<Self, AuthenticError> {
+        let value = parse_json(json)?;
+        let keys = value.get("keys").ok_or_else(|| {
+            AuthenticError::Configuration("JWKS has no \"keys\" member".to_owned())
+        })?;
+        let key = jwks_keys(keys)?
+            .find(|key| key.get("kid").and_then(serde_json::Value::as_str) == Some(kid))
+            .ok_or_else(|| {
+                AuthenticError::Configuration(format!("JWKS has no key with key ID {:?}", kid))
+            })?;
+        Self::from_jwk_value(key)
+    }
+
+    /// Set the signing algorithm.
+    ///
+    /// Returns an error if the algorithm cannot be used with the key.
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Result<Self, AuthenticError> {
+        if !self.key_type.supports(algorithm) {
+            return Err(AuthenticError::Configuration(format!(
+                "Algorithm {:?} cannot be used with a {} key",
+                algorithm,
+                self.key_type.name()
+            )));
+        }
+        self.algorithm = algorithm;
+        Ok(self)
+    }
+
+    /// Set the key ID, which is set as the `kid` header.
+    #[must_use]
+    pub fn with_key_id(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    /// Return a header with the algorithm and key ID, and the key, as used by [`jsonwebtoken`].
+    pub fn into_header_and_key(self) -> (Header, EncodingKey) {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.kid;
+        (header, self.key)
+    }
+
+    /// Create a key, checking that it can sign using its algorithm.
+    ///
+    /// The key formats are only fully checked when signing, so this reports an invalid key when
+    /// it is loaded, rather than when the first token is created.
+    fn new(
+        key: EncodingKey,
+        key_type: KeyType,
+        kid: Option<String>,
+    ) -> Result<Self, AuthenticError> {
+        let algorithm = key_type.default_algorithm();
+        if let Err(err) = jsonwebtoken::crypto::sign(b"", &key, algorithm) {
+            return Err(AuthenticError::Configuration(format!(
+                "Invalid {} private key: {}",
+                key_type.name(),
+                err
+            )));
+        }
+        Ok(Self {
+            key,
+            key_type,
+            algorithm,
+            kid,
+        })
+    }
+
+    fn from_pkcs8(der: &[u8]) -> Result<Self, AuthenticError> {
+        let info = PrivateKeyInfo::from_der(der).map_err(invalid_der)?;
+        let oid = info.algorithm.oid;
+        if oid == OID_RSA_ENCRYPTION {
+            Self::from_pkcs1(info.private_key)
+        } else if oid == OID_EC_PUBLIC_KEY {
+            let curve = info.algorithm.parameters_oid().map_err(invalid_der)?;
+            let curve = Curve::from_oid(curve)?;
+            Self::new(EncodingKey::from_ec_der(der), KeyType::Ec(curve), None)
+        } else if oid == OID_ED25519 {
+            Self::new(EncodingKey::from_ed_der(der), KeyType::Ed25519, None)
+        } else {
+            Err(AuthenticError::Configuration(
+                "Unsupported PKCS#8 key algorithm, expected RSA, EC or Ed25519".to_owned(),
+            ))
+        }
+    }
+
+    fn from_pkcs1(der: &[u8]) -> Result<Self, AuthenticError> {
+        Self::new(EncodingKey::from_rsa_der(der), KeyType::Rsa, None)
+    }
+
+    fn from_sec1(der: &[u8]) -> Result<Self, AuthenticError> {
+        let key = EcPrivateKey::from_der(der).map_err(invalid_der)?;
+        let curve = key
+            .parameters
+            .and_then(EcParameters::named_curve)
+            .ok_or_else(|| {
+                AuthenticError::Configuration("EC private key does not name its curve".to_owned())
+            })?;
+        let curve = Curve::from_oid(curve)?;
+        Self::new(
+            EncodingKey::from_ec_der(&ec_pkcs8(curve, der)?),
+            KeyType::Ec(curve),
+            None,
+        )
+    }
+
+    fn from_jwk_value(jwk: &serde_json::Value) -> Result<Self, AuthenticError> {
+        let kid = jwk
+            .get("kid")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_owned);
+        if jwk.get("use").and_then(serde_json::Value::as_str) == Some("enc") {
+            return Err(AuthenticError::Configuration(
+                "JWK is an encryption key".to_owned(),
+            ));
+        }
+        if !is_private_jwk(jwk) {
+            return Err(AuthenticError::Configuration(
+                "JWK is a public key, a private key is required".to_owned(),
+            ));
+        }
+        let key = match jwk_str(jwk, "kty")? {
+            "oct" => Self::new(
+                EncodingKey::from_secret(&jwk_bytes(jwk, "k")?),
+                KeyType::Hmac,
+                kid,
+            )?,
+            "RSA" => {
+                let fields = ["n", "e", "d", "p", "q", "dp", "dq", "qi"]
+                    .iter()
+                    .map(|name| jwk_bytes(jwk, name))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let key = pkcs1::RsaPrivateKey {
+                    modulus: der_uint(&fields[0])?,
+                    public_exponent: der_uint(&fields[1])?,
+                    private_exponent: der_uint(&fields[2])?,
+                    prime1: der_uint(&fields[3])?,
+                    prime2: der_uint(&fields[4])?,
+                    exponent1: der_uint(&fields[5])?,
+                    exponent2: der_uint(&fields[6])?,
+                    coefficient: der_uint(&fields[7])?,
+                    other_prime_infos: None,
+                };
+                Self::new(
+                    EncodingKey::from_rsa_der(&key.to_der().map_err(invalid_der)?),
+                    KeyType::Rsa,
+                    kid,
+                )?
+            }
+            "EC" => {
+                let curve = Curve::from_name(jwk_str(jwk, "crv")?)?;
+                let private_key = jwk_coordinate(jwk, "d", curve.size())?;
+                let mut public_key = vec![0x04];
+                public_key.extend(jwk_coordinate(jwk, "x", curve.size())?);
+                public_key.extend(jwk_coordinate(jwk, "y", curve.size())?);
+                let sec1 = EcPrivateKey {
+                    private_key: &private_key,
+                    parameters: Some(EcParameters::NamedCurve(curve.oid())),
+                    public_key: Some(&public_key),
+                }
+                .to_der()
+                .map_err(invalid_der)?;
+                Self::new(
+                    EncodingKey::from_ec_der(&ec_pkcs8(curve, &sec1)?),
+                    KeyType::Ec(curve),
+                    kid,
+                )?
+            }
+            "OKP" => {
+                let crv = jwk_str(jwk, "crv")?;
+                if crv != "Ed25519" {
+                    return Err(AuthenticError::Configuration(format!(
+                        "Unsupported OKP curve {:?}, expected Ed25519",
+                        crv
+                    )));
+                }
+                let private_key = jwk_bytes(jwk, "d")?;
+                let private_key = OctetStringRef::new(&private_key)
+                    .and_then(|private_key| private_key.to_der())
+                    .map_err(invalid_der)?;
+                let algorithm = AlgorithmIdentifierRef {
+                    oid: OID_ED25519,
+                    parameters: None,
+                };
+                let pkcs8 = PrivateKeyInfo::new(algorithm, &private_key)
+                    .to_der()
+                    .map_err(invalid_der)?;
+                Self::new(EncodingKey::from_ed_der(&pkcs8), KeyType::Ed25519, kid)?
+            }
+            kty => {
+                return Err(AuthenticError::Configuration(format!(
+                    "Unsupported JWK key type {:?}",
+                    kty
+                )))
+            }
+        };
+        match jwk.get("alg").and_then(serde_json::Value::as_str) {
+            Some(alg) => {
+                let algorithm = Algorithm::from_str(alg).map_err(|_| {
+                    AuthenticError::Configuration(format!("Unsupported JWK algorithm {:?}", alg))
+                })?;
+                key.with_algorithm(algorithm)
+            }
+            None => Ok(key),
+        }
+    }
+}
+
+fn encrypted() -> AuthenticError {
+    AuthenticError::Configuration(
+        "Private key is encrypted, load it using JwtPrivateKey::from_encrypted_pem or \
+         JwtPrivateKey::from_encrypted_der"
+            .to_owned(),
+    )
+}
+
+fn invalid_der(err: impl std::fmt::Display) -> AuthenticError {
+    AuthenticError::Configuration(format!("Invalid DER-encoded private key: {}", err))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Get the label and contents of the PEM block in `pem`.
+fn decode_pem(pem: &[u8]) -> Result<(&str, Vec<u8>), AuthenticError> {
+    pkcs8::der::pem::decode_vec(pem).map_err(|err| match err {
+        // Keys encrypted using the legacy OpenSSL format have a `Proc-Type` header.
+        pkcs8::der::pem::Error::HeaderDisallowed => AuthenticError::Configuration(
+            "Encrypted PEM keys in the legacy OpenSSL format are not supported, convert the key \
+             to PKCS#8 first"
+                .to_owned(),
+        ),
+        err => AuthenticError::Configuration(format!("Invalid PEM key: {}", err)),
+    })
+}
+
+fn parse_json(json: &[u8]) -> Result<serde_json::Value, AuthenticError> {
+    serde_json::from_slice(json)
+        .map_err(|err| AuthenticError::Configuration(format!("Invalid JWK: {}", err)))
+}
+
+fn jwks_keys(
+    keys: &serde_json::Value,
+) -> Result<impl Iterator<Item = &serde_json::Value>, AuthenticError> {
+    keys.as_array().map(|keys| keys.iter()).ok_or_else(|| {
+        AuthenticError::Configuration("JWKS \"keys\" member is not an array".to_owned())
+    })
+}
+
+fn is_private_jwk(jwk: &serde_json::Value) -> bool {
+    jwk.get("d").is_some() || jwk.get("kty").and_then(serde_json::Value::as_str) == Some("oct")
+}
+
+fn jwk_str<'a>(jwk: &'a serde_json::Value, name: &str) -> Result<&'a str, AuthenticError> {
+    jwk.get(name)
+        .and_then(serde_json::Value::as_str)
+        .ok_or_else(|| AuthenticError::Configuration(format!("JWK has no {:?} member", name)))
+}
+
+fn jwk_bytes(jwk: &serde_json::Value, name: &str) -> Result<Vec<u8>, AuthenticError> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(jwk_str(jwk, name)?.trim_end_matches('='))
+        .map_err(|_| {
+            AuthenticError::Configuration(format!("JWK {:?} member is not valid base64url", name))
+        })
+}
+
+/// Get an EC coordinate or private key, padded to the size of the curve.
+fn jwk_coordinate(
+    jwk: &serde_json::Value,
+    name: &str,
+    size: usize,
+) -> Result<Vec<u8>, AuthenticError> {
+    let bytes = jwk_bytes(jwk, name)?;
+    if bytes.len() > size {
+        return Err(AuthenticError::Configuration(format!(
+            "JWK {:?} member is too long for the curve",
+            name
+        )));
+    }
+    let mut padded = vec![0; size - bytes.len()];
+    padded.extend(bytes);
+    Ok(padded)
+}
+
+/// Wrap a SEC1 EC private key in a PKCS#8 structure.
+fn ec_pkcs8(curve: Curve, sec1: &[u8]) -> Result<Vec<u8>, AuthenticError> {
+    let curve = curve.oid();
+    let algorithm = AlgorithmIdentifierRef {
+        oid: OID_EC_PUBLIC_KEY,
+        parameters: Some((&curve).into()),
+    };
+    PrivateKeyInfo::new(algorithm, sec1)
+        .to_der()
+        .map_err(invalid_der)
+}
+
+/// Get an unsigned big-endian integer for encoding as a DER integer.
+fn der_uint(bytes: &[u8]) -> Result<UintRef<'_>, AuthenticError> {
+    UintRef::new(bytes).map_err(invalid_der)
+}
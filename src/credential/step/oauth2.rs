@@ -0,0 +1,356 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::RenewRequests;
+#[cfg(feature = "jwt")]
+use crate::credential::JsonWebTokenCredential;
+use crate::credential::{
+    AuthenticationCredential, Clock, FetchedToken, RenewableCredential, RenewalPolicy, SystemClock,
+};
+use crate::{AuthenticError, AuthenticationStep};
+
+/// Lifetime of an access token returned without `expires_in`.
+const DEFAULT_EXPIRES_IN: Duration = Duration::from_secs(60 * 60);
+
+/// Client assertion type for JWT client authentication (RFC 7523 section 2.2).
+#[cfg(feature = "jwt")]
+const JWT_BEARER_ASSERTION: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// An implementation of [`FetchedToken`] returned from [`OAuth2ClientCredential`].
+pub struct FetchedOAuth2ClientCredential {
+    token: Vec<u8>,
+    renew: SystemTime,
+    expiry: SystemTime,
+}
+
+#[derive(serde::Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// How the client authenticates to the token endpoint.
+enum ClientAuthentication {
+    /// `client_secret_basic`: the client ID and secret using HTTP Basic authentication.
+    SecretBasic(Cow<'static, str>),
+    /// `client_secret_post`: the client ID and secret in the request body.
+    SecretPost(Cow<'static, str>),
+    /// `client_secret_jwt` or `private_key_jwt`: a JWT signed by the client.
+    #[cfg(feature = "jwt")]
+    Assertion(Box<JsonWebTokenCredential>),
+}
+
+/// Credential for an OAuth2 client, using the client credentials grant.
+///
+/// Requests an access token from the token endpoint, authenticating as the client, and renews the
+/// access token before it expires. The client can authenticate using:
+/// - `client_secret_basic`: [`OAuth2ClientCredential::client_secret_basic`];
+/// - `client_secret_post`: [`OAuth2ClientCredential::client_secret_post`];
+/// - `client_secret_jwt`: [`OAuth2ClientCredential::client_secret_jwt`] (feature `jwt`);
+/// - `private_key_jwt`: [`OAuth2ClientCredential::private_key_jwt`] (feature `jwt`).
+///
+/// For the JWT methods (RFC 7523), a new client assertion is signed for each token request, with
+/// the client ID as the issuer and subject, the token endpoint URL as the audience, and a unique
+/// JWT ID.
+pub struct OAuth2ClientCredential {
+    current: arc_swap::ArcSwapOption<FetchedOAuth2ClientCredential>,
+    renewing: RenewRequests,
+    token_url: Cow<'static, str>,
+    client_id: Cow<'static, str>,
+    client_authentication: ClientAuthentication,
+    scope: Option<Cow<'static, str>>,
+    parameters: Vec<(Cow<'static, str>, Cow<'static, str>)>,
+    renewal: RenewalPolicy,
+    clock: Arc<dyn Clock>,
+}
+
+impl OAuth2ClientCredential {
+    /// Create a credential authenticating using HTTP Basic authentication with the client ID and
+    /// secret.
+    pub fn client_secret_basic(
+        token_url: impl Into<Cow<'static, str>>,
+        client_id: impl Into<Cow<'static, str>>,
+        client_secret: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self::new(
+            token_url.into(),
+            client_id.into(),
+            ClientAuthentication::SecretBasic(client_secret.into()),
+        )
+    }
+
+    /// Create a credential sending the client ID and secret in the request body.
+    pub fn client_secret_post(
+        token_url: impl Into<Cow<'static, str>>,
+        client_id: impl Into<Cow<'static, str>>,
+        client_secret: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self::new(
+            token_url.into(),
+            client_id.into(),
+            ClientAuthentication::SecretPost(client_secret.into()),
+        )
+    }
+
+    /// Create a credential authenticating using a JWT signed with the client secret, using
+    /// `HS256`.
+    ///
+    /// Requires feature `jwt`.
+    #[cfg(feature = "jwt")]
+    pub fn client_secret_jwt(
+        token_url: impl Into<Cow<'static, str>>,
+        client_id: impl Into<Cow<'static, str>>,
+        client_secret: impl AsRef<[u8]>,
+    ) -> Self {
+        let assertion = JsonWebTokenCredential::new(
+            jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+            jsonwebtoken::EncodingKey::from_secret(client_secret.as_ref()),
+            Duration::from_secs(60),
+        );
+        Self::private_key_jwt(token_url, client_id, assertion)
+    }
+
+    /// Create a credential authenticating using a JWT signed by `assertion`.
+    ///
+    /// The `iss`, `sub`, `aud` and `jti` claims of `assertion` are set by this credential, and
+    /// other settings, such as the key, expiration and custom claims, are kept. For example:
+    ///
+    /// ```ignore
+    /// let assertion = JsonWebTokenCredential::from_key_file("client.pem", Duration::from_secs(60))?;
+    /// let credential = OAuth2ClientCredential::private_key_jwt(token_url, client_id, assertion);
+    /// ```
+    ///
+    /// Requires feature `jwt`.
+    #[cfg(feature = "jwt")]
+    pub fn private_key_jwt(
+        token_url: impl Into<Cow<'static, str>>,
+        client_id: impl Into<Cow<'static, str>>,
+        assertion: JsonWebTokenCredential,
+    ) -> Self {
+        let token_url = token_url.into();
+        let client_id = client_id.into();
+        let assertion = assertion
+            .with_issuer(client_id.clone())
+            .with_subject(client_id.clone())
+            .with_audience(token_url.clone())
+            .with_jwt_id();
+        Self::new(
+            token_url,
+            client_id,
+            ClientAuthentication::Assertion(Box::new(assertion)),
+        )
+    }
+
+    fn new(
+        token_url: Cow<'static, str>,
+        client_id: Cow<'static, str>,
+        client_authentication: ClientAuthentication,
+    ) -> Self {
+        Self {
+            current: arc_swap::ArcSwapOption::from(None),
+            renewing: RenewRequests::new(()),
+            token_url,
+            client_id,
+            client_authentication,
+            scope: None,
+            parameters: Vec::new(),
+            renewal: RenewalPolicy::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Use a different clock to decide when to renew the token, and to set the times in client
+    /// assertions.
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Set when the access token is renewed.
+    ///
+    /// By default, access tokens are renewed after half their lifetime. Tokens returned without
+    /// an `expires_in` lifetime are treated as valid for one hour.
+    #[must_use]
+    pub fn with_renewal_policy(mut self, renewal: RenewalPolicy) -> Self {
+        self.renewal = renewal;
+        self
+    }
+
+    /// Request an access token with `scope`, a space-separated list of scopes.
+    #[must_use]
+    pub fn with_scope(mut self, scope: impl Into<Cow<'static, str>>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Add a parameter to the token request, such as `audience` or `resource`.
+    #[must_use]
+    pub fn with_parameter(
+        mut self,
+        name: impl Into<Cow<'static, str>>,
+        value: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        self.parameters.push((name.into(), value.into()));
+        self
+    }
+
+    // Only JWT client authentication uses the time.
+    #[cfg_attr(not(feature = "jwt"), allow(unused_variables))]
+    fn token_request(&self, now: SystemTime) -> Result<http::Request<Vec<u8>>, AuthenticError> {
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", "client_credentials");
+        if let Some(scope) = &self.scope {
+            form.append_pair("scope", scope);
+        }
+        for (name, value) in &self.parameters {
+            form.append_pair(name, value);
+        }
+        let mut request = http::Request::post(self.token_url.as_ref())
+            .header(http::header::ACCEPT, "application/json")
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .header(http::header::USER_AGENT, "authentic");
+        match &self.client_authentication {
+            ClientAuthentication::SecretBasic(client_secret) => {
+                // The client ID and secret are form-encoded before Basic encoding (RFC 6749
+                // section 2.3.1).
+                let encode = |value: &str| {
+                    form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>()
+                };
+                let mut header_value =
+                    http::HeaderValue::try_from(http_auth::basic::encode_credentials(
+                        &encode(&self.client_id),
+                        &encode(client_secret),
+                    ))?;
+                header_value.set_sensitive(true);
+                request = request.header(http::header::AUTHORIZATION, header_value);
+            }
+            ClientAuthentication::SecretPost(client_secret) => {
+                form.append_pair("client_id", &self.client_id);
+                form.append_pair("client_secret", client_secret);
+            }
+            #[cfg(feature = "jwt")]
+            ClientAuthentication::Assertion(assertion) => {
+                let token = assertion.create_token(now)?.into_token();
+                // A JWT in compact serialization is always ASCII.
+                let token = String::from_utf8_lossy(&token);
+                form.append_pair("client_id", &self.client_id);
+                form.append_pair("client_assertion_type", JWT_BEARER_ASSERTION);
+                form.append_pair("client_assertion", &token);
+            }
+        }
+        Ok(request.body(form.finish().into_bytes())?)
+    }
+
+    fn parse_response(
+        &self,
+        response: Result<http::Response<Vec<u8>>, AuthenticError>,
+    ) -> Result<FetchedOAuth2ClientCredential, AuthenticError> {
+        let response = response?;
+        if !response.status().is_success() {
+            return Err(AuthenticError::token_endpoint(
+                response.status(),
+                response.body(),
+            ));
+        }
+        let body: AccessTokenResponse = serde_json::from_slice(response.body())
+            .map_err(|err| AuthenticError::TokenResponse(err.to_string()))?;
+        let now = self.clock.now();
+        let expires_in = body
+            .expires_in
+            .map_or(DEFAULT_EXPIRES_IN, Duration::from_secs);
+        let expiry = now.checked_add(expires_in).ok_or_else(|| {
+            AuthenticError::TokenResponse(format!("Token expiry too large: {:?}", expires_in))
+        })?;
+        Ok(FetchedOAuth2ClientCredential {
+            token: body.access_token.into_bytes(),
+            renew: self.renewal.renew_time(now, expiry),
+            expiry,
+        })
+    }
+}
+
+impl AuthenticationCredential for OAuth2ClientCredential {
+    type Fetch = Arc<FetchedOAuth2ClientCredential>;
+
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        self.refresh_step(Duration::ZERO)
+    }
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        match self.parse_response(response) {
+            Ok(fetched) => {
+                let renew = fetched.renew;
+                self.renewing.succeeded(renew, |_| {
+                    self.current.store(Some(Arc::new(fetched)));
+                });
+            }
+            Err(err) => {
+                // Back off, keeping the current token while it is valid.
+                let expiry = self.current.load().as_ref().map(|current| current.expiry);
+                let retry = self.renewal.retry_time(self.clock.now(), expiry);
+                self.renewing.failed(retry, err, |_| {});
+            }
+        }
+    }
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        self.current.load_full().ok_or_else(|| {
+            AuthenticError::CredentialUnavailable("No token has been requested".to_owned())
+        })
+    }
+}
+
+impl RenewableCredential for OAuth2ClientCredential {
+    fn renew_in(&self) -> Option<Duration> {
+        self.current.load().as_ref().map(|current| {
+            current
+                .renew
+                .duration_since(self.clock.now())
+                .unwrap_or_default()
+        })
+    }
+
+    fn refresh_step(
+        &self,
+        lead: Duration,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        let now = self.clock.now();
+        // Time used to decide whether the token is due to be renewed.
+        let due = now + lead;
+        let current_is_valid = {
+            let guard = self.current.load();
+            if let Some(current) = &*guard {
+                if due < current.renew {
+                    // Current token is valid and too early to renew.
+                    return Ok(None);
+                } else {
+                    now < current.expiry
+                }
+            } else {
+                false
+            }
+        };
+        // The renew time is checked again while holding the lock, in case another caller renewed
+        // the token after this caller loaded the current token.
+        self.renewing.step(
+            now,
+            current_is_valid,
+            |state| due >= state.renew,
+            |_| self.token_request(now),
+        )
+    }
+}
+
+impl FetchedToken for Arc<FetchedOAuth2ClientCredential> {
+    fn token(&self) -> &[u8] {
+        &self.token
+    }
+}
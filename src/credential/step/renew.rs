@@ -0,0 +1,123 @@
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use crate::credential::RenewalNotifier;
+use crate::{AuthenticError, AuthenticationStep};
+
+/// Time after which an unanswered renewal request is abandoned and a new request may be made.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub(crate) struct RenewState<Extra> {
+    // Time at which the credential is due to be renewed, or a failed request retried. Copy of the
+    // renew time, to prevent race conditions.
+    pub(crate) renew: SystemTime,
+    // Time at which the current request was made, if a request is outstanding.
+    requested: Option<SystemTime>,
+    // Error from the last request, returned to the next caller without a valid credential.
+    error: Option<AuthenticError>,
+    // State specific to the credential.
+    pub(crate) extra: Extra,
+}
+
+/// Requests renewing a credential that is fetched from a server.
+///
+/// One caller makes each request. Other callers continue to use the current credential while it
+/// is valid, or wait for the response. After a failed request, the error is returned to the next
+/// caller that has no valid credential, and the request is not made again until its retry time.
+pub(crate) struct RenewRequests<Extra = ()> {
+    state: Mutex<RenewState<Extra>>,
+    // Wakes callers waiting for the outstanding request to complete.
+    renewed: RenewalNotifier,
+}
+
+impl<Extra> RenewRequests<Extra> {
+    pub(crate) fn new(extra: Extra) -> Self {
+        Self {
+            state: Mutex::new(RenewState {
+                renew: SystemTime::UNIX_EPOCH,
+                requested: None,
+                error: None,
+                extra,
+            }),
+            renewed: RenewalNotifier::new(),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> Result<MutexGuard<'_, RenewState<Extra>>, AuthenticError> {
+        self.state.lock().map_err(|_| AuthenticError::LockPoisoned)
+    }
+
+    /// Get the next step for a caller at `now`.
+    ///
+    /// `is_due` decides whether the credential is due to be renewed, and `request` creates the
+    /// request to renew it.
+    pub(crate) fn step(
+        &self,
+        now: SystemTime,
+        current_is_valid: bool,
+        is_due: impl FnOnce(&RenewState<Extra>) -> bool,
+        request: impl FnOnce(&mut Extra) -> Result<http::Request<Vec<u8>>, AuthenticError>,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        let mut state = self.lock()?;
+        if !current_is_valid {
+            if let Some(error) = state.error.take() {
+                return Err(error);
+            }
+        }
+        if !is_due(&state) {
+            return Ok(None);
+        }
+        match state.requested {
+            Some(requested) if now < requested + REQUEST_TIMEOUT => {
+                // Another caller is making the request.
+                if current_is_valid {
+                    Ok(None)
+                } else {
+                    // The response is stored while holding the lock, so a waiter created here
+                    // will be notified.
+                    let timeout = (requested + REQUEST_TIMEOUT)
+                        .duration_since(now)
+                        .unwrap_or_default();
+                    Ok(Some(AuthenticationStep::WaitForRenewal(
+                        self.renewed.waiter(timeout),
+                    )))
+                }
+            }
+            _ => {
+                // First caller after the renew time makes the request.
+                let request = request(&mut state.extra)?;
+                state.requested = Some(now);
+                state.error = None;
+                Ok(Some(AuthenticationStep::Request(request)))
+            }
+        }
+    }
+
+    /// Record a successful response, renewing the credential again at `renew`.
+    ///
+    /// `store` is called while holding the lock, to store the new credential.
+    pub(crate) fn succeeded(&self, renew: SystemTime, store: impl FnOnce(&mut Extra)) {
+        if let Ok(mut state) = self.state.lock() {
+            state.requested = None;
+            state.renew = renew;
+            store(&mut state.extra);
+        }
+        self.renewed.notify();
+    }
+
+    /// Record a failed request, making the request again at `retry`.
+    pub(crate) fn failed(
+        &self,
+        retry: SystemTime,
+        error: AuthenticError,
+        update: impl FnOnce(&mut Extra),
+    ) {
+        if let Ok(mut state) = self.state.lock() {
+            state.requested = None;
+            state.renew = retry;
+            state.error = Some(error);
+            update(&mut state.extra);
+        }
+        self.renewed.notify();
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex, Weak};
+
+type Callback<Fetch> = Arc<dyn Fn(&Fetch) + Send + Sync>;
+
+struct Callbacks<Fetch> {
+    // Identifier for the next subscription.
+    next_id: u64,
+    callbacks: Vec<(u64, Callback<Fetch>)>,
+}
+
+struct SubscriberList<Fetch> {
+    callbacks: Mutex<Callbacks<Fetch>>,
+}
+
+impl<Fetch> SubscriberList<Fetch> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Callbacks<Fetch>> {
+        self.callbacks
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+}
+
+trait Unsubscribe: Send + Sync {
+    fn unsubscribe(&self, id: u64);
+}
+
+impl<Fetch> Unsubscribe for SubscriberList<Fetch> {
+    fn unsubscribe(&self, id: u64) {
+        self.lock()
+            .callbacks
+            .retain(|(callback_id, _)| *callback_id != id);
+    }
+}
+
+/// A callback subscribed to credential updates.
+///
+/// The callback is removed when the subscription is dropped. Call [`Subscription::detach`] to
+/// keep the callback for as long as the credential exists.
+#[must_use = "the callback is removed when the subscription is dropped"]
+pub struct Subscription {
+    subscribers: Option<Weak<dyn Unsubscribe>>,
+    id: u64,
+}
+
+impl Subscription {
+    /// Keep the callback for as long as the credential exists.
+    pub fn detach(mut self) {
+        self.subscribers = None;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(subscribers) = self.subscribers.take().and_then(|weak| weak.upgrade()) {
+            subscribers.unsubscribe(self.id);
+        }
+    }
+}
+
+/// Callbacks to call when a credential is updated.
+pub(crate) struct UpdateSubscribers<Fetch> {
+    list: Arc<SubscriberList<Fetch>>,
+}
+
+impl<Fetch> UpdateSubscribers<Fetch>
+where
+    Fetch: 'static,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            list: Arc::new(SubscriberList {
+                callbacks: Mutex::new(Callbacks {
+                    next_id: 0,
+                    callbacks: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    pub(crate) fn subscribe(
+        &self,
+        callback: impl Fn(&Fetch) + Send + Sync + 'static,
+    ) -> Subscription {
+        let mut callbacks = self.list.lock();
+        let id = callbacks.next_id;
+        callbacks.next_id += 1;
+        callbacks.callbacks.push((id, Arc::new(callback)));
+        let list: Arc<dyn Unsubscribe> = self.list.clone();
+        Subscription {
+            subscribers: Some(Arc::downgrade(&list)),
+            id,
+        }
+    }
+
+    /// Call each callback with the updated credential.
+    pub(crate) fn notify(&self, fetched: &Fetch) {
+        // Callbacks are called without holding the lock, so that they can subscribe.
+        let callbacks = self
+            .list
+            .lock()
+            .callbacks
+            .iter()
+            .map(|(_, callback)| callback.clone())
+            .collect::<Vec<_>>();
+        for callback in callbacks {
+            callback(fetched);
+        }
+    }
+}
@@ -6,11 +6,134 @@ use std::sync::Arc;
 
 use http::HeaderValue;
 
+#[cfg(all(feature = "jwt", feature = "step"))]
+use crate::credential::JwtAudienceCredentials;
+#[cfg(feature = "oci")]
+use crate::credential::OciRegistryCredentials;
 use crate::credential::{AuthenticationCredential, FetchedToken, FetchedUsernamePassword};
+#[cfg(feature = "loop")]
+use crate::HttpNegotiation;
 use crate::{
     AuthenticError, AuthenticationProtocol, AuthenticationProtocolConfigure, AuthenticationStep,
+    DynAuthenticationProtocol,
 };
 
+/// Read the body of a response to an [`AuthenticationStep::Request`] into memory.
+///
+/// The `respond` method of a protocol is synchronous, so it can only read a response body that
+/// has already been received. Pass responses through this function before calling `respond`:
+///
+/// ```ignore
+/// AuthenticationStep::Request(request) => {
+///     let auth_response = buffer_response(client.request(request).await).await;
+///     authentication.respond(auth_response);
+/// }
+/// ```
+pub async fn buffer_response(
+    response: Result<hyper::Response<hyper::Body>, hyper::Error>,
+) -> Result<hyper::Response<hyper::Body>, hyper::Error> {
+    let (parts, body) = response?.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    Ok(hyper::Response::from_parts(parts, hyper::Body::from(body)))
+}
+
+/// Convert a step using an `http` request into a step using a request for this library.
+fn into_step(
+    step: Option<AuthenticationStep<http::Request<Vec<u8>>>>,
+) -> Result<Option<AuthenticationStep<hyper::Request<hyper::Body>>>, AuthenticError> {
+    match step {
+        Some(AuthenticationStep::Request(request)) => Ok(Some(AuthenticationStep::Request(
+            request.map(hyper::Body::from),
+        ))),
+        Some(AuthenticationStep::WaitFor(duration)) => {
+            Ok(Some(AuthenticationStep::WaitFor(duration)))
+        }
+        Some(AuthenticationStep::WaitForRenewal(waiter)) => {
+            Ok(Some(AuthenticationStep::WaitForRenewal(waiter)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Convert a response to a step request into an `http` response with the body read into memory.
+fn into_response(
+    response: Result<hyper::Response<hyper::Body>, hyper::Error>,
+) -> Result<http::Response<Vec<u8>>, AuthenticError> {
+    let (parts, body) = response?.into_parts();
+    let body = crate::now_or_never(hyper::body::to_bytes(body))
+        .ok_or_else(|| AuthenticError::ResponseNotBuffered)??;
+    Ok(http::Response::from_parts(parts, body.to_vec()))
+}
+
+/// Protocol chosen at runtime, for use with `hyper`.
+pub type DynAuthentication = dyn DynAuthenticationProtocol<
+        hyper::Request<hyper::Body>,
+        hyper::Response<hyper::Body>,
+        hyper::Error,
+    > + Send
+    + Sync;
+
+impl<Protocol>
+    DynAuthenticationProtocol<
+        hyper::Request<hyper::Body>,
+        hyper::Response<hyper::Body>,
+        hyper::Error,
+    > for Protocol
+where
+    Protocol: AuthenticationProtocol<
+            Request = hyper::Request<hyper::Body>,
+            Response = hyper::Response<hyper::Body>,
+            Error = hyper::Error,
+        > + AuthenticationProtocolConfigure<http::request::Builder>,
+{
+    fn dyn_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<hyper::Request<hyper::Body>>>, AuthenticError> {
+        self.step()
+    }
+
+    fn dyn_respond(&mut self, response: Result<hyper::Response<hyper::Body>, hyper::Error>) {
+        self.respond(response)
+    }
+
+    fn dyn_configure(
+        &self,
+        mut request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Request<hyper::Body>, AuthenticError> {
+        // Protocols configure a builder, so configure an empty request with the same method and
+        // URI, and copy the headers that were added. The copied headers replace any existing
+        // values, so that configuring a request again does not duplicate `Authorization`.
+        let builder = http::Request::builder()
+            .method(request.method().clone())
+            .uri(request.uri().clone())
+            .version(request.version());
+        let (configured, ()) = self.configure(builder)?.body(())?.into_parts();
+        let mut previous = None;
+        for (name, value) in configured.headers {
+            // Further values for the same header are returned without a name.
+            match name {
+                Some(name) => {
+                    request.headers_mut().insert(&name, value);
+                    previous = Some(name);
+                }
+                None => {
+                    if let Some(name) = &previous {
+                        request.headers_mut().append(name, value);
+                    }
+                }
+            }
+        }
+        Ok(request)
+    }
+
+    fn dyn_has_completed(
+        &mut self,
+        response: &hyper::Response<hyper::Body>,
+    ) -> Result<bool, AuthenticError> {
+        self.has_completed(response)
+    }
+}
+
 /// Protocol for no authentication
 ///
 /// Identical to not using `authentic` but allows minimal code changes when changing protocols.
@@ -60,11 +183,11 @@ where
     type Error = hyper::Error;
 
     fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
-        match self.credential.auth_step() {
-            Ok(duration) if duration.is_zero() => Ok(None),
-            Ok(duration) => Ok(Some(AuthenticationStep::WaitFor(duration))),
-            Err(err) => Err(err),
-        }
+        into_step(self.credential.auth_step()?)
+    }
+
+    fn respond(&mut self, response: Result<Self::Response, Self::Error>) {
+        self.credential.auth_respond(into_response(response));
     }
 }
 
@@ -122,11 +245,11 @@ where
     type Error = hyper::Error;
 
     fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
-        match self.credential.auth_step() {
-            Ok(duration) if duration.is_zero() => Ok(None),
-            Ok(duration) => Ok(Some(AuthenticationStep::WaitFor(duration))),
-            Err(err) => Err(err),
-        }
+        into_step(self.credential.auth_step()?)
+    }
+
+    fn respond(&mut self, response: Result<Self::Response, Self::Error>) {
+        self.credential.auth_respond(into_response(response));
     }
 }
 
@@ -152,6 +275,75 @@ where
     }
 }
 
+/// Authentication using a JWT bearer token for the audience of each request.
+///
+/// Requires features `jwt` and `step`.
+///
+/// The audience is chosen from the request URL when the request is configured, and the token for
+/// the audience is created or renewed at that time, so `step` never returns a step.
+#[cfg(all(feature = "jwt", feature = "step"))]
+pub struct JwtAudienceAuthentication {
+    auth_scheme: Cow<'static, str>,
+    credentials: Arc<JwtAudienceCredentials>,
+}
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+impl JwtAudienceAuthentication {
+    pub fn new(credentials: Arc<JwtAudienceCredentials>) -> Self {
+        Self {
+            auth_scheme: "Bearer".into(),
+            credentials,
+        }
+    }
+
+    /// Change the default `Bearer` scheme to another string.
+    pub fn with_auth_scheme(mut self, auth_scheme: impl Into<Cow<'static, str>>) -> Self {
+        self.auth_scheme = auth_scheme.into();
+        self
+    }
+
+    /// The `Authorization` header value for a request to `url`.
+    fn header_value(&self, url: &str) -> Result<HeaderValue, AuthenticError> {
+        let fetched = self.credentials.token(url)?;
+        let token = fetched.token();
+        let mut value = Vec::with_capacity(self.auth_scheme.len() + 1 + token.len());
+        value.extend(self.auth_scheme.as_bytes());
+        value.push(b' ');
+        value.extend(token);
+        let mut header_value = HeaderValue::try_from(value)?;
+        header_value.set_sensitive(true);
+        Ok(header_value)
+    }
+}
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+impl AuthenticationProtocol for JwtAudienceAuthentication {
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+    type Error = hyper::Error;
+
+    fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
+        Ok(None)
+    }
+
+    fn respond(&mut self, _response: Result<Self::Response, Self::Error>) {}
+}
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+impl AuthenticationProtocolConfigure<http::request::Builder> for JwtAudienceAuthentication {
+    fn configure(
+        &self,
+        builder: http::request::Builder,
+    ) -> Result<http::request::Builder, AuthenticError> {
+        let url = builder
+            .uri_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let header_value = self.header_value(&url)?;
+        Ok(builder.header(hyper::header::AUTHORIZATION, header_value))
+    }
+}
+
 /// Authentication using HTTP Basic authentication on the initial call without waiting for a challenge.
 pub struct BasicAuthentication<Credential> {
     credential: Arc<Credential>,
@@ -173,11 +365,11 @@ where
     type Error = hyper::Error;
 
     fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
-        match self.credential.auth_step() {
-            Ok(duration) if duration.is_zero() => Ok(None),
-            Ok(duration) => Ok(Some(AuthenticationStep::WaitFor(duration))),
-            Err(err) => Err(err),
-        }
+        into_step(self.credential.auth_step()?)
+    }
+
+    fn respond(&mut self, response: Result<Self::Response, Self::Error>) {
+        self.credential.auth_respond(into_response(response));
     }
 }
 
@@ -199,23 +391,38 @@ where
     }
 }
 
-/// Authentication using HTTP Basic authentication to respond to a challenge.
+/// Authentication using HTTP Basic or Digest authentication to respond to a challenge.
 ///
 /// Requires feature `loop` (enabled by default).
 ///
-/// This currently only supports Basic authentication.
-///
-/// This limitation is expected to be removed in a future version.
+/// If the server offers several challenges, the scheme is chosen by the [`HttpNegotiation`].
+/// By default, Digest is preferred over Basic, and challenges for realms without a credential
+/// are skipped.
 #[cfg(feature = "loop")]
-pub enum HttpAuthentication<Credential> {
-    Initial(Arc<crate::credential::HttpRealmCredentials<Credential>>),
-    Basic(BasicAuthentication<Credential>),
+pub struct HttpAuthentication<Credential> {
+    credential: Arc<crate::credential::HttpRealmCredentials<Credential>>,
+    state: crate::protocol::HttpChallengeState<Credential>,
 }
 
 #[cfg(feature = "loop")]
-impl<Credential> HttpAuthentication<Credential> {
+impl<Credential> HttpAuthentication<Credential>
+where
+    Credential: AuthenticationCredential,
+    <Credential as AuthenticationCredential>::Fetch: FetchedUsernamePassword,
+{
     pub fn new(credential: Arc<crate::credential::HttpRealmCredentials<Credential>>) -> Self {
-        Self::Initial(credential)
+        Self::with_negotiation(credential, HttpNegotiation::default())
+    }
+
+    /// Create the protocol, choosing between offered challenges using `negotiation`.
+    pub fn with_negotiation(
+        credential: Arc<crate::credential::HttpRealmCredentials<Credential>>,
+        negotiation: HttpNegotiation,
+    ) -> Self {
+        Self {
+            credential,
+            state: crate::protocol::HttpChallengeState::new(negotiation),
+        }
     }
 }
 
@@ -230,51 +437,20 @@ where
     type Error = hyper::Error;
 
     fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
-        match self {
-            Self::Initial(_) => Ok(None),
-            Self::Basic(basic) => basic.step(),
-        }
+        into_step(self.state.step()?)
     }
 
     fn respond(&mut self, response: Result<Self::Response, Self::Error>) {
-        match self {
-            Self::Initial(_) => unimplemented!(),
-            Self::Basic(basic) => basic.respond(response),
-        }
+        self.state.respond(into_response(response));
     }
 
     fn has_completed(&mut self, response: &Self::Response) -> Result<bool, AuthenticError> {
-        match self {
-            Self::Initial(realm_credentials) => {
-                if response.status() == ::http::StatusCode::UNAUTHORIZED {
-                    let pw_client = ::http_auth::PasswordClient::try_from(
-                        response
-                            .headers()
-                            .get_all(::hyper::header::WWW_AUTHENTICATE),
-                    )
-                    .map_err(AuthenticError::Other)?;
-                    match pw_client {
-                        http_auth::PasswordClient::Basic(client) => {
-                            let realm = client.realm();
-                            let fetched = realm_credentials.fetch()?;
-                            match fetched.credential(realm) {
-                                Some(credential) => {
-                                    *self =
-                                        Self::Basic(BasicAuthentication::new(credential.clone()));
-                                    Ok(false)
-                                }
-                                None => Err(AuthenticError::UnknownRealm(realm.to_owned())),
-                            }
-                        }
-                        http_auth::PasswordClient::Digest(_) => todo!(),
-                        _ => todo!(),
-                    }
-                } else {
-                    Ok(true)
-                }
-            }
-            Self::Basic(basic) => basic.has_completed(response),
-        }
+        self.state.has_completed(
+            &self.credential,
+            response.status(),
+            response.headers(),
+            None,
+        )
     }
 }
 
@@ -289,9 +465,222 @@ where
         &self,
         builder: http::request::Builder,
     ) -> Result<http::request::Builder, AuthenticError> {
-        match self {
-            Self::Initial(_) => Ok(builder),
-            Self::Basic(basic) => basic.configure(builder),
+        let target = match (builder.method_ref(), builder.uri_ref()) {
+            (Some(method), Some(uri)) => {
+                Some(crate::protocol::RequestTarget::from_uri(method, uri))
+            }
+            _ => None,
+        };
+        match self.state.authorization(target)? {
+            Some(header_value) => Ok(builder.header(hyper::header::AUTHORIZATION, header_value)),
+            None => Ok(builder),
+        }
+    }
+}
+
+/// Authentication for OCI (Docker) registries.
+///
+/// Requires feature `oci`.
+///
+/// The registry responds to an unauthenticated request with a Bearer challenge naming a token
+/// server and the scope required. This protocol requests a token for the scope from the token
+/// server, and retries the original request using the token. Tokens are cached in the
+/// [`OciRegistryCredentials`] for later requests with the same scope.
+///
+/// Responses to the token server request must be passed through [`buffer_response`].
+#[cfg(feature = "oci")]
+pub struct OciRegistryAuthentication<Credential> {
+    credential: Arc<OciRegistryCredentials<Credential>>,
+    state: crate::protocol::OciRegistryState,
+}
+
+#[cfg(feature = "oci")]
+impl<Credential> OciRegistryAuthentication<Credential> {
+    pub fn new(credential: Arc<OciRegistryCredentials<Credential>>) -> Self {
+        Self {
+            credential,
+            state: crate::protocol::OciRegistryState::Initial,
+        }
+    }
+}
+
+#[cfg(feature = "oci")]
+impl<Credential> AuthenticationProtocol for OciRegistryAuthentication<Credential>
+where
+    Credential: AuthenticationCredential,
+    <Credential as AuthenticationCredential>::Fetch: FetchedUsernamePassword,
+{
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+    type Error = hyper::Error;
+
+    fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
+        into_step(self.state.step(&self.credential)?)
+    }
+
+    fn respond(&mut self, response: Result<Self::Response, Self::Error>) {
+        self.state
+            .respond(&self.credential, into_response(response));
+    }
+
+    fn has_completed(&mut self, response: &Self::Response) -> Result<bool, AuthenticError> {
+        self.state
+            .has_completed(&self.credential, response.status(), response.headers())
+    }
+}
+
+#[cfg(feature = "oci")]
+impl<Credential> AuthenticationProtocolConfigure<http::request::Builder>
+    for OciRegistryAuthentication<Credential>
+where
+    Credential: AuthenticationCredential,
+    <Credential as AuthenticationCredential>::Fetch: FetchedUsernamePassword,
+{
+    fn configure(
+        &self,
+        builder: http::request::Builder,
+    ) -> Result<http::request::Builder, AuthenticError> {
+        match self.state.authorization()? {
+            Some(header_value) => Ok(builder.header(hyper::header::AUTHORIZATION, header_value)),
+            None => Ok(builder),
         }
     }
 }
+
+/// Any of the protocols in this module, chosen at runtime.
+///
+/// Protocols using a token share the `Token` credential type, and protocols using a username and
+/// password share the `UsernamePassword` credential type. To combine different credential types,
+/// use a [`crate::credential::CredentialChain`] that maps them to a common type.
+///
+/// For protocols that are not known in advance, use a `Box<DynAuthentication>`.
+pub enum AnyAuthentication<
+    Token = crate::credential::TokenCredential,
+    UsernamePassword = crate::credential::UsernamePasswordCredential,
+> {
+    None(NoAuthentication),
+    Header(HeaderAuthentication<Token>),
+    Bearer(BearerAuthentication<Token>),
+    Basic(BasicAuthentication<UsernamePassword>),
+    #[cfg(feature = "loop")]
+    Http(HttpAuthentication<UsernamePassword>),
+    #[cfg(feature = "oci")]
+    OciRegistry(OciRegistryAuthentication<UsernamePassword>),
+}
+
+impl<Token, UsernamePassword> AuthenticationProtocol for AnyAuthentication<Token, UsernamePassword>
+where
+    Token: AuthenticationCredential,
+    <Token as AuthenticationCredential>::Fetch: FetchedToken,
+    UsernamePassword: AuthenticationCredential,
+    <UsernamePassword as AuthenticationCredential>::Fetch: FetchedUsernamePassword,
+{
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+    type Error = hyper::Error;
+
+    fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
+        any_authentication!(self, protocol => protocol.step())
+    }
+
+    fn respond(&mut self, response: Result<Self::Response, Self::Error>) {
+        any_authentication!(self, protocol => protocol.respond(response))
+    }
+
+    fn has_completed(&mut self, response: &Self::Response) -> Result<bool, AuthenticError> {
+        any_authentication!(self, protocol => protocol.has_completed(response))
+    }
+}
+
+impl<Token, UsernamePassword> AuthenticationProtocolConfigure<http::request::Builder>
+    for AnyAuthentication<Token, UsernamePassword>
+where
+    Token: AuthenticationCredential,
+    <Token as AuthenticationCredential>::Fetch: FetchedToken,
+    UsernamePassword: AuthenticationCredential,
+    <UsernamePassword as AuthenticationCredential>::Fetch: FetchedUsernamePassword,
+{
+    fn configure(
+        &self,
+        builder: http::request::Builder,
+    ) -> Result<http::request::Builder, AuthenticError> {
+        any_authentication!(self, protocol => protocol.configure(builder))
+    }
+}
+
+impl<Token, UsernamePassword> From<NoAuthentication>
+    for AnyAuthentication<Token, UsernamePassword>
+{
+    fn from(protocol: NoAuthentication) -> Self {
+        Self::None(protocol)
+    }
+}
+
+impl<Token, UsernamePassword> From<HeaderAuthentication<Token>>
+    for AnyAuthentication<Token, UsernamePassword>
+{
+    fn from(protocol: HeaderAuthentication<Token>) -> Self {
+        Self::Header(protocol)
+    }
+}
+
+impl<Token, UsernamePassword> From<BearerAuthentication<Token>>
+    for AnyAuthentication<Token, UsernamePassword>
+{
+    fn from(protocol: BearerAuthentication<Token>) -> Self {
+        Self::Bearer(protocol)
+    }
+}
+
+impl<Token, UsernamePassword> From<BasicAuthentication<UsernamePassword>>
+    for AnyAuthentication<Token, UsernamePassword>
+{
+    fn from(protocol: BasicAuthentication<UsernamePassword>) -> Self {
+        Self::Basic(protocol)
+    }
+}
+
+#[cfg(feature = "loop")]
+impl<Token, UsernamePassword> From<HttpAuthentication<UsernamePassword>>
+    for AnyAuthentication<Token, UsernamePassword>
+{
+    fn from(protocol: HttpAuthentication<UsernamePassword>) -> Self {
+        Self::Http(protocol)
+    }
+}
+
+#[cfg(feature = "oci")]
+impl<Token, UsernamePassword> From<OciRegistryAuthentication<UsernamePassword>>
+    for AnyAuthentication<Token, UsernamePassword>
+{
+    fn from(protocol: OciRegistryAuthentication<UsernamePassword>) -> Self {
+        Self::OciRegistry(protocol)
+    }
+}
+
+impl AuthenticationProtocol for Box<DynAuthentication> {
+    type Request = hyper::Request<hyper::Body>;
+    type Response = hyper::Response<hyper::Body>;
+    type Error = hyper::Error;
+
+    fn step(&self) -> Result<Option<AuthenticationStep<Self::Request>>, AuthenticError> {
+        self.dyn_step()
+    }
+
+    fn respond(&mut self, response: Result<Self::Response, Self::Error>) {
+        self.dyn_respond(response)
+    }
+
+    fn has_completed(&mut self, response: &Self::Response) -> Result<bool, AuthenticError> {
+        self.dyn_has_completed(response)
+    }
+}
+
+impl AuthenticationProtocolConfigure<hyper::Request<hyper::Body>> for Box<DynAuthentication> {
+    fn configure(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<hyper::Request<hyper::Body>, AuthenticError> {
+        self.dyn_configure(request)
+    }
+}
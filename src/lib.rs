@@ -32,6 +32,9 @@
 //!             AuthenticationStep::WaitFor(duration) => {
 //!                 std::thread::sleep(duration);
 //!             }
+//!             AuthenticationStep::WaitForRenewal(waiter) => {
+//!                 waiter.wait();
+//!             }
 //!         }
 //!     }
 //!
@@ -103,6 +106,9 @@
 //!         AuthenticationStep::WaitFor(duration) => {
 //!             std::thread::sleep(duration);
 //!         }
+//!         AuthenticationStep::WaitForRenewal(waiter) => {
+//!             waiter.wait();
+//!         }
 //!     }
 //! }
 //!
@@ -134,30 +140,112 @@
 //! The supported algorithm-credential pairs, and the features required to enable them, are:
 //! - `NoAuthentication`
 //! - `BasicAuthentication<UsernamePasswordCredential>`
+//! - `BasicAuthentication<DockerRegistryCredential>` (`features = ["docker"]`)
+//! - `BearerAuthentication<GitHubAppInstallationCredential>` (`features = ["github"]`)
 //! - `BearerAuthentication<JsonWebTokenCredential>` (`features = ["jwt", "step"]`)
+//! - `BearerAuthentication<OAuth2ClientCredential>` (`features = ["oauth2"]`, and `"jwt"` for client assertions)
 //! - `BearerAuthentication<TokenCredential>`
 //! - `HeaderAuthentication<JsonWebTokenCredential>` (`features = ["jwt", "step"]`)
 //! - `HeaderAuthentication<TokenCredential>`
-//! - `HttpAuthentication<HttpRealmCredentials<UsernamePasswordCredential>>` (`features = ["loop"]`)
+//! - `HttpAuthentication<HttpRealmCredentials<UsernamePasswordCredential>>`, using Basic or Digest (`features = ["loop"]`)
+//! - `OciRegistryAuthentication<UsernamePasswordCredential>` (`features = ["oci"]`)
+//! - `OciRegistryAuthentication<DockerRegistryCredential>` (`features = ["docker", "oci"]`)
+//!
+//! ## Asynchronous responses
+//!
+//! The `respond()` method is synchronous. When using `hyper` or async `reqwest`, responses to an
+//! `AuthenticationStep::Request` must have their body read into memory using the module's
+//! `buffer_response()` function before being passed to `respond()`.
+//!
+//! ## Runtime selection
+//!
+//! To choose a protocol at runtime, each HTTP library module provides an `AnyAuthentication` enum
+//! covering the built-in protocols, and a `DynAuthentication` trait object type for any protocol,
+//! including those defined outside this crate. Both can be used wherever a protocol is expected.
+//!
+//! ## Server-side verification
+//!
+//! The `verifier` feature provides the [`verifier`] module, to check the credentials sent by
+//! these protocols in requests received by a server. JSON Web Tokens can be verified using keys
+//! published by the issuer, cached by a `JwksCache` (`features = ["jwt", "step"]`).
+//!
+//! The `tower` feature adds a [`tower`] layer that rejects unauthenticated requests with the
+//! challenges expected by `HttpAuthentication`.
+//!
+//! ## Testing
+//!
+//! The `test-server` feature provides an in-process HTTP server in the [`test_server`] module, so
+//! that code using `authentic` can be tested without network access.
+//!
+//! ## Configuration
+//!
+//! With the `config` feature, protocols and credentials can be described by configuration
+//! deserialized with `serde`, so that changing protocol does not require a code change. See the
+//! [`config`] module.
+//!
+//! ## Background renewal
+//!
+//! Credentials implementing `RenewableCredential`, such as `JsonWebTokenCredential` and
+//! `GitHubAppInstallationCredential`, can be renewed ahead of time by a `CredentialRefresher`
+//! (`features = ["refresh"]`), running on a thread or, with the `tokio` feature
+//! (`features = ["refresh", "tokio"]`), a tokio task.
+//! Requests then find a valid token without waiting for it to be renewed.
 //!
 
 use std::time::Duration;
 
 use thiserror::Error;
 
+// Evaluate an expression for the protocol in any variant of an `AnyAuthentication`. Defined
+// before the modules so that each HTTP library module can use it.
+#[cfg(any(feature = "hyper", feature = "reqwest"))]
+macro_rules! any_authentication {
+    ($value:expr, $protocol:ident => $expression:expr) => {
+        match $value {
+            Self::None($protocol) => $expression,
+            Self::Header($protocol) => $expression,
+            Self::Bearer($protocol) => $expression,
+            Self::Basic($protocol) => $expression,
+            #[cfg(feature = "loop")]
+            Self::Http($protocol) => $expression,
+            #[cfg(feature = "oci")]
+            Self::OciRegistry($protocol) => $expression,
+        }
+    };
+}
+
+#[cfg(feature = "config")]
+pub mod config;
 pub mod credential;
 
 #[cfg(feature = "hyper")]
 pub mod hyper;
+mod protocol;
+
+#[cfg(feature = "loop")]
+pub use protocol::{HttpNegotiation, HttpScheme};
 #[cfg(feature = "reqwest")]
 pub mod reqwest;
+#[cfg(feature = "test-server")]
+pub mod test_server;
+#[cfg(feature = "tower")]
+pub mod tower;
+#[cfg(feature = "verifier")]
+pub mod verifier;
 
+/// Errors returned by authentication protocols and credentials.
+///
+/// Use [`AuthenticError::is_retryable`] and [`AuthenticError::is_permanent`] to decide how to
+/// handle an error. Errors that are neither may be retried with caution.
 #[derive(Error, Debug)]
 pub enum AuthenticError {
     #[cfg(feature = "hyper")]
     #[error("Hyper error")]
     Hyper(#[from] ::hyper::Error),
 
+    #[error("HTTP error")]
+    Http(#[from] ::http::Error),
+
     #[error("Invalid header value")]
     InvalidHeaderValue(#[from] ::http::header::InvalidHeaderValue),
 
@@ -176,16 +264,236 @@ pub enum AuthenticError {
     #[error("System time error")]
     SystemTime(#[from] ::std::time::SystemTimeError),
 
+    #[error("I/O error")]
+    Io(#[from] ::std::io::Error),
+
     #[error("No credentials found for realm {0:?}")]
     UnknownRealm(String),
 
+    /// The credential has no current value, or its source could not provide one.
+    #[error("Credential unavailable: {0}")]
+    CredentialUnavailable(String),
+
+    /// A `www-authenticate` header could not be parsed.
+    #[error("Invalid authentication challenge: {0}")]
+    ChallengeParse(String),
+
+    /// The server requires an authentication scheme that is not supported.
+    #[error("Unsupported authentication scheme: {0}")]
+    UnsupportedScheme(String),
+
+    /// A token endpoint returned an error status, with the OAuth2 `error` code, if any.
+    #[error("Token endpoint returned {status}")]
+    TokenEndpoint {
+        status: ::http::StatusCode,
+        oauth_error: Option<String>,
+    },
+
+    /// A token endpoint returned a successful response that could not be used.
+    #[error("Invalid token response: {0}")]
+    TokenResponse(String),
+
+    /// A [`credential::JwtSigner`] failed to sign a token, or returned an invalid signature.
+    #[cfg(feature = "jwt")]
+    #[error("Signing failed: {0}")]
+    Signing(String),
+
+    /// Authentication did not complete within the number of requests allowed by the protocol,
+    /// for example when a server keeps rejecting a Digest nonce as stale.
+    #[error("Authentication did not complete after {0} rounds")]
+    TooManyRounds(usize),
+
+    /// A protocol needs details of the request, such as its method and URI, that are not
+    /// available from the request or builder being configured.
+    #[error("Unsupported request: {0}")]
+    UnsupportedRequest(String),
+
+    /// A credential or protocol was configured with invalid values.
+    #[error("Invalid configuration: {0}")]
+    Configuration(String),
+
+    /// An async response was passed to `respond()` without reading the body first.
+    #[error("Response body must be read using buffer_response")]
+    ResponseNotBuffered,
+
+    /// A thread panicked while holding a lock on shared credential state.
+    #[error("Lock poisoned")]
+    LockPoisoned,
+
+    /// A copy of an error from another library that cannot be cloned, such as a `hyper` error,
+    /// returned again by each call to a protocol that has failed. The message is that of the
+    /// original error.
     #[error("{0}")]
     Other(String),
 }
 
+impl AuthenticError {
+    /// Create a [`AuthenticError::TokenEndpoint`] error from an error response.
+    #[cfg(any(
+        feature = "github",
+        feature = "oauth2",
+        all(feature = "oci", any(feature = "hyper", feature = "reqwest")),
+        all(feature = "jwt", feature = "step")
+    ))]
+    pub(crate) fn token_endpoint(status: ::http::StatusCode, body: &[u8]) -> Self {
+        let oauth_error = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|body| body.get("error")?.as_str().map(str::to_owned));
+        Self::TokenEndpoint {
+            status,
+            oauth_error,
+        }
+    }
+
+    /// Create a copy of the error, for state that returns the same error from each call.
+    ///
+    /// Errors from other libraries that cannot be copied are replaced by an
+    /// [`AuthenticError::Other`] error with the same message. Errors from `std` and
+    /// `jsonwebtoken` keep their kind.
+    #[cfg(all(feature = "loop", any(feature = "hyper", feature = "reqwest")))]
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            #[cfg(feature = "jwt")]
+            Self::JsonWebToken(err) => Self::JsonWebToken(err.kind().clone().into()),
+            Self::SystemTime(err) => Self::SystemTime(err.clone()),
+            Self::Io(err) => Self::Io(std::io::Error::new(err.kind(), err.to_string())),
+            Self::UnknownRealm(realm) => Self::UnknownRealm(realm.clone()),
+            Self::CredentialUnavailable(message) => Self::CredentialUnavailable(message.clone()),
+            Self::ChallengeParse(message) => Self::ChallengeParse(message.clone()),
+            Self::UnsupportedScheme(message) => Self::UnsupportedScheme(message.clone()),
+            Self::TokenEndpoint {
+                status,
+                oauth_error,
+            } => Self::TokenEndpoint {
+                status: *status,
+                oauth_error: oauth_error.clone(),
+            },
+            Self::TokenResponse(message) => Self::TokenResponse(message.clone()),
+            #[cfg(feature = "jwt")]
+            Self::Signing(message) => Self::Signing(message.clone()),
+            Self::UnsupportedRequest(message) => Self::UnsupportedRequest(message.clone()),
+            Self::Configuration(message) => Self::Configuration(message.clone()),
+            Self::TooManyRounds(rounds) => Self::TooManyRounds(*rounds),
+            Self::ResponseNotBuffered => Self::ResponseNotBuffered,
+            Self::LockPoisoned => Self::LockPoisoned,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Returns `true` if the same operation may succeed if it is tried again later.
+    ///
+    /// This includes network failures, server errors, rate limiting, and credentials that are
+    /// not yet available.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            #[cfg(feature = "hyper")]
+            Self::Hyper(err) => {
+                err.is_connect()
+                    || err.is_timeout()
+                    || err.is_closed()
+                    || err.is_incomplete_message()
+                    || err.is_canceled()
+            }
+            #[cfg(feature = "reqwest")]
+            Self::Reqwest(err) => {
+                err.is_connect()
+                    || err.is_timeout()
+                    || matches!(err.status(), Some(status) if is_retryable_status(status))
+            }
+            Self::SystemTime(_) | Self::CredentialUnavailable(_) => true,
+            Self::Io(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::WouldBlock
+            ),
+            Self::TokenEndpoint { status, .. } => is_retryable_status(*status),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the same operation will fail if it is tried again, without a change
+    /// to the configuration, credentials or server.
+    ///
+    /// This includes rejected credentials, unsupported protocols, and invalid configuration.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            #[cfg(feature = "hyper")]
+            Self::Hyper(err) => err.is_user() || err.is_parse(),
+            #[cfg(feature = "reqwest")]
+            Self::Reqwest(err) => {
+                err.is_builder()
+                    || err.is_redirect()
+                    || matches!(err.status(), Some(status)
+                        if status.is_client_error() && !is_retryable_status(status))
+            }
+            #[cfg(feature = "reqwest")]
+            Self::ReqwestInvalidHeaderName(_) => true,
+            #[cfg(feature = "jwt")]
+            Self::JsonWebToken(_) => true,
+            Self::Http(_)
+            | Self::InvalidHeaderValue(_)
+            | Self::UnknownRealm(_)
+            | Self::ChallengeParse(_)
+            | Self::UnsupportedScheme(_)
+            | Self::TokenResponse(_)
+            | Self::TooManyRounds(_)
+            | Self::UnsupportedRequest(_)
+            | Self::Configuration(_)
+            | Self::ResponseNotBuffered
+            | Self::LockPoisoned => true,
+            Self::Io(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::NotFound
+                    | std::io::ErrorKind::PermissionDenied
+                    | std::io::ErrorKind::InvalidData
+            ),
+            Self::TokenEndpoint { status, .. } => {
+                status.is_client_error() && !is_retryable_status(*status)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Status codes indicating that a request may succeed later.
+fn is_retryable_status(status: ::http::StatusCode) -> bool {
+    status.is_server_error()
+        || status == ::http::StatusCode::TOO_MANY_REQUESTS
+        || status == ::http::StatusCode::REQUEST_TIMEOUT
+}
+
+/// Poll a future once, returning its output if it is immediately ready.
+///
+/// Used to read response bodies inside the synchronous `respond` method. The body must have been
+/// buffered by the caller for this to succeed.
+#[cfg(any(feature = "hyper", feature = "reqwest-async"))]
+pub(crate) fn now_or_never<F: std::future::Future>(future: F) -> Option<F::Output> {
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    // Safety: the vtable functions do nothing, so ignore the null data pointer.
+    let waker = unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    match future.as_mut().poll(&mut context) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
+
 pub enum AuthenticationStep<Request> {
     Request(Request),
     WaitFor(Duration),
+    /// Another caller is renewing the credential. Wait for the renewal to complete, using
+    /// `waiter.wait()` in blocking code, or by awaiting `waiter` with a timeout of
+    /// `waiter.timeout()` in async code.
+    WaitForRenewal(credential::RenewalWaiter),
 }
 
 pub trait AuthenticationProtocol {
@@ -197,11 +505,13 @@ pub trait AuthenticationProtocol {
         Ok(None)
     }
 
+    /// Called with the response to a request returned from `step`.
+    ///
+    /// Protocols that never return a request from `step` ignore the response.
     fn respond(
         &mut self,
         #[allow(unused_variables)] response: Result<Self::Response, Self::Error>,
     ) {
-        unimplemented!();
     }
 
     fn has_completed(
@@ -218,6 +528,24 @@ pub trait AuthenticationProtocolConfigure<Builder> {
     }
 }
 
+/// Object-safe form of [`AuthenticationProtocol`] and [`AuthenticationProtocolConfigure`].
+///
+/// Allows a protocol chosen at runtime to be stored as a trait object. Each HTTP library module
+/// implements this for its protocols, and defines a `DynAuthentication` trait object type.
+pub trait DynAuthenticationProtocol<Request, Response, Error> {
+    /// Same as [`AuthenticationProtocol::step`].
+    fn dyn_step(&self) -> Result<Option<AuthenticationStep<Request>>, AuthenticError>;
+
+    /// Same as [`AuthenticationProtocol::respond`].
+    fn dyn_respond(&mut self, response: Result<Response, Error>);
+
+    /// Add authentication to a request.
+    fn dyn_configure(&self, request: Request) -> Result<Request, AuthenticError>;
+
+    /// Same as [`AuthenticationProtocol::has_completed`].
+    fn dyn_has_completed(&mut self, response: &Response) -> Result<bool, AuthenticError>;
+}
+
 // Allow request builder authentication to use fluent model.
 pub trait WithAuthentication
 where
@@ -232,6 +560,8 @@ where
 }
 #[cfg(feature = "hyper")]
 impl WithAuthentication for http::request::Builder {}
+#[cfg(feature = "hyper")]
+impl WithAuthentication for ::hyper::Request<::hyper::Body> {}
 
 #[cfg(feature = "reqwest-async")]
 impl WithAuthentication for ::reqwest::RequestBuilder {}
@@ -0,0 +1,18 @@
+//! State machines shared by the protocols for each HTTP library.
+//!
+//! These work with `http` requests and responses containing a fully-read body. Each HTTP library
+//! module converts its own types before calling them.
+
+#[cfg(all(feature = "loop", any(feature = "hyper", feature = "reqwest")))]
+mod http;
+#[cfg(feature = "loop")]
+mod negotiation;
+#[cfg(all(feature = "oci", any(feature = "hyper", feature = "reqwest")))]
+mod oci;
+
+#[cfg(all(feature = "loop", any(feature = "hyper", feature = "reqwest")))]
+pub(crate) use self::http::*;
+#[cfg(feature = "loop")]
+pub use negotiation::{HttpNegotiation, HttpScheme};
+#[cfg(all(feature = "oci", any(feature = "hyper", feature = "reqwest")))]
+pub(crate) use oci::*;
@@ -0,0 +1,345 @@
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+use http::HeaderValue;
+use http_auth::{BasicClient, ChallengeRef, DigestClient, PasswordParams};
+
+use super::{HttpNegotiation, HttpScheme};
+use crate::credential::{AuthenticationCredential, FetchedUsernamePassword, HttpRealmCredentials};
+use crate::{AuthenticError, AuthenticationStep};
+
+/// The request being authenticated, as required to respond to a Digest challenge.
+pub(crate) struct RequestTarget<'a> {
+    pub(crate) method: &'a str,
+    /// Path and query of the request URI.
+    pub(crate) path: Cow<'a, str>,
+    /// Scheme, host and port of the request URI, if known.
+    pub(crate) origin: Option<String>,
+}
+
+impl<'a> RequestTarget<'a> {
+    #[cfg(feature = "hyper")]
+    pub(crate) fn from_uri(method: &'a http::Method, uri: &'a http::Uri) -> Self {
+        Self {
+            method: method.as_str(),
+            path: Cow::Borrowed(uri.path_and_query().map_or("/", |path| path.as_str())),
+            origin: match (uri.scheme_str(), uri.authority()) {
+                (Some(scheme), Some(authority)) => Some(format!("{}://{}", scheme, authority)),
+                _ => None,
+            },
+        }
+    }
+
+    #[cfg(feature = "reqwest")]
+    pub(crate) fn from_url(method: &'a http::Method, url: &'a reqwest::Url) -> Self {
+        let path = match url.query() {
+            Some(query) => Cow::Owned(format!("{}?{}", url.path(), query)),
+            None => Cow::Borrowed(url.path()),
+        };
+        Self {
+            method: method.as_str(),
+            path,
+            origin: Some(url.origin().ascii_serialization()),
+        }
+    }
+}
+
+/// A challenge offered by the server that authentic can respond to.
+enum Candidate {
+    Basic(BasicClient),
+    Digest(DigestClient),
+}
+
+impl Candidate {
+    fn parse(challenge: &ChallengeRef<'_>) -> Option<Result<Self, String>> {
+        if challenge.scheme.eq_ignore_ascii_case("Basic") {
+            Some(BasicClient::try_from(challenge).map(Self::Basic))
+        } else if challenge.scheme.eq_ignore_ascii_case("Digest") {
+            Some(DigestClient::try_from(challenge).map(Self::Digest))
+        } else {
+            None
+        }
+    }
+
+    fn scheme(&self) -> HttpScheme {
+        match self {
+            Self::Basic(_) => HttpScheme::Basic,
+            Self::Digest(_) => HttpScheme::Digest,
+        }
+    }
+
+    fn realm(&self) -> &str {
+        match self {
+            Self::Basic(client) => client.realm(),
+            Self::Digest(client) => client.realm(),
+        }
+    }
+
+    /// Strength of the challenge within its scheme.
+    fn strength(&self) -> u8 {
+        match self {
+            Self::Basic(_) => 0,
+            Self::Digest(client) => match client.algorithm() {
+                http_auth::digest::Algorithm::Sha512Trunc256 => 2,
+                http_auth::digest::Algorithm::Sha256 => 1,
+                _ => 0,
+            },
+        }
+    }
+}
+
+/// Requests made by Digest authentication: the unauthenticated request, the response to the
+/// challenge, and one retry with a new nonce after the server reports the nonce is stale.
+const DIGEST_MAX_ROUNDS: usize = 3;
+
+enum Phase<Credential> {
+    /// No challenge received yet.
+    Initial,
+    Basic(Arc<Credential>),
+    Digest(Arc<Credential>, Mutex<DigestClient>),
+    /// The challenge could not be answered. Contains the error, which is returned by each
+    /// later call.
+    Failed(AuthenticError),
+}
+
+pub(crate) struct HttpChallengeState<Credential> {
+    negotiation: HttpNegotiation,
+    // Origin of the last request configured before the challenge, if known.
+    origin: Mutex<Option<String>>,
+    phase: Phase<Credential>,
+}
+
+impl<Credential> HttpChallengeState<Credential>
+where
+    Credential: AuthenticationCredential,
+    <Credential as AuthenticationCredential>::Fetch: FetchedUsernamePassword,
+{
+    pub(crate) fn new(negotiation: HttpNegotiation) -> Self {
+        Self {
+            negotiation,
+            origin: Mutex::new(None),
+            phase: Phase::Initial,
+        }
+    }
+
+    pub(crate) fn step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        match &self.phase {
+            Phase::Initial => Ok(None),
+            Phase::Basic(credential) | Phase::Digest(credential, _) => credential.auth_step(),
+            Phase::Failed(err) => Err(err.duplicate()),
+        }
+    }
+
+    pub(crate) fn respond(&mut self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        match &self.phase {
+            Phase::Basic(credential) | Phase::Digest(credential, _) => {
+                credential.auth_respond(response)
+            }
+            // No step request has been made, so there is no response to handle.
+            Phase::Initial | Phase::Failed(_) => {}
+        }
+    }
+
+    /// Check a response, answering a challenge if the request was unauthorized.
+    ///
+    /// `origin` is the origin of the request, if known. Otherwise, the origin recorded by
+    /// `authorization` is used.
+    pub(crate) fn has_completed(
+        &mut self,
+        realm_credentials: &HttpRealmCredentials<Credential>,
+        status: http::StatusCode,
+        headers: &http::HeaderMap,
+        origin: Option<String>,
+    ) -> Result<bool, AuthenticError> {
+        if status != http::StatusCode::UNAUTHORIZED {
+            return Ok(true);
+        }
+        match &self.phase {
+            Phase::Initial => {
+                let origin = origin.or_else(|| {
+                    self.origin
+                        .lock()
+                        .unwrap_or_else(|err| err.into_inner())
+                        .clone()
+                });
+                match self.negotiate(realm_credentials, headers, origin.as_deref()) {
+                    Ok(phase) => {
+                        self.phase = phase;
+                        Ok(false)
+                    }
+                    Err(err) => {
+                        let result = Err(err.duplicate());
+                        self.phase = Phase::Failed(err);
+                        result
+                    }
+                }
+            }
+            Phase::Digest(_, client) => {
+                // A stale nonce is retried once with the new nonce.
+                let mut client = client.lock().map_err(|_| AuthenticError::LockPoisoned)?;
+                let stale = challenges(headers)?
+                    .into_iter()
+                    .filter_map(|candidate| match candidate {
+                        Ok(Candidate::Digest(digest)) => Some(digest),
+                        _ => None,
+                    })
+                    .find(|digest| digest.stale() && digest.realm() == client.realm());
+                match stale {
+                    Some(_) if client.stale() => {
+                        Err(AuthenticError::TooManyRounds(DIGEST_MAX_ROUNDS))
+                    }
+                    Some(digest) => {
+                        *client = digest;
+                        Ok(false)
+                    }
+                    None => Ok(true),
+                }
+            }
+            Phase::Basic(_) => Ok(true),
+            Phase::Failed(err) => Err(err.duplicate()),
+        }
+    }
+
+    /// Choose the strongest allowed challenge that has a credential for its realm.
+    fn negotiate(
+        &self,
+        realm_credentials: &HttpRealmCredentials<Credential>,
+        headers: &http::HeaderMap,
+        origin: Option<&str>,
+    ) -> Result<Phase<Credential>, AuthenticError> {
+        let mut parse_error = None;
+        let mut candidates = Vec::new();
+        for candidate in challenges(headers)? {
+            match candidate {
+                Ok(candidate) => candidates.push(candidate),
+                Err(err) => {
+                    parse_error.get_or_insert(err);
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return Err(match parse_error {
+                Some(err) => AuthenticError::ChallengeParse(err),
+                None => AuthenticError::UnsupportedScheme(
+                    "No Basic or Digest challenge offered".to_owned(),
+                ),
+            });
+        }
+        let secure = matches!(origin, Some(origin) if origin.starts_with("https://"));
+        let minimum = if secure {
+            self.negotiation.minimum
+        } else {
+            self.negotiation
+                .minimum
+                .max(self.negotiation.insecure_minimum)
+        };
+        // Stronger challenges of the same scheme first.
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.strength()));
+        let fetched = realm_credentials.fetch()?;
+        let mut unknown_realm = None;
+        for scheme in &self.negotiation.preference {
+            if *scheme < minimum {
+                continue;
+            }
+            for index in 0..candidates.len() {
+                let candidate = &candidates[index];
+                if candidate.scheme() != *scheme {
+                    continue;
+                }
+                match fetched.resolve(origin, candidate.realm(), *scheme) {
+                    Some(credential) => {
+                        return Ok(match candidates.swap_remove(index) {
+                            Candidate::Basic(_) => Phase::Basic(credential),
+                            Candidate::Digest(client) => {
+                                Phase::Digest(credential, Mutex::new(client))
+                            }
+                        });
+                    }
+                    None => {
+                        unknown_realm.get_or_insert_with(|| candidate.realm().to_owned());
+                    }
+                }
+            }
+        }
+        match unknown_realm {
+            Some(realm) => Err(AuthenticError::UnknownRealm(realm)),
+            None => {
+                let offered = candidates
+                    .iter()
+                    .map(|candidate| candidate.scheme().name())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Err(AuthenticError::UnsupportedScheme(format!(
+                    "{} not allowed for this request",
+                    offered
+                )))
+            }
+        }
+    }
+
+    /// Whether `authorization` needs the request method and URI.
+    #[cfg(feature = "reqwest")]
+    pub(crate) fn requires_target(&self) -> bool {
+        matches!(self.phase, Phase::Digest(..))
+    }
+
+    /// Get the `Authorization` header value to add to a request, if any.
+    pub(crate) fn authorization(
+        &self,
+        target: Option<RequestTarget<'_>>,
+    ) -> Result<Option<HeaderValue>, AuthenticError> {
+        let value = match &self.phase {
+            Phase::Initial => {
+                *self
+                    .origin
+                    .lock()
+                    .map_err(|_| AuthenticError::LockPoisoned)? =
+                    target.and_then(|target| target.origin);
+                return Ok(None);
+            }
+            Phase::Basic(credential) => {
+                let fetched = credential.fetch()?;
+                http_auth::basic::encode_credentials(fetched.username(), fetched.password())
+            }
+            Phase::Digest(credential, client) => {
+                let target = target.ok_or_else(|| {
+                    AuthenticError::UnsupportedRequest(
+                        "Digest authentication requires the request method and URI".to_owned(),
+                    )
+                })?;
+                let fetched = credential.fetch()?;
+                client
+                    .lock()
+                    .map_err(|_| AuthenticError::LockPoisoned)?
+                    .respond(&PasswordParams {
+                        username: fetched.username(),
+                        password: fetched.password(),
+                        uri: &target.path,
+                        method: target.method,
+                        body: None,
+                    })
+                    .map_err(AuthenticError::UnsupportedScheme)?
+            }
+            Phase::Failed(err) => return Err(err.duplicate()),
+        };
+        let mut header_value = HeaderValue::try_from(value)?;
+        header_value.set_sensitive(true);
+        Ok(Some(header_value))
+    }
+}
+
+/// Parse the challenges that authentic supports from a set of `www-authenticate` headers.
+fn challenges(headers: &http::HeaderMap) -> Result<Vec<Result<Candidate, String>>, AuthenticError> {
+    let mut candidates = Vec::new();
+    for value in headers.get_all(http::header::WWW_AUTHENTICATE) {
+        let value = value
+            .to_str()
+            .map_err(|err| AuthenticError::ChallengeParse(err.to_string()))?;
+        let challenges = http_auth::parse_challenges(value)
+            .map_err(|err| AuthenticError::ChallengeParse(err.to_string()))?;
+        candidates.extend(challenges.iter().filter_map(Candidate::parse));
+    }
+    Ok(candidates)
+}
@@ -0,0 +1,75 @@
+/// HTTP authentication schemes supported by `HttpAuthentication`, ordered by strength.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "config",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum HttpScheme {
+    /// Basic authentication, sending the password in every request.
+    Basic,
+    /// Digest authentication, sending a hash of the password and a server nonce.
+    Digest,
+}
+
+impl HttpScheme {
+    #[cfg(any(feature = "hyper", feature = "reqwest"))]
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Self::Basic => "Basic",
+            Self::Digest => "Digest",
+        }
+    }
+}
+
+/// Selection of a scheme when a server offers several `www-authenticate` challenges.
+///
+/// By default, the strongest offered scheme with a credential for its realm is used. Schemes
+/// can be excluded, or required to meet a minimum strength, which may be higher for requests
+/// over plain `http`.
+#[derive(Clone, Debug)]
+pub struct HttpNegotiation {
+    pub(super) preference: Vec<HttpScheme>,
+    pub(super) minimum: HttpScheme,
+    pub(super) insecure_minimum: HttpScheme,
+}
+
+impl HttpNegotiation {
+    pub fn new() -> Self {
+        Self {
+            preference: vec![HttpScheme::Digest, HttpScheme::Basic],
+            minimum: HttpScheme::Basic,
+            insecure_minimum: HttpScheme::Basic,
+        }
+    }
+
+    /// Set the schemes that may be used, most preferred first.
+    #[must_use]
+    pub fn with_preference(mut self, schemes: impl IntoIterator<Item = HttpScheme>) -> Self {
+        self.preference = schemes.into_iter().collect();
+        self
+    }
+
+    /// Refuse schemes weaker than `minimum`.
+    #[must_use]
+    pub fn with_minimum(mut self, minimum: HttpScheme) -> Self {
+        self.minimum = minimum;
+        self
+    }
+
+    /// Refuse schemes weaker than `minimum` for requests that are not known to use `https`.
+    ///
+    /// For example, `with_insecure_minimum(HttpScheme::Digest)` refuses to send Basic
+    /// credentials over plain `http`.
+    #[must_use]
+    pub fn with_insecure_minimum(mut self, minimum: HttpScheme) -> Self {
+        self.insecure_minimum = minimum;
+        self
+    }
+}
+
+impl Default for HttpNegotiation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
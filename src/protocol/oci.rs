@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use http::HeaderValue;
+
+use crate::credential::{
+    AuthenticationCredential, FetchedOciToken, FetchedUsernamePassword, OciChallenge,
+    OciRegistryCredentials,
+};
+use crate::{AuthenticError, AuthenticationStep};
+
+pub(crate) enum OciRegistryState {
+    /// No challenge received yet.
+    Initial,
+    /// Challenge received, and a token must be requested from the token server.
+    ///
+    /// The flag records whether the last request returned from `step` was for the login
+    /// credential, rather than for the token server.
+    Challenged(OciChallenge, AtomicBool),
+    /// Token available for the challenge.
+    Authorized {
+        challenge: OciChallenge,
+        token: Arc<FetchedOciToken>,
+        cached: bool,
+    },
+    /// Token request failed.
+    ///
+    /// The error is returned by each later call to `step`.
+    Failed(AuthenticError),
+}
+
+impl OciRegistryState {
+    pub(crate) fn step<Credential>(
+        &self,
+        credential: &OciRegistryCredentials<Credential>,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError>
+    where
+        Credential: AuthenticationCredential,
+        <Credential as AuthenticationCredential>::Fetch: FetchedUsernamePassword,
+    {
+        match self {
+            Self::Initial | Self::Authorized { .. } => Ok(None),
+            Self::Challenged(challenge, login_request) => {
+                let step = credential.auth_step()?;
+                login_request.store(
+                    matches!(step, Some(AuthenticationStep::Request(_))),
+                    Ordering::Relaxed,
+                );
+                if step.is_some() {
+                    return Ok(step);
+                }
+                let login = credential.fetch()?;
+                let request = challenge.token_request(login.as_ref())?;
+                Ok(Some(AuthenticationStep::Request(request)))
+            }
+            Self::Failed(err) => Err(err.duplicate()),
+        }
+    }
+
+    pub(crate) fn respond<Credential>(
+        &mut self,
+        credential: &OciRegistryCredentials<Credential>,
+        response: Result<http::Response<Vec<u8>>, AuthenticError>,
+    ) where
+        Credential: AuthenticationCredential,
+    {
+        if let Self::Challenged(challenge, login_request) = self {
+            if login_request.swap(false, Ordering::Relaxed) {
+                credential.auth_respond(response);
+                return;
+            }
+            *self = match response.and_then(|response| credential.store_token(challenge, response))
+            {
+                Ok(token) => Self::Authorized {
+                    challenge: challenge.clone(),
+                    token,
+                    cached: false,
+                },
+                Err(err) => Self::Failed(err),
+            };
+        }
+    }
+
+    pub(crate) fn has_completed<Credential>(
+        &mut self,
+        credential: &OciRegistryCredentials<Credential>,
+        status: http::StatusCode,
+        headers: &http::HeaderMap,
+    ) -> Result<bool, AuthenticError> {
+        if status != http::StatusCode::UNAUTHORIZED {
+            return Ok(true);
+        }
+        match self {
+            Self::Initial => match OciChallenge::from_headers(headers)? {
+                Some(challenge) => {
+                    *self = match credential.token(&challenge) {
+                        Some(token) => Self::Authorized {
+                            challenge,
+                            token,
+                            cached: true,
+                        },
+                        None => Self::Challenged(challenge, AtomicBool::new(false)),
+                    };
+                    Ok(false)
+                }
+                None => Ok(true),
+            },
+            Self::Authorized {
+                challenge,
+                cached: true,
+                ..
+            } => {
+                // The registry rejected a cached token, possibly revoked before it expired.
+                // Request a new token once.
+                credential.remove_token(challenge);
+                *self = Self::Challenged(challenge.clone(), AtomicBool::new(false));
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    /// Get the `Authorization` header value to add to a request, if any.
+    pub(crate) fn authorization(&self) -> Result<Option<HeaderValue>, AuthenticError> {
+        match self {
+            Self::Authorized { token, .. } => {
+                let mut header_value = HeaderValue::try_from(format!("Bearer {}", token.token()))?;
+                header_value.set_sensitive(true);
+                Ok(Some(header_value))
+            }
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! In-process HTTP server for testing authentication without network access.
+//!
+//! The server runs on its own thread, bound to a local port, so it can be used from both
+//! synchronous and asynchronous tests. It is stopped when the [`TestServer`] is dropped.
+//!
+//! ```no_run
+//! use authentic::test_server::TestServer;
+//!
+//! let server = TestServer::builder()
+//!     .with_basic("Fake Realm", "username", "password")
+//!     .start()?;
+//! // Requests to any path except the token endpoint require authentication.
+//! let url = server.url("/basic-auth");
+//! # Ok::<(), authentic::AuthenticError>(())
+//! ```
+//!
+//! Unauthenticated requests receive a `401 Unauthorized` response with a `www-authenticate`
+//! challenge for each configured realm, in the order they were added. Authenticated requests
+//! receive a `200 OK` response whose body is the name of the scheme used, such as `Basic`.
+//!
+//! If OAuth2 clients are configured, `POST` requests to [`TestServer::token_url`] implement the
+//! client credentials grant, issuing access tokens that are accepted as bearer tokens.
+
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+
+use ::hyper::service::{make_service_fn, service_fn};
+use ::hyper::{Body, Method, Request, Response, Server, StatusCode};
+use md5::{Digest, Md5};
+
+use crate::AuthenticError;
+
+const TOKEN_PATH: &str = "/token";
+
+enum Realm {
+    Basic {
+        realm: String,
+        authorization: String,
+    },
+    Digest {
+        realm: String,
+        username: String,
+        password: String,
+    },
+}
+
+struct OAuth2Client {
+    client_id: String,
+    client_secret: String,
+    access_token: String,
+}
+
+struct Config {
+    realms: Vec<Realm>,
+    tokens: Vec<String>,
+    #[cfg(feature = "jwt")]
+    jwt: Option<(jsonwebtoken::DecodingKey, jsonwebtoken::Validation)>,
+    clients: Vec<OAuth2Client>,
+    nonce: String,
+}
+
+/// Builder for a [`TestServer`].
+pub struct TestServerBuilder {
+    config: Config,
+}
+
+impl TestServerBuilder {
+    /// Accept Basic credentials `username` and `password` for `realm`.
+    #[must_use]
+    pub fn with_basic(
+        mut self,
+        realm: impl Into<String>,
+        username: impl AsRef<str>,
+        password: impl AsRef<str>,
+    ) -> Self {
+        let authorization =
+            http_auth::basic::encode_credentials(username.as_ref(), password.as_ref());
+        self.config.realms.push(Realm::Basic {
+            realm: realm.into(),
+            authorization,
+        });
+        self
+    }
+
+    /// Accept Digest credentials `username` and `password` for `realm`, using MD5 with
+    /// `qop=auth`.
+    #[must_use]
+    pub fn with_digest(
+        mut self,
+        realm: impl Into<String>,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.config.realms.push(Realm::Digest {
+            realm: realm.into(),
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Accept `token` as a bearer token.
+    #[must_use]
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.config.tokens.push(token.into());
+        self
+    }
+
+    /// Accept bearer tokens that are JSON Web Tokens verified by `key` and `validation`.
+    #[cfg(feature = "jwt")]
+    #[must_use]
+    pub fn with_jwt(
+        mut self,
+        key: jsonwebtoken::DecodingKey,
+        validation: jsonwebtoken::Validation,
+    ) -> Self {
+        self.config.jwt = Some((key, validation));
+        self
+    }
+
+    /// Issue `access_token` from the token endpoint to the OAuth2 client `client_id`
+    /// authenticating with `client_secret`.
+    ///
+    /// The access token is then accepted as a bearer token.
+    #[must_use]
+    pub fn with_oauth2_client(
+        mut self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        access_token: impl Into<String>,
+    ) -> Self {
+        self.config.clients.push(OAuth2Client {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            access_token: access_token.into(),
+        });
+        self
+    }
+
+    /// Start the server on a local port.
+    pub fn start(mut self) -> Result<TestServer, AuthenticError> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        self.config.nonce = md5_hex(&format!("{}:{:?}", addr, std::time::SystemTime::now()));
+        let config = Arc::new(self.config);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let (shutdown, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = {
+            // Entering the runtime allows the listener to be registered with it.
+            let _guard = runtime.enter();
+            Server::from_tcp(listener)?
+        };
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let config = config.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request| {
+                            let config = config.clone();
+                            async move { Ok::<_, Infallible>(handle(&config, request).await) }
+                        }))
+                    }
+                });
+                let _ = server
+                    .serve(make_service)
+                    .with_graceful_shutdown(async {
+                        let _ = shutdown_rx.await;
+                    })
+                    .await;
+            });
+        });
+
+        Ok(TestServer {
+            addr,
+            shutdown: Some(shutdown),
+        })
+    }
+}
+
+/// A running test server.
+pub struct TestServer {
+    addr: SocketAddr,
+    shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl TestServer {
+    pub fn builder() -> TestServerBuilder {
+        TestServerBuilder {
+            config: Config {
+                realms: Vec::new(),
+                tokens: Vec::new(),
+                #[cfg(feature = "jwt")]
+                jwt: None,
+                clients: Vec::new(),
+                nonce: String::new(),
+            },
+        }
+    }
+
+    /// The local address of the server.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL of `path` on the server, such as `http://127.0.0.1:8080/path`.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// The URL of the OAuth2 token endpoint.
+    pub fn token_url(&self) -> String {
+        self.url(TOKEN_PATH)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        // The server thread stops once open connections are closed. It is not joined, as a
+        // client in the dropping thread may still hold a connection.
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle(config: &Config, request: Request<Body>) -> Response<Body> {
+    if request.uri().path() == TOKEN_PATH && !config.clients.is_empty() {
+        return token_endpoint(config, request).await;
+    }
+    let scheme = request
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|authorization| authenticated(config, &request, authorization));
+    match scheme {
+        Some(scheme) => Response::new(Body::from(scheme)),
+        None => {
+            let mut builder = Response::builder().status(StatusCode::UNAUTHORIZED);
+            for realm in &config.realms {
+                let challenge = match realm {
+                    Realm::Basic { realm, .. } => format!("Basic realm=\"{}\"", realm),
+                    Realm::Digest { realm, .. } => format!(
+                        "Digest realm=\"{}\", qop=\"auth\", algorithm=MD5, nonce=\"{}\"",
+                        realm, config.nonce
+                    ),
+                };
+                builder = builder.header(http::header::WWW_AUTHENTICATE, challenge);
+            }
+            if has_bearer(config) {
+                builder = builder.header(http::header::WWW_AUTHENTICATE, "Bearer");
+            }
+            builder.body(Body::empty()).unwrap()
+        }
+    }
+}
+
+fn has_bearer(config: &Config) -> bool {
+    #[cfg(feature = "jwt")]
+    if config.jwt.is_some() {
+        return true;
+    }
+    !config.tokens.is_empty() || !config.clients.is_empty()
+}
+
+/// Check an `Authorization` header, returning the scheme if the credentials are accepted.
+fn authenticated(
+    config: &Config,
+    request: &Request<Body>,
+    authorization: &str,
+) -> Option<&'static str> {
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        return if bearer_accepted(config, token) {
+            Some("Bearer")
+        } else {
+            None
+        };
+    }
+    if authorization.starts_with("Basic ") {
+        let accepted = config.realms.iter().any(|realm| {
+            matches!(realm, Realm::Basic { authorization: expected, .. } if expected == authorization)
+        });
+        return if accepted { Some("Basic") } else { None };
+    }
+    let challenges = http_auth::parse_challenges(authorization).ok()?;
+    let digest = challenges
+        .first()
+        .filter(|c| c.scheme.eq_ignore_ascii_case("Digest"))?;
+    let param = |name: &str| {
+        digest
+            .params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_unescaped())
+    };
+    if param("nonce")? != config.nonce {
+        return None;
+    }
+    let realm = param("realm")?;
+    let username = param("username")?;
+    let (_, password) = config.realms.iter().find_map(|r| match r {
+        Realm::Digest {
+            realm: name,
+            username: expected,
+            password,
+        } if *name == realm && *expected == username => Some((name, password)),
+        _ => None,
+    })?;
+    // The digest only covers the `uri` parameter, so it must name the requested resource.
+    let uri = param("uri")?;
+    if Some(uri.as_str()) != request.uri().path_and_query().map(|p| p.as_str()) {
+        return None;
+    }
+    let ha1 = md5_hex(&format!("{}:{}:{}", username, realm, password));
+    let ha2 = md5_hex(&format!("{}:{}", request.method(), uri));
+    let expected = md5_hex(&format!(
+        "{}:{}:{}:{}:auth:{}",
+        ha1,
+        config.nonce,
+        param("nc")?,
+        param("cnonce")?,
+        ha2
+    ));
+    if param("response")? == expected {
+        Some("Digest")
+    } else {
+        None
+    }
+}
+
+fn bearer_accepted(config: &Config, token: &str) -> bool {
+    if config.tokens.iter().any(|expected| expected == token)
+        || config
+            .clients
+            .iter()
+            .any(|client| client.access_token == token)
+    {
+        return true;
+    }
+    #[cfg(feature = "jwt")]
+    if let Some((key, validation)) = &config.jwt {
+        return jsonwebtoken::decode::<serde_json::Value>(token, key, validation).is_ok();
+    }
+    false
+}
+
+/// OAuth2 token endpoint for the client credentials grant (RFC 6749 section 4.4).
+///
+/// Clients authenticate using Basic authentication or `client_id` and `client_secret` form
+/// parameters.
+async fn token_endpoint(config: &Config, request: Request<Body>) -> Response<Body> {
+    if request.method() != Method::POST {
+        return Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Body::empty())
+            .unwrap();
+    }
+    let authorization = request
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned);
+    let body = match ::hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return token_error(StatusCode::BAD_REQUEST, "invalid_request"),
+    };
+    let params = form_urlencoded::parse(&body)
+        .into_owned()
+        .collect::<Vec<(String, String)>>();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    if param("grant_type") != Some("client_credentials") {
+        return token_error(StatusCode::BAD_REQUEST, "unsupported_grant_type");
+    }
+    let client = config.clients.iter().find(|client| {
+        let basic = http_auth::basic::encode_credentials(&client.client_id, &client.client_secret);
+        authorization.as_deref() == Some(basic.as_str())
+            || (param("client_id") == Some(client.client_id.as_str())
+                && param("client_secret") == Some(client.client_secret.as_str()))
+    });
+    match client {
+        Some(client) => {
+            let body = serde_json::json!({
+                "access_token": client.access_token,
+                "token_type": "Bearer",
+                "expires_in": 3600,
+            });
+            Response::builder()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        }
+        None => token_error(StatusCode::UNAUTHORIZED, "invalid_client"),
+    }
+}
+
+fn token_error(status: StatusCode, error: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({ "error": error }).to_string(),
+        ))
+        .unwrap()
+}
+
+fn md5_hex(data: &str) -> String {
+    Md5::digest(data.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
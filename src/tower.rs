@@ -0,0 +1,199 @@
+//! Tower middleware requiring authenticated requests.
+//!
+//! [`AuthenticationLayer`] wraps a service, verifying each request using one or more
+//! [`Verifier`]s. The first verifier to accept the request adds its identity to the request
+//! extensions as [`Authenticated<Identity>`], and the request is passed to the inner service.
+//! If no verifier accepts the request, the layer responds with `401 Unauthorized` and a
+//! `www-authenticate` challenge for each verifier, so that a client using `HttpAuthentication`
+//! can choose a scheme and retry.
+//!
+//! ```ignore
+//! let layer = AuthenticationLayer::new()
+//!     .with_verifier(BasicVerifier::new("Fake Realm", users))
+//!     .with_verifier(JwtVerifier::<Claims>::new("api", key, Algorithm::RS256));
+//! let service = ServiceBuilder::new().layer(layer).service(handler);
+//!
+//! // In the handler:
+//! if let Some(Authenticated(username)) = request.extensions().get::<Authenticated<String>>() {
+//!     ...
+//! }
+//! ```
+//!
+//! Different routes can accept different schemes by applying a different layer to each route.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::{Extensions, HeaderMap, Request, Response};
+use pin_project_lite::pin_project;
+
+use crate::verifier::{Rejection, Verifier};
+
+/// The identity returned by a verifier, added to the extensions of an authenticated request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Authenticated<Identity>(pub Identity);
+
+/// A verifier that adds its identity to the request extensions.
+trait ExtensionVerifier: Send + Sync {
+    fn verify_into(
+        &self,
+        headers: &HeaderMap,
+        extensions: &mut Extensions,
+    ) -> Result<(), Rejection>;
+}
+
+impl<V> ExtensionVerifier for V
+where
+    V: Verifier + Send + Sync,
+    V::Identity: Clone + Send + Sync + 'static,
+{
+    fn verify_into(
+        &self,
+        headers: &HeaderMap,
+        extensions: &mut Extensions,
+    ) -> Result<(), Rejection> {
+        let identity = self.verify(headers)?;
+        extensions.insert(Authenticated(identity));
+        Ok(())
+    }
+}
+
+/// Layer requiring requests to be accepted by one of its verifiers.
+#[derive(Clone, Default)]
+pub struct AuthenticationLayer {
+    verifiers: Vec<Arc<dyn ExtensionVerifier>>,
+}
+
+impl AuthenticationLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept requests verified by `verifier`, after trying the verifiers already added.
+    ///
+    /// Each verifier adds a challenge to the response for rejected requests.
+    #[must_use]
+    pub fn with_verifier<V>(mut self, verifier: V) -> Self
+    where
+        V: Verifier + Send + Sync + 'static,
+        V::Identity: Clone + Send + Sync + 'static,
+    {
+        self.verifiers.push(Arc::new(verifier));
+        self
+    }
+
+    /// Verify `request`, adding the identity to its extensions, or return the rejections of all
+    /// verifiers.
+    fn verify<Body>(&self, request: Request<Body>) -> Result<Request<Body>, Vec<Rejection>> {
+        let (mut parts, body) = request.into_parts();
+        let mut rejections = Vec::with_capacity(self.verifiers.len());
+        for verifier in &self.verifiers {
+            match verifier.verify_into(&parts.headers, &mut parts.extensions) {
+                Ok(()) => return Ok(Request::from_parts(parts, body)),
+                Err(rejection) => rejections.push(rejection),
+            }
+        }
+        Err(rejections)
+    }
+}
+
+impl<S> tower_layer::Layer<S> for AuthenticationLayer {
+    type Service = AuthenticationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthenticationService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`AuthenticationLayer`].
+#[derive(Clone)]
+pub struct AuthenticationService<S> {
+    inner: S,
+    layer: AuthenticationLayer,
+}
+
+impl<S, ReqBody, ResBody> tower_service::Service<Request<ReqBody>> for AuthenticationService<S>
+where
+    S: tower_service::Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        match self.layer.verify(request) {
+            Ok(request) => ResponseFuture::Inner {
+                future: self.inner.call(request),
+            },
+            Err(rejections) => ResponseFuture::Rejected {
+                response: Some(rejected(&rejections)),
+            },
+        }
+    }
+}
+
+/// Build the response for a request rejected by every verifier.
+///
+/// The status is taken from the first rejection with an error code, so that a malformed
+/// request is reported even if another scheme was expected. Otherwise the status is
+/// `401 Unauthorized`.
+fn rejected<Body: Default>(rejections: &[Rejection]) -> Response<Body> {
+    let status = rejections
+        .iter()
+        .find(|rejection| rejection.error().is_some())
+        .or_else(|| rejections.first())
+        .map(Rejection::status)
+        .unwrap_or(http::StatusCode::UNAUTHORIZED);
+    let mut response = Response::new(Body::default());
+    *response.status_mut() = status;
+    for rejection in rejections {
+        // A realm that cannot be sent in a header is a configuration error, and the challenge
+        // is omitted.
+        if let Ok(Some(challenge)) = rejection.www_authenticate() {
+            response
+                .headers_mut()
+                .append(http::header::WWW_AUTHENTICATE, challenge);
+        }
+    }
+    response
+}
+
+pin_project! {
+    /// Response future of [`AuthenticationService`].
+    #[project = ResponseFutureProj]
+    pub enum ResponseFuture<F, Body> {
+        Inner {
+            #[pin]
+            future: F,
+        },
+        Rejected {
+            response: Option<Response<Body>>,
+        },
+    }
+}
+
+impl<F, Body, Error> Future for ResponseFuture<F, Body>
+where
+    F: Future<Output = Result<Response<Body>, Error>>,
+{
+    type Output = Result<Response<Body>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Inner { future } => future.poll(cx),
+            ResponseFutureProj::Rejected { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+        }
+    }
+}
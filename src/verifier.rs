@@ -0,0 +1,556 @@
+//! Server-side verification of authenticated requests.
+//!
+//! Each verifier checks the request headers sent by the matching client-side protocol:
+//! - [`BasicVerifier`] checks `BasicAuthentication` against a [`PasswordStore`].
+//! - [`TokenVerifier`] checks `BearerAuthentication` or `HeaderAuthentication` against a set of
+//!   tokens.
+//! - [`JwtVerifier`] checks `BearerAuthentication` or `HeaderAuthentication` using a
+//!   `JsonWebTokenCredential`, validating the signature and claims (`features = ["jwt"]`).
+//!
+//! A failed verification returns a [`Rejection`], which provides the status code and
+//! `www-authenticate` challenge for the response.
+//!
+//! ```ignore
+//! let verifier = BasicVerifier::new("Fake Realm", users);
+//! match verifier.verify(request.headers()) {
+//!     Ok(username) => handle(username, request),
+//!     Err(rejection) => {
+//!         let mut response = Response::builder().status(rejection.status());
+//!         if let Some(challenge) = rejection.www_authenticate()? {
+//!             response = response.header(WWW_AUTHENTICATE, challenge);
+//!         }
+//!         response.body(Body::empty())
+//!     }
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+#[cfg(feature = "jwt")]
+use std::marker::PhantomData;
+#[cfg(feature = "jwt")]
+use std::time::Duration;
+
+use base64::Engine;
+use http::{HeaderMap, HeaderValue, StatusCode};
+
+use crate::credential::{AuthenticationCredential, FetchedUsernamePassword};
+use crate::AuthenticError;
+
+/// Verifies the credentials in the headers of a request.
+pub trait Verifier {
+    /// The authenticated identity returned by a successful verification.
+    type Identity;
+
+    fn verify(&self, headers: &HeaderMap) -> Result<Self::Identity, Rejection>;
+}
+
+/// Error codes for bearer token rejections (RFC 6750 section 3.1).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BearerError {
+    /// The request is malformed, such as having several `Authorization` headers.
+    InvalidRequest,
+    /// The token is expired, revoked, malformed or invalid.
+    InvalidToken,
+    /// The token does not allow access to the resource.
+    InsufficientScope,
+}
+
+impl BearerError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest => "invalid_request",
+            Self::InvalidToken => "invalid_token",
+            Self::InsufficientScope => "insufficient_scope",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidRequest => StatusCode::BAD_REQUEST,
+            Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::InsufficientScope => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+/// A request that failed verification.
+#[derive(Clone, Debug)]
+pub struct Rejection {
+    // The scheme to challenge with, or `None` for a custom header.
+    scheme: Option<Cow<'static, str>>,
+    realm: String,
+    error: Option<BearerError>,
+    description: Cow<'static, str>,
+}
+
+impl Rejection {
+    fn new(
+        location: &TokenLocation,
+        realm: &str,
+        error: Option<BearerError>,
+        description: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        let scheme = match location {
+            TokenLocation::Authorization(scheme) => Some(scheme.clone()),
+            TokenLocation::Header(_) => None,
+        };
+        Self {
+            scheme,
+            realm: realm.to_owned(),
+            error,
+            description: description.into(),
+        }
+    }
+
+    /// The status code for the response: `401 Unauthorized` unless the error code requires
+    /// another status.
+    pub fn status(&self) -> StatusCode {
+        self.error
+            .map(|error| error.status())
+            .unwrap_or(StatusCode::UNAUTHORIZED)
+    }
+
+    /// The bearer token error code, if any.
+    ///
+    /// As recommended by RFC 6750, requests with no credentials have no error code.
+    pub fn error(&self) -> Option<BearerError> {
+        self.error
+    }
+
+    /// A description of the failure, for logging.
+    ///
+    /// The description is not included in the challenge, to avoid revealing why the
+    /// credentials were rejected.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The value for a `www-authenticate` response header, or `None` if the credentials are
+    /// passed in a custom header with no challenge scheme.
+    pub fn www_authenticate(&self) -> Result<Option<HeaderValue>, AuthenticError> {
+        let scheme = match &self.scheme {
+            Some(scheme) => scheme,
+            None => return Ok(None),
+        };
+        let mut challenge = format!("{} realm=\"{}\"", scheme, quote(&self.realm));
+        if scheme.eq_ignore_ascii_case("Basic") {
+            challenge.push_str(", charset=\"UTF-8\"");
+        }
+        if let Some(error) = self.error {
+            challenge.push_str(", error=\"");
+            challenge.push_str(error.code());
+            challenge.push('"');
+        }
+        Ok(Some(HeaderValue::try_from(challenge)?))
+    }
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Authentication rejected: {}", self.description)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// Escape a value for a quoted string.
+fn quote(value: &str) -> Cow<'_, str> {
+    if value.contains(&['"', '\\'][..]) {
+        value.replace('\\', "\\\\").replace('"', "\\\"").into()
+    } else {
+        value.into()
+    }
+}
+
+/// Compare secrets in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Where a token is found in a request.
+#[derive(Clone, Debug)]
+enum TokenLocation {
+    /// In the `Authorization` header, following the scheme.
+    Authorization(Cow<'static, str>),
+    /// The whole value of a header.
+    Header(Cow<'static, str>),
+}
+
+impl TokenLocation {
+    /// Get the credentials following `scheme` in the `Authorization` header, or the value of a
+    /// custom header.
+    fn find<'h>(&self, headers: &'h HeaderMap, realm: &str) -> Result<&'h [u8], Rejection> {
+        let name: &str = match self {
+            Self::Authorization(_) => http::header::AUTHORIZATION.as_str(),
+            Self::Header(name) => name,
+        };
+        let mut values = headers.get_all(name).iter();
+        let value = match (values.next(), values.next()) {
+            (None, _) => return Err(Rejection::new(self, realm, None, "No credentials")),
+            (Some(value), None) => value.as_bytes(),
+            (Some(_), Some(_)) => {
+                return Err(Rejection::new(
+                    self,
+                    realm,
+                    Some(BearerError::InvalidRequest),
+                    format!("Multiple {} headers", name),
+                ))
+            }
+        };
+        match self {
+            Self::Authorization(scheme) => {
+                let scheme = scheme.as_bytes();
+                // Schemes are case-insensitive, and separated from the credentials by spaces.
+                if value.len() > scheme.len()
+                    && value[..scheme.len()].eq_ignore_ascii_case(scheme)
+                    && value[scheme.len()] == b' '
+                {
+                    let credentials = &value[scheme.len()..];
+                    let start = credentials
+                        .iter()
+                        .position(|c| *c != b' ')
+                        .unwrap_or(credentials.len());
+                    Ok(&credentials[start..])
+                } else {
+                    Err(Rejection::new(
+                        self,
+                        realm,
+                        None,
+                        "Credentials use a different scheme",
+                    ))
+                }
+            }
+            Self::Header(_) => Ok(value),
+        }
+    }
+}
+
+/// Checks usernames and passwords for Basic authentication.
+///
+/// Implementations are provided for a `HashMap` from usernames to passwords, and for a single
+/// [`crate::credential::UsernamePasswordCredential`].
+pub trait PasswordStore: Send + Sync {
+    fn verify_password(&self, username: &str, password: &str) -> bool;
+}
+
+impl<Username, Password> PasswordStore for HashMap<Username, Password>
+where
+    Username: std::borrow::Borrow<str> + std::hash::Hash + Eq + Send + Sync,
+    Password: AsRef<str> + Send + Sync,
+{
+    fn verify_password(&self, username: &str, password: &str) -> bool {
+        match self.get(username) {
+            Some(expected) => constant_time_eq(expected.as_ref().as_bytes(), password.as_bytes()),
+            None => false,
+        }
+    }
+}
+
+impl PasswordStore for crate::credential::UsernamePasswordCredential {
+    fn verify_password(&self, username: &str, password: &str) -> bool {
+        match self.fetch() {
+            Ok(fetched) => {
+                fetched.username() == username
+                    && constant_time_eq(fetched.password().as_bytes(), password.as_bytes())
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Verifies Basic authentication, returning the username.
+pub struct BasicVerifier<Store> {
+    realm: Cow<'static, str>,
+    store: Store,
+}
+
+impl<Store> BasicVerifier<Store>
+where
+    Store: PasswordStore,
+{
+    pub fn new(realm: impl Into<Cow<'static, str>>, store: Store) -> Self {
+        Self {
+            realm: realm.into(),
+            store,
+        }
+    }
+}
+
+impl<Store> Verifier for BasicVerifier<Store>
+where
+    Store: PasswordStore,
+{
+    type Identity = String;
+
+    fn verify(&self, headers: &HeaderMap) -> Result<String, Rejection> {
+        let location = TokenLocation::Authorization("Basic".into());
+        let encoded = location.find(headers, &self.realm)?;
+        let invalid = |description| Rejection::new(&location, &self.realm, None, description);
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| invalid("Invalid Basic credentials encoding"))?;
+        let decoded = String::from_utf8(decoded)
+            .map_err(|_| invalid("Invalid Basic credentials encoding"))?;
+        let (username, password) = decoded
+            .split_once(':')
+            .ok_or_else(|| invalid("Missing password in Basic credentials"))?;
+        if self.store.verify_password(username, password) {
+            Ok(username.to_owned())
+        } else {
+            Err(invalid("Invalid username or password"))
+        }
+    }
+}
+
+/// Verifies a token from a fixed set, returning the index of the matching token.
+///
+/// By default, the token is expected in the `Authorization` header using the `Bearer` scheme,
+/// as sent by `BearerAuthentication`.
+pub struct TokenVerifier {
+    realm: Cow<'static, str>,
+    location: TokenLocation,
+    tokens: Vec<Cow<'static, [u8]>>,
+}
+
+impl TokenVerifier {
+    pub fn new(realm: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            realm: realm.into(),
+            location: TokenLocation::Authorization("Bearer".into()),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Accept `token`.
+    #[must_use]
+    pub fn with_token(mut self, token: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.tokens.push(token.into());
+        self
+    }
+
+    /// Expect the token in the `Authorization` header using `auth_scheme`, matching
+    /// `BearerAuthentication::with_auth_scheme`.
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: impl Into<Cow<'static, str>>) -> Self {
+        self.location = TokenLocation::Authorization(auth_scheme.into());
+        self
+    }
+
+    /// Expect the token as the value of the header `header_name`, as sent by
+    /// `HeaderAuthentication`.
+    #[must_use]
+    pub fn with_header(mut self, header_name: impl Into<Cow<'static, str>>) -> Self {
+        self.location = TokenLocation::Header(header_name.into());
+        self
+    }
+}
+
+impl Verifier for TokenVerifier {
+    type Identity = usize;
+
+    fn verify(&self, headers: &HeaderMap) -> Result<usize, Rejection> {
+        let token = self.location.find(headers, &self.realm)?;
+        // Compare with every token, so the time taken does not reveal which token matched.
+        self.tokens
+            .iter()
+            .enumerate()
+            .fold(None, |found, (index, expected)| {
+                if constant_time_eq(expected, token) {
+                    Some(index)
+                } else {
+                    found
+                }
+            })
+            .ok_or_else(|| {
+                Rejection::new(
+                    &self.location,
+                    &self.realm,
+                    Some(BearerError::InvalidToken),
+                    "Unknown token",
+                )
+            })
+    }
+}
+
+/// Keys used to verify the signature of a JSON Web Token.
+#[cfg(feature = "jwt")]
+enum JwtKeys {
+    Key(jsonwebtoken::DecodingKey),
+    #[cfg(feature = "step")]
+    Jwks(std::sync::Arc<crate::credential::JwksCache>),
+}
+
+/// Verifies a JSON Web Token, returning its claims.
+///
+/// The signature, and the `exp` and `nbf` claims, are always validated. Use
+/// [`JwtVerifier::with_issuer`] and [`JwtVerifier::with_audience`] to also validate the `iss`
+/// and `aud` claims.
+///
+/// Tokens are verified using a single key, or with the `step` feature, using the key matching
+/// their `kid` header in a [`crate::credential::JwksCache`].
+#[cfg(feature = "jwt")]
+pub struct JwtVerifier<Claims = serde_json::Value> {
+    realm: Cow<'static, str>,
+    location: TokenLocation,
+    keys: JwtKeys,
+    validation: jsonwebtoken::Validation,
+    claims: PhantomData<fn() -> Claims>,
+}
+
+#[cfg(feature = "jwt")]
+impl<Claims> JwtVerifier<Claims>
+where
+    Claims: serde::de::DeserializeOwned,
+{
+    /// Verify tokens signed with `algorithm` using `key`.
+    pub fn new(
+        realm: impl Into<Cow<'static, str>>,
+        key: jsonwebtoken::DecodingKey,
+        algorithm: jsonwebtoken::Algorithm,
+    ) -> Self {
+        let mut validation = jsonwebtoken::Validation::new(algorithm);
+        validation.validate_nbf = true;
+        Self::with_validation(realm, key, validation)
+    }
+
+    /// Verify tokens using `key` and custom validation.
+    pub fn with_validation(
+        realm: impl Into<Cow<'static, str>>,
+        key: jsonwebtoken::DecodingKey,
+        validation: jsonwebtoken::Validation,
+    ) -> Self {
+        Self {
+            realm: realm.into(),
+            location: TokenLocation::Authorization("Bearer".into()),
+            keys: JwtKeys::Key(key),
+            validation,
+            claims: PhantomData,
+        }
+    }
+
+    /// Verify tokens signed with `algorithm` using the key from `jwks` matching the token `kid`.
+    ///
+    /// The key set must be fetched before verifying tokens, and fetched again when due, by the
+    /// server calling `auth_step` or [`crate::credential::JwksCache::refresh_with`], or by a
+    /// `CredentialRefresher`.
+    #[cfg(feature = "step")]
+    pub fn from_jwks(
+        realm: impl Into<Cow<'static, str>>,
+        jwks: std::sync::Arc<crate::credential::JwksCache>,
+        algorithm: jsonwebtoken::Algorithm,
+    ) -> Self {
+        let mut validation = jsonwebtoken::Validation::new(algorithm);
+        validation.validate_nbf = true;
+        Self {
+            realm: realm.into(),
+            location: TokenLocation::Authorization("Bearer".into()),
+            keys: JwtKeys::Jwks(jwks),
+            validation,
+            claims: PhantomData,
+        }
+    }
+
+    /// Require the `iss` claim to be one of `issuers`.
+    #[must_use]
+    pub fn with_issuer<T: ToString>(mut self, issuers: &[T]) -> Self {
+        self.validation.set_issuer(issuers);
+        self.validation
+            .required_spec_claims
+            .insert("iss".to_owned());
+        self
+    }
+
+    /// Require the `aud` claim to contain one of `audience`.
+    #[must_use]
+    pub fn with_audience<T: ToString>(mut self, audience: &[T]) -> Self {
+        self.validation.set_audience(audience);
+        self.validation
+            .required_spec_claims
+            .insert("aud".to_owned());
+        self
+    }
+
+    /// Allow for clock differences when validating the `exp` and `nbf` claims.
+    ///
+    /// The default leeway is 60 seconds.
+    #[must_use]
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.validation.leeway = leeway.as_secs();
+        self
+    }
+
+    /// Expect the token in the `Authorization` header using `auth_scheme`, matching
+    /// `BearerAuthentication::with_auth_scheme`.
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: impl Into<Cow<'static, str>>) -> Self {
+        self.location = TokenLocation::Authorization(auth_scheme.into());
+        self
+    }
+
+    /// Expect the token as the value of the header `header_name`, as sent by
+    /// `HeaderAuthentication`.
+    #[must_use]
+    pub fn with_header(mut self, header_name: impl Into<Cow<'static, str>>) -> Self {
+        self.location = TokenLocation::Header(header_name.into());
+        self
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl<Claims> Verifier for JwtVerifier<Claims>
+where
+    Claims: serde::de::DeserializeOwned,
+{
+    type Identity = Claims;
+
+    fn verify(&self, headers: &HeaderMap) -> Result<Claims, Rejection> {
+        use jsonwebtoken::errors::ErrorKind;
+
+        let token = self.location.find(headers, &self.realm)?;
+        let invalid = |description: String| {
+            Rejection::new(
+                &self.location,
+                &self.realm,
+                Some(BearerError::InvalidToken),
+                description,
+            )
+        };
+        let token = std::str::from_utf8(token).map_err(|_| invalid("Invalid token".to_owned()))?;
+        #[cfg(feature = "step")]
+        let fetched;
+        #[cfg_attr(not(feature = "step"), allow(clippy::infallible_destructuring_match))]
+        let key = match &self.keys {
+            JwtKeys::Key(key) => key,
+            #[cfg(feature = "step")]
+            JwtKeys::Jwks(jwks) => {
+                let header = jsonwebtoken::decode_header(token)
+                    .map_err(|err| invalid(format!("Invalid token: {}", err)))?;
+                fetched = jwks
+                    .fetch()
+                    .map_err(|err| invalid(format!("No keys to verify token: {}", err)))?;
+                match fetched.key(header.kid.as_deref()) {
+                    Some(key) => key,
+                    None => {
+                        jwks.unknown_key();
+                        return Err(invalid(match header.kid {
+                            Some(kid) => format!("Unknown key ID {:?}", kid),
+                            None => "Token has no key ID".to_owned(),
+                        }));
+                    }
+                }
+            }
+        };
+        match jsonwebtoken::decode::<Claims>(token, key, &self.validation) {
+            Ok(data) => Ok(data.claims),
+            Err(err) => Err(invalid(match err.kind() {
+                ErrorKind::ExpiredSignature => "Token has expired".to_owned(),
+                ErrorKind::ImmatureSignature => "Token is not yet valid".to_owned(),
+                ErrorKind::InvalidIssuer => "Token has an invalid issuer".to_owned(),
+                ErrorKind::InvalidAudience => "Token has an invalid audience".to_owned(),
+                ErrorKind::InvalidSignature => "Token has an invalid signature".to_owned(),
+                _ => format!("Invalid token: {}", err),
+            })),
+        }
+    }
+}
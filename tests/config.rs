@@ -0,0 +1,174 @@
+#![cfg(all(feature = "config", feature = "hyper"))]
+
+use authentic::config::ProtocolConfig;
+use authentic::AuthenticError;
+use hyper::{Body, Request};
+
+fn protocol_config(json: &str) -> ProtocolConfig {
+    serde_json::from_str(json).unwrap()
+}
+
+/// Configure a request using a protocol created from `json`, and return its headers.
+fn configured_headers(json: &str) -> Result<http::HeaderMap, AuthenticError> {
+    let factory = protocol_config(json).build()?;
+    let authentication = factory.hyper();
+    let request = authentication.dyn_configure(
+        Request::get("https://example.com/")
+            .header("x-other", "other")
+            .body(Body::empty())?,
+    )?;
+    Ok(request.headers().clone())
+}
+
+#[test]
+fn test_config_none() -> Result<(), AuthenticError> {
+    let headers = configured_headers(r#"{"type": "none"}"#)?;
+    assert_eq!(headers.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_config_header() -> Result<(), AuthenticError> {
+    let headers = configured_headers(
+        r#"{"type": "header", "name": "x-api-key", "token": {"value": "secret"}}"#,
+    )?;
+    assert_eq!(headers["x-api-key"], "secret");
+    assert_eq!(headers["x-other"], "other");
+    Ok(())
+}
+
+#[test]
+fn test_config_bearer() -> Result<(), AuthenticError> {
+    std::env::set_var("AUTHENTIC_TEST_CONFIG_TOKEN", "from-env");
+    let headers = configured_headers(
+        r#"{"type": "bearer", "token": {"env": "AUTHENTIC_TEST_CONFIG_TOKEN"}}"#,
+    )?;
+    assert_eq!(headers[http::header::AUTHORIZATION], "Bearer from-env");
+
+    let headers =
+        configured_headers(r#"{"type": "bearer", "scheme": "token", "token": {"value": "abc"}}"#)?;
+    assert_eq!(headers[http::header::AUTHORIZATION], "token abc");
+    Ok(())
+}
+
+#[test]
+fn test_config_basic_file() -> Result<(), AuthenticError> {
+    let path = std::env::temp_dir().join(format!("authentic-config-{}", std::process::id()));
+    std::fs::write(&path, "password\n")?;
+    let json = format!(
+        r#"{{"type": "basic", "username": "username", "password": {{"file": {:?}}}}}"#,
+        path
+    );
+    let headers = configured_headers(&json);
+    std::fs::remove_file(&path)?;
+    assert_eq!(
+        headers?[http::header::AUTHORIZATION],
+        "Basic dXNlcm5hbWU6cGFzc3dvcmQ="
+    );
+    Ok(())
+}
+
+/// Missing secrets are reported when building the credentials.
+#[test]
+fn test_config_missing_secret() {
+    let config =
+        protocol_config(r#"{"type": "bearer", "token": {"env": "AUTHENTIC_TEST_CONFIG_MISSING"}}"#);
+    assert!(matches!(
+        config.build(),
+        Err(AuthenticError::CredentialUnavailable(_))
+    ));
+}
+
+/// HTTP authentication responds to a challenge using the configured realms.
+#[cfg(feature = "loop")]
+#[test]
+fn test_config_http() -> Result<(), AuthenticError> {
+    let factory = protocol_config(
+        r#"{
+            "type": "http",
+            "realms": {
+                "Fake Realm": {"username": "username", "password": {"value": "password"}}
+            },
+            "insecure_minimum": "digest"
+        }"#,
+    )
+    .build()?;
+
+    let challenge = || {
+        hyper::Response::builder()
+            .status(401)
+            .header(
+                http::header::WWW_AUTHENTICATE,
+                r#"Basic realm="Fake Realm""#,
+            )
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let mut authentication = factory.hyper();
+    let request = Request::get("https://example.com/").body(Body::empty())?;
+    authentication.dyn_configure(request)?;
+    assert!(!authentication.dyn_has_completed(&challenge())?);
+    let request = Request::get("https://example.com/").body(Body::empty())?;
+    let request = authentication.dyn_configure(request)?;
+    assert_eq!(
+        request.headers()[http::header::AUTHORIZATION],
+        "Basic dXNlcm5hbWU6cGFzc3dvcmQ="
+    );
+
+    // Basic is refused over plain http.
+    let mut authentication = factory.hyper();
+    let request = Request::get("http://example.com/").body(Body::empty())?;
+    authentication.dyn_configure(request)?;
+    assert!(authentication.dyn_has_completed(&challenge()).is_err());
+    Ok(())
+}
+
+/// JWTs are created for each protocol using the same credential.
+#[cfg(all(feature = "jwt", feature = "step"))]
+#[test]
+fn test_config_jwt() -> Result<(), AuthenticError> {
+    let factory = protocol_config(
+        r#"{
+            "type": "bearer",
+            "token": {"jwt": {"key": {"value": "secret"}, "issuer": "me", "claims": {"role": "reader"}}}
+        }"#,
+    )
+    .build()?;
+    let authentication = factory.hyper();
+    while authentication.dyn_step()?.is_some() {}
+    let request =
+        authentication.dyn_configure(Request::get("https://example.com/").body(Body::empty())?)?;
+    let value = request.headers()[http::header::AUTHORIZATION]
+        .to_str()
+        .unwrap();
+    let jwt = value.strip_prefix("Bearer ").unwrap();
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+    validation.set_issuer(&["me"]);
+    let claims = jsonwebtoken::decode::<serde_json::Value>(
+        jwt,
+        &jsonwebtoken::DecodingKey::from_secret(b"secret"),
+        &validation,
+    )?;
+    assert_eq!(claims.claims["role"], "reader");
+    Ok(())
+}
+
+/// JWT lifetimes outside the supported range are rejected when building the credentials.
+#[cfg(all(feature = "jwt", feature = "step"))]
+#[test]
+fn test_config_jwt_expiration() {
+    for expiration in ["0", "18446744073709551615"] {
+        let config = protocol_config(&format!(
+            r#"{{
+                "type": "bearer",
+                "token": {{"jwt": {{"key": {{"value": "secret"}}, "expiration": {}}}}}
+            }}"#,
+            expiration
+        ));
+        assert!(matches!(
+            config.build(),
+            Err(AuthenticError::Configuration(_))
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use authentic::credential::{
+    AuthenticationCredential, CredentialChain, FetchedToken, TokenCredential,
+};
+use authentic::{AuthenticError, AuthenticationStep};
+
+/// Token source that can be made unavailable, counting fetch attempts.
+struct SwitchableToken {
+    token: TokenCredential,
+    available: AtomicBool,
+    attempts: AtomicUsize,
+}
+
+impl SwitchableToken {
+    fn new(token: &'static str, available: bool) -> Arc<Self> {
+        Arc::new(Self {
+            token: TokenCredential::new(token.as_bytes()),
+            available: AtomicBool::new(available),
+            attempts: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl AuthenticationCredential for SwitchableToken {
+    type Fetch = <TokenCredential as AuthenticationCredential>::Fetch;
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        if self.available.load(Ordering::SeqCst) {
+            self.token.fetch()
+        } else {
+            Err(AuthenticError::CredentialUnavailable("unavailable".into()))
+        }
+    }
+}
+
+/// Token source that requests its token from an endpoint, making one request at a time.
+struct RequestedToken {
+    token: Mutex<Option<Vec<u8>>>,
+    outstanding: AtomicBool,
+    requests: AtomicUsize,
+}
+
+impl RequestedToken {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            token: Mutex::new(None),
+            outstanding: AtomicBool::new(false),
+            requests: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl AuthenticationCredential for RequestedToken {
+    type Fetch = <TokenCredential as AuthenticationCredential>::Fetch;
+
+    fn auth_step(
+        &self,
+    ) -> Result<Option<AuthenticationStep<http::Request<Vec<u8>>>>, AuthenticError> {
+        if self.token.lock().unwrap().is_some() || self.outstanding.swap(true, Ordering::SeqCst) {
+            return Ok(None);
+        }
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let request = http::Request::post("https://example.com/token").body(Vec::new())?;
+        Ok(Some(AuthenticationStep::Request(request)))
+    }
+
+    fn auth_respond(&self, response: Result<http::Response<Vec<u8>>, AuthenticError>) {
+        self.outstanding.store(false, Ordering::SeqCst);
+        if let Ok(response) = response {
+            *self.token.lock().unwrap() = Some(response.into_body());
+        }
+    }
+
+    fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+        match &*self.token.lock().unwrap() {
+            Some(token) => TokenCredential::new(token.clone()).fetch(),
+            None => Err(AuthenticError::CredentialUnavailable("no token".into())),
+        }
+    }
+}
+
+/// A source that needs requests completes its steps before its credential is fetched, and the
+/// response is passed to the source that made the request.
+#[test]
+fn test_chain_step_fallback() {
+    let env = SwitchableToken::new("env", false);
+    let endpoint = RequestedToken::new();
+    let chain = CredentialChain::new()
+        .with_source(env.clone())
+        .with_source(endpoint.clone());
+
+    let step = chain.auth_step().unwrap();
+    assert!(matches!(step, Some(AuthenticationStep::Request(_))));
+    assert_eq!(chain.current_source(), 1);
+
+    // A fetch during the request can change the current source.
+    env.available.store(true, Ordering::SeqCst);
+    assert_eq!(chain.fetch().unwrap().token(), b"env");
+    assert_eq!(chain.current_source(), 0);
+    env.available.store(false, Ordering::SeqCst);
+
+    chain.auth_respond(Ok(http::Response::new(b"endpoint".to_vec())));
+    assert!(chain.auth_step().unwrap().is_none());
+    assert_eq!(chain.current_source(), 1);
+    assert_eq!(chain.fetch().unwrap().token(), b"endpoint");
+    assert_eq!(endpoint.requests.load(Ordering::SeqCst), 1);
+}
+
+/// While a source has an outstanding request, other sources do not make requests, and callers
+/// wait for the response, which is passed to the source that made the request.
+#[test]
+fn test_chain_concurrent_steps() {
+    let first = RequestedToken::new();
+    let second = RequestedToken::new();
+    let chain = CredentialChain::new()
+        .with_source(first.clone())
+        .with_source(second.clone());
+
+    let step = chain.auth_step().unwrap();
+    assert!(matches!(step, Some(AuthenticationStep::Request(_))));
+    let waiter = match chain.auth_step().unwrap() {
+        Some(AuthenticationStep::WaitForRenewal(waiter)) => waiter,
+        _ => panic!("expected to wait for the outstanding request"),
+    };
+    assert_eq!(second.requests.load(Ordering::SeqCst), 0);
+
+    chain.auth_respond(Ok(http::Response::new(b"first".to_vec())));
+    waiter.wait();
+    assert!(chain.auth_step().unwrap().is_none());
+    assert_eq!(chain.fetch().unwrap().token(), b"first");
+    assert_eq!(chain.current_source(), 0);
+    assert_eq!(second.requests.load(Ordering::SeqCst), 0);
+}
+
+/// The first available source is used, and used again without trying earlier sources.
+#[test]
+fn test_chain_first_available() {
+    let env = SwitchableToken::new("env", false);
+    let file = SwitchableToken::new("file", true);
+    let chain = CredentialChain::new()
+        .with_source(env.clone())
+        .with_source(file.clone())
+        .with_source(Arc::new(TokenCredential::new(b"default".as_ref())));
+
+    assert_eq!(chain.fetch().unwrap().token(), b"file");
+    assert_eq!(chain.current_source(), 1);
+    assert_eq!(chain.fetch().unwrap().token(), b"file");
+    assert_eq!(env.attempts.load(Ordering::SeqCst), 1);
+    assert_eq!(file.attempts.load(Ordering::SeqCst), 2);
+}
+
+/// Sources are tried again in order when the current source fails.
+#[test]
+fn test_chain_reprobe() {
+    let env = SwitchableToken::new("env", false);
+    let file = SwitchableToken::new("file", true);
+    let chain = CredentialChain::new()
+        .with_source(env.clone())
+        .with_source(file.clone())
+        .with_source(Arc::new(TokenCredential::new(b"default".as_ref())));
+    assert_eq!(chain.fetch().unwrap().token(), b"file");
+
+    file.available.store(false, Ordering::SeqCst);
+    assert_eq!(chain.fetch().unwrap().token(), b"default");
+    assert_eq!(chain.current_source(), 2);
+
+    env.available.store(true, Ordering::SeqCst);
+    chain.fetch().unwrap();
+    assert_eq!(chain.current_source(), 2);
+}
+
+/// When every source fails, the first retryable error is returned, or otherwise the last error.
+#[test]
+fn test_chain_unavailable() {
+    struct Misconfigured;
+
+    impl AuthenticationCredential for Misconfigured {
+        type Fetch = <TokenCredential as AuthenticationCredential>::Fetch;
+
+        fn fetch(&self) -> Result<Self::Fetch, AuthenticError> {
+            Err(AuthenticError::Configuration("misconfigured".into()))
+        }
+    }
+
+    let chain = CredentialChain::new()
+        .with_source(Arc::new(Misconfigured))
+        .with_source(SwitchableToken::new("env", false))
+        .with_source(Arc::new(Misconfigured));
+    let err = chain.fetch().err().expect("expected error");
+    assert!(
+        matches!(&err, AuthenticError::CredentialUnavailable(message) if message == "unavailable")
+    );
+    assert!(err.is_retryable());
+
+    let chain = CredentialChain::new()
+        .with_source(SwitchableToken::new("env", false))
+        .with_source(Arc::new(Misconfigured));
+    assert!(chain.fetch().err().expect("expected error").is_retryable());
+
+    let chain = CredentialChain::new().with_source(Arc::new(Misconfigured));
+    assert!(matches!(
+        chain.fetch(),
+        Err(AuthenticError::Configuration(_))
+    ));
+    assert!(CredentialChain::<()>::new().fetch().is_err());
+}
+
+/// Sources of different types are combined by mapping to a common type.
+#[test]
+fn test_chain_mixed_sources() {
+    let chain: CredentialChain<Box<dyn FetchedToken + Send + Sync>> = CredentialChain::new()
+        .with_source_map(SwitchableToken::new("env", false), |fetched| {
+            Box::new(fetched) as Box<dyn FetchedToken + Send + Sync>
+        })
+        .with_source_map(
+            Arc::new(TokenCredential::new(b"default".as_ref())),
+            |fetched| Box::new(fetched) as Box<dyn FetchedToken + Send + Sync>,
+        );
+    assert_eq!(chain.fetch().unwrap().token(), b"default");
+}
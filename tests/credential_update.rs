@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use authentic::credential::{
+    AuthenticationCredential, FetchedToken, FetchedUsernamePassword, TokenCredential,
+    UsernamePasswordCredential,
+};
+
+/// Updated tokens are used by later fetches, without affecting earlier fetches.
+#[test]
+fn test_token_update() {
+    let credential = TokenCredential::new(b"first".as_ref());
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let subscriber_updates = updates.clone();
+    let _subscription = credential.subscribe(move |fetched| {
+        subscriber_updates
+            .lock()
+            .unwrap()
+            .push(fetched.token().to_vec());
+    });
+
+    let before = credential.fetch().unwrap();
+    credential.update(b"second".as_ref());
+    assert_eq!(before.token(), b"first");
+    assert_eq!(credential.fetch().unwrap().token(), b"second");
+    assert_eq!(*updates.lock().unwrap(), [b"second".to_vec()]);
+
+    credential.rotate(b"third".as_ref());
+    assert_eq!(credential.fetch().unwrap().token(), b"third");
+    assert_eq!(
+        *updates.lock().unwrap(),
+        [b"second".to_vec(), b"third".to_vec()]
+    );
+}
+
+/// Rotating the password keeps the username.
+#[test]
+fn test_username_password_rotate() {
+    let credential = UsernamePasswordCredential::new("username", "password");
+    let updates = Arc::new(AtomicUsize::new(0));
+    let subscriber_updates = updates.clone();
+    let _subscription = credential.subscribe(move |_| {
+        subscriber_updates.fetch_add(1, Ordering::SeqCst);
+    });
+
+    credential.rotate("rotated");
+    let fetched = credential.fetch().unwrap();
+    assert_eq!(fetched.username(), "username");
+    assert_eq!(fetched.password(), "rotated");
+
+    credential.update("other", "changed");
+    let fetched = credential.fetch().unwrap();
+    assert_eq!(fetched.username(), "other");
+    assert_eq!(fetched.password(), "changed");
+    assert_eq!(updates.load(Ordering::SeqCst), 2);
+}
+
+/// Dropping a subscription removes the callback, while detached callbacks remain.
+#[test]
+fn test_unsubscribe() {
+    let credential = TokenCredential::new(b"first".as_ref());
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let detached = Arc::new(AtomicUsize::new(0));
+
+    let subscriber_dropped = dropped.clone();
+    let subscription = credential.subscribe(move |_| {
+        subscriber_dropped.fetch_add(1, Ordering::SeqCst);
+    });
+    let subscriber_detached = detached.clone();
+    credential
+        .subscribe(move |_| {
+            subscriber_detached.fetch_add(1, Ordering::SeqCst);
+        })
+        .detach();
+
+    credential.update(b"second".as_ref());
+    drop(subscription);
+    credential.update(b"third".as_ref());
+
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    assert_eq!(detached.load(Ordering::SeqCst), 2);
+    // The callback, and the values it holds, are released.
+    assert_eq!(Arc::strong_count(&dropped), 1);
+}
+
+/// Updated realms are used by later fetches.
+#[cfg(feature = "loop")]
+#[test]
+fn test_realm_update() {
+    use std::collections::HashMap;
+
+    use authentic::credential::HttpRealmCredentials;
+    use authentic::HttpScheme;
+
+    let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
+    let mut realm_credentials = HashMap::new();
+    realm_credentials.insert("Old Realm".into(), credential.clone());
+    let credentials = HttpRealmCredentials::new(realm_credentials);
+
+    let mut realm_credentials = HashMap::new();
+    realm_credentials.insert("New Realm".into(), credential);
+    credentials.update(realm_credentials);
+
+    let fetched = credentials.fetch().unwrap();
+    assert!(fetched
+        .resolve(None, "Old Realm", HttpScheme::Basic)
+        .is_none());
+    assert!(fetched
+        .resolve(None, "New Realm", HttpScheme::Basic)
+        .is_some());
+}
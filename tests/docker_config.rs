@@ -0,0 +1,124 @@
+#![cfg(feature = "docker")]
+
+use authentic::credential::{AuthenticationCredential, DockerConfig, FetchedUsernamePassword};
+
+const CONFIG: &[u8] = br#"{
+    "auths": {
+        "https://index.docker.io/v1/": {
+            "auth": "dXNlcm5hbWU6cGFzc3dvcmQ="
+        },
+        "registry.example.com": {
+            "identitytoken": "identity-token"
+        }
+    }
+}"#;
+
+/// Logins stored directly in the `auths` section.
+#[test]
+fn test_docker_config_auths() -> Result<(), Box<dyn std::error::Error>> {
+    let config = DockerConfig::from_slice(CONFIG)?;
+
+    let credential = config.credential("docker.io")?.unwrap();
+    let fetched = credential.fetch()?;
+    assert_eq!(fetched.username(), "username");
+    assert_eq!(fetched.password(), "password");
+    assert_eq!(fetched.identity_token(), None);
+
+    let credential = config
+        .credential("https://registry.example.com/v2/")?
+        .unwrap();
+    let fetched = credential.fetch()?;
+    assert_eq!(fetched.identity_token(), Some("identity-token"));
+
+    assert!(config.credential("ghcr.io")?.is_none());
+
+    Ok(())
+}
+
+/// An exact key match is preferred to other keys for the same registry.
+#[test]
+fn test_docker_config_key_precedence() -> Result<(), Box<dyn std::error::Error>> {
+    let config = DockerConfig::from_slice(
+        br#"{
+            "auths": {
+                "docker.io": {"username": "short", "password": "password"},
+                "https://index.docker.io/v1/": {"username": "hub", "password": "password"},
+                "registry-1.docker.io": {"username": "registry", "password": "password"}
+            }
+        }"#,
+    )?;
+    let username = |registry: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let credential = config.credential(registry)?.unwrap();
+        Ok(credential.fetch()?.username().to_owned())
+    };
+    assert_eq!(username("docker.io")?, "short");
+    assert_eq!(username("registry-1.docker.io")?, "registry");
+    // Other names for Docker Hub use the key written by `docker login`.
+    assert_eq!(username("index.docker.io")?, "hub");
+    Ok(())
+}
+
+/// Create a directory containing a credential helper `docker-credential-fake` that returns a
+/// login for `ghcr.io` only.
+#[cfg(unix)]
+fn helper_dir(name: &str) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir =
+        std::env::temp_dir().join(format!("authentic-docker-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let helper = dir.join("docker-credential-fake");
+    std::fs::write(
+        &helper,
+        "#!/bin/sh\nread server\nif [ \"$server\" != ghcr.io ]; then echo 'credentials not found in native keychain'; exit 1; fi\necho \"{\\\"ServerURL\\\":\\\"$server\\\",\\\"Username\\\":\\\"$server-user\\\",\\\"Secret\\\":\\\"secret\\\"}\"\n",
+    )?;
+    std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o755))?;
+    Ok(dir)
+}
+
+/// Logins provided by a credential helper named in `credHelpers`.
+#[cfg(unix)]
+#[test]
+fn test_docker_config_cred_helpers() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = helper_dir("helpers")?;
+
+    let config =
+        DockerConfig::from_slice(br#"{"credHelpers": {"ghcr.io": "fake"}}"#)?.with_helper_dir(&dir);
+
+    let credential = config.credential("ghcr.io")?.unwrap();
+    let fetched = credential.fetch()?;
+    assert_eq!(fetched.username(), "ghcr.io-user");
+    assert_eq!(fetched.password(), "secret");
+
+    assert!(config.credential("docker.io")?.is_none());
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+/// Logins provided by the `credsStore` helper, with the empty `auths` entries written by
+/// `docker login`.
+#[cfg(unix)]
+#[test]
+fn test_docker_config_creds_store() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = helper_dir("store")?;
+
+    let config = DockerConfig::from_slice(
+        br#"{
+            "auths": {"ghcr.io": {}, "quay.io": {}},
+            "credsStore": "fake"
+        }"#,
+    )?
+    .with_helper_dir(&dir);
+
+    let credential = config.credential("ghcr.io")?.unwrap();
+    assert_eq!(credential.fetch()?.username(), "ghcr.io-user");
+
+    // The store has no login, and the empty entry holds no credentials.
+    assert!(config.credential("quay.io")?.is_none());
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
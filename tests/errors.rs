@@ -0,0 +1,53 @@
+use authentic::AuthenticError;
+use http::StatusCode;
+
+/// Token endpoint errors are classified by status code.
+#[test]
+fn test_token_endpoint_classification() {
+    let error = |status| AuthenticError::TokenEndpoint {
+        status,
+        oauth_error: None,
+    };
+    for status in [
+        StatusCode::SERVICE_UNAVAILABLE,
+        StatusCode::TOO_MANY_REQUESTS,
+        StatusCode::REQUEST_TIMEOUT,
+    ] {
+        assert!(error(status).is_retryable());
+        assert!(!error(status).is_permanent());
+    }
+    for status in [StatusCode::UNAUTHORIZED, StatusCode::BAD_REQUEST] {
+        assert!(!error(status).is_retryable());
+        assert!(error(status).is_permanent());
+    }
+}
+
+/// Errors are retryable, permanent, or unclassified.
+#[test]
+fn test_error_classification() {
+    let unavailable = AuthenticError::CredentialUnavailable("No token".to_owned());
+    assert!(unavailable.is_retryable());
+    assert!(!unavailable.is_permanent());
+
+    for error in [
+        AuthenticError::ChallengeParse("Invalid".to_owned()),
+        AuthenticError::UnsupportedScheme("Negotiate".to_owned()),
+        AuthenticError::TooManyRounds(3),
+        AuthenticError::UnsupportedRequest("No URI".to_owned()),
+        AuthenticError::LockPoisoned,
+    ] {
+        assert!(!error.is_retryable());
+        assert!(error.is_permanent());
+    }
+
+    let other = AuthenticError::Other("Unknown".to_owned());
+    assert!(!other.is_retryable());
+    assert!(!other.is_permanent());
+
+    #[cfg(feature = "jwt")]
+    {
+        let signing = AuthenticError::Signing("Key disabled".to_owned());
+        assert!(!signing.is_retryable());
+        assert!(!signing.is_permanent());
+    }
+}
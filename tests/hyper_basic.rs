@@ -1,21 +1,28 @@
-#![cfg(feature = "hyper")]
+#![cfg(all(feature = "hyper", feature = "test-server"))]
 
 use std::sync::Arc;
 
 use authentic::credential::UsernamePasswordCredential;
 use authentic::hyper::BasicAuthentication;
-use authentic::{AuthenticationProtocol, AuthenticationStep, WithAuthentication};
+use authentic::test_server::TestServer;
+use authentic::{AuthenticError, AuthenticationProtocol, AuthenticationStep, WithAuthentication};
 use http::StatusCode;
 use hyper::Client;
-use hyper_tls::HttpsConnector;
+
+/// Local server accepting the credentials `username` and `password` for the realm `Fake Realm`.
+fn server() -> Result<TestServer, AuthenticError> {
+    TestServer::builder()
+        .with_basic("Fake Realm", "username", "password")
+        .start()
+}
 
 /// Direct basic authentication, passing the username and password on the first request.
 /// This test can run without the `loop` feature to demonstrate non-looping authentication.
 #[::tokio::test]
 async fn test_basic_authentication(
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, ::hyper::Body>(https);
+    let server = server()?;
+    let client = Client::new();
 
     let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
     let mut authentication = BasicAuthentication::new(credential);
@@ -29,9 +36,12 @@ async fn test_basic_authentication(
             AuthenticationStep::WaitFor(duration) => {
                 ::tokio::time::sleep(duration).await;
             }
+            AuthenticationStep::WaitForRenewal(waiter) => {
+                let _ = ::tokio::time::timeout(waiter.timeout(), waiter).await;
+            }
         }
     }
-    let request = ::hyper::Request::get("https://httpbin.org/basic-auth/username/password")
+    let request = ::hyper::Request::get(server.url("/basic-auth/username/password"))
         .with_authentication(&authentication)?
         .body(::hyper::Body::empty())?;
 
@@ -52,8 +62,8 @@ async fn test_basic_authentication(
 #[::tokio::test]
 async fn test_basic_authentication_loop(
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, ::hyper::Body>(https);
+    let server = server()?;
+    let client = Client::new();
 
     let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
     let mut authentication = BasicAuthentication::new(credential);
@@ -70,9 +80,12 @@ async fn test_basic_authentication_loop(
                 AuthenticationStep::WaitFor(duration) => {
                     ::tokio::time::sleep(duration).await;
                 }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    let _ = ::tokio::time::timeout(waiter.timeout(), waiter).await;
+                }
             }
         }
-        let request = ::hyper::Request::get("https://httpbin.org/basic-auth/username/password")
+        let request = ::hyper::Request::get(server.url("/basic-auth/username/password"))
             .with_authentication(&authentication)?
             .body(::hyper::Body::empty())?;
 
@@ -100,8 +113,8 @@ async fn test_basic_authentication_loop(
 #[cfg(feature = "loop")]
 #[::tokio::test]
 async fn test_basic_challenge() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, ::hyper::Body>(https);
+    let server = server()?;
+    let client = Client::new();
 
     let mut realm_credentials = std::collections::HashMap::new();
     realm_credentials.insert(
@@ -125,9 +138,12 @@ async fn test_basic_challenge() -> Result<(), Box<dyn std::error::Error + Send +
                 AuthenticationStep::WaitFor(duration) => {
                     ::tokio::time::sleep(duration).await;
                 }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    let _ = ::tokio::time::timeout(waiter.timeout(), waiter).await;
+                }
             }
         }
-        let request = ::hyper::Request::get("https://httpbin.org/basic-auth/username/password")
+        let request = ::hyper::Request::get(server.url("/basic-auth/username/password"))
             .with_authentication(&authentication)?
             .body(::hyper::Body::empty())?;
 
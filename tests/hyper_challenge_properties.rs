@@ -0,0 +1,151 @@
+#![cfg(all(feature = "hyper", feature = "loop"))]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use authentic::credential::{HttpRealmCredentials, UsernamePasswordCredential};
+use authentic::hyper::HttpAuthentication;
+use authentic::{AuthenticationProtocol, AuthenticationProtocolConfigure};
+use http::HeaderValue;
+use hyper::{Body, Response};
+use proptest::prelude::*;
+
+/// Challenge parameter values, including quoted strings with escapes and non-ASCII text.
+fn param_value() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[A-Za-z0-9._~+/=-]{0,12}",
+        "\"([^\"\\\\]|\\\\.){0,12}\"",
+        "\"[^\"]{0,12}",
+        any::<String>(),
+    ]
+}
+
+/// Challenges resembling those sent by servers, in both valid and malformed forms.
+fn challenge() -> impl Strategy<Value = String> {
+    let scheme = prop_oneof![
+        Just("Basic".to_owned()),
+        Just("Digest".to_owned()),
+        Just("Bearer".to_owned()),
+        Just("Negotiate".to_owned()),
+        "[A-Za-z!#$%&'*+.^_`|~-]{0,10}",
+    ];
+    let param = (
+        prop_oneof![
+            Just("realm".to_owned()),
+            Just("nonce".to_owned()),
+            Just("qop".to_owned()),
+            Just("algorithm".to_owned()),
+            Just("service".to_owned()),
+            Just("scope".to_owned()),
+            "[A-Za-z]{0,8}",
+        ],
+        param_value(),
+    )
+        .prop_map(|(name, value)| format!("{}={}", name, value));
+    (
+        scheme,
+        prop::collection::vec(param, 0..5),
+        prop_oneof![Just(", "), Just(","), Just(" ")],
+    )
+        .prop_map(|(scheme, params, separator)| format!("{} {}", scheme, params.join(separator)))
+}
+
+/// Header values made of challenges, or arbitrary bytes.
+fn header_value() -> impl Strategy<Value = HeaderValue> {
+    prop_oneof![
+        prop::collection::vec(challenge(), 1..4)
+            .prop_map(|challenges| challenges.join(", ").into_bytes()),
+        any::<String>().prop_map(String::into_bytes),
+        prop::collection::vec(any::<u8>(), 0..64),
+    ]
+    .prop_filter_map("invalid header value", |bytes| {
+        HeaderValue::from_bytes(&bytes).ok()
+    })
+}
+
+/// Bearer challenges with a usable token server, and arbitrary parameters.
+#[cfg(feature = "oci")]
+fn bearer_challenge() -> impl Strategy<Value = HeaderValue> {
+    prop::collection::vec(("[a-z]{1,8}", param_value()), 0..3).prop_filter_map(
+        "invalid header value",
+        |params| {
+            let mut challenge = "Bearer realm=\"http://localhost/token\"".to_owned();
+            for (name, value) in params {
+                challenge.push_str(&format!(",{}={}", name, value));
+            }
+            HeaderValue::from_str(&challenge).ok()
+        },
+    )
+}
+
+fn response(status: u16, headers: &[HeaderValue]) -> Response<Body> {
+    let mut builder = Response::builder().status(status);
+    for value in headers {
+        builder = builder.header(http::header::WWW_AUTHENTICATE, value.clone());
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+proptest! {
+    /// No challenge sent by a server causes HTTP authentication to panic.
+    #[test]
+    fn test_http_authentication_challenges(
+        status in prop_oneof![Just(401u16), 100u16..600],
+        headers in prop::collection::vec(header_value(), 0..3),
+    ) {
+        let mut realm_credentials = HashMap::new();
+        realm_credentials.insert(
+            "Fake Realm".into(),
+            Arc::new(UsernamePasswordCredential::new("username", "password")),
+        );
+        let credential = Arc::new(HttpRealmCredentials::new(realm_credentials));
+        let mut authentication = HttpAuthentication::new(credential);
+
+        for _ in 0..3 {
+            let _ = authentication.step();
+            authentication.respond(Ok(response(status, &headers)));
+            let _ = authentication.configure(hyper::Request::get("http://localhost/"));
+            let _ = authentication.has_completed(&response(status, &headers));
+        }
+    }
+}
+
+#[cfg(feature = "oci")]
+proptest! {
+    /// No challenge or token server response causes OCI registry authentication to panic.
+    #[test]
+    fn test_oci_registry_challenges(
+        status in prop_oneof![Just(401u16), 100u16..600],
+        headers in prop_oneof![
+            bearer_challenge().prop_map(|challenge| vec![challenge]),
+            prop::collection::vec(header_value(), 0..3),
+        ],
+        token_status in prop_oneof![Just(200u16), 100u16..600],
+        token_body in prop_oneof![
+            Just(r#"{"token": "registry-token", "expires_in": 18446744073709551615}"#.to_owned()),
+            "\\{\"(access_)?token\": \"[a-z]{0,8}\"(, \"expires_in\": -?[0-9]{1,22})?\\}",
+            any::<String>(),
+        ],
+    ) {
+        use authentic::credential::OciRegistryCredentials;
+        use authentic::hyper::OciRegistryAuthentication;
+        use authentic::AuthenticationStep;
+
+        let credential = Arc::new(OciRegistryCredentials::new(Arc::new(
+            UsernamePasswordCredential::new("username", "password"),
+        )));
+        let mut authentication = OciRegistryAuthentication::new(credential);
+
+        for _ in 0..3 {
+            if let Ok(Some(AuthenticationStep::Request(_))) = authentication.step() {
+                let token_response = Response::builder()
+                    .status(token_status)
+                    .body(Body::from(token_body.clone()))
+                    .unwrap();
+                authentication.respond(Ok(token_response));
+            }
+            let _ = authentication.configure(hyper::Request::get("http://localhost/"));
+            let _ = authentication.has_completed(&response(status, &headers));
+        }
+    }
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "hyper")]
+
+use std::sync::Arc;
+
+use authentic::credential::{TokenCredential, UsernamePasswordCredential};
+use authentic::hyper::{
+    AnyAuthentication, BasicAuthentication, BearerAuthentication, DynAuthentication,
+    HeaderAuthentication, NoAuthentication,
+};
+use authentic::{
+    AuthenticError, AuthenticationProtocol, DynAuthenticationProtocol, WithAuthentication,
+};
+use hyper::{Body, Request};
+
+/// Select a protocol using a name only known at runtime.
+fn select(name: &str) -> AnyAuthentication {
+    let token = Arc::new(TokenCredential::new(b"token".to_vec()));
+    match name {
+        "header" => HeaderAuthentication::new("x-api-key", token).into(),
+        "bearer" => BearerAuthentication::new(token).into(),
+        "basic" => {
+            BasicAuthentication::new(Arc::new(UsernamePasswordCredential::new("user", "pass")))
+                .into()
+        }
+        _ => NoAuthentication::new().into(),
+    }
+}
+
+fn configured_header(
+    protocol: &AnyAuthentication,
+    name: &str,
+) -> Result<Option<String>, AuthenticError> {
+    let request = Request::builder()
+        .uri("https://example.com/")
+        .with_authentication(protocol)?
+        .body(Body::empty())?;
+    Ok(request
+        .headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap().to_owned()))
+}
+
+#[test]
+fn test_any_authentication() -> Result<(), AuthenticError> {
+    assert_eq!(
+        configured_header(&select("header"), "x-api-key")?.as_deref(),
+        Some("token")
+    );
+    assert_eq!(
+        configured_header(&select("bearer"), "authorization")?.as_deref(),
+        Some("Bearer token")
+    );
+    assert_eq!(
+        configured_header(&select("basic"), "authorization")?.as_deref(),
+        Some("Basic dXNlcjpwYXNz")
+    );
+    assert_eq!(configured_header(&select("none"), "authorization")?, None);
+    assert!(select("basic").step()?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_boxed_authentication() -> Result<(), AuthenticError> {
+    let token = Arc::new(TokenCredential::new(b"token".to_vec()));
+    let protocols: Vec<Box<DynAuthentication>> = vec![
+        Box::new(NoAuthentication::new()),
+        Box::new(BearerAuthentication::new(token.clone()).with_auth_scheme("Token")),
+        Box::new(select("header")),
+    ];
+    let mut headers = Vec::new();
+    for mut protocol in protocols {
+        assert!(protocol.step()?.is_none());
+        let request = Request::get("https://example.com/")
+            .body(Body::empty())?
+            .with_authentication(&protocol)?;
+        let response = hyper::Response::new(Body::empty());
+        assert!(protocol.has_completed(&response)?);
+        headers.push(request.headers().len());
+    }
+    assert_eq!(headers, vec![0, 1, 1]);
+    Ok(())
+}
+
+/// Configuring a request that already has credentials replaces them.
+#[test]
+fn test_dyn_configure_replaces_header() -> Result<(), AuthenticError> {
+    let protocol = select("bearer");
+    let request = Request::get("https://example.com/")
+        .header("authorization", "Bearer stale")
+        .body(Body::empty())?;
+    let request = protocol.dyn_configure(request)?;
+    let request = protocol.dyn_configure(request)?;
+    let values = request
+        .headers()
+        .get_all("authorization")
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(values, ["Bearer token"]);
+    Ok(())
+}
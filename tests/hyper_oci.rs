@@ -0,0 +1,131 @@
+#![cfg(all(feature = "hyper", feature = "oci"))]
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use authentic::credential::{OciRegistryCredentials, UsernamePasswordCredential};
+use authentic::hyper::{buffer_response, OciRegistryAuthentication};
+use authentic::{AuthenticationProtocol, AuthenticationStep, WithAuthentication};
+use http::StatusCode;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Request, Response, Server};
+
+/// Minimal registry: `/v2/` requires a token from `/token`, which requires Basic credentials.
+async fn registry(
+    addr: SocketAddr,
+    token_requests: Arc<AtomicUsize>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let authorization = request
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned);
+    let response = match request.uri().path() {
+        "/token" => {
+            token_requests.fetch_add(1, Ordering::SeqCst);
+            let expected = http_auth::basic::encode_credentials("username", "password");
+            let query = request.uri().query().unwrap_or_default();
+            if authorization.as_deref() == Some(expected.as_str())
+                && query == "service=registry.test&scope=repository%3Asamalba%2Fmy-app%3Apull"
+            {
+                Response::new(Body::from(
+                    r#"{"token": "registry-token", "expires_in": 300}"#,
+                ))
+            } else {
+                Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        }
+        _ => {
+            if authorization.as_deref() == Some("Bearer registry-token") {
+                Response::new(Body::from("{}"))
+            } else {
+                Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header(
+                        http::header::WWW_AUTHENTICATE,
+                        format!(
+                            r#"Bearer realm="http://{}/token",service="registry.test",scope="repository:samalba/my-app:pull""#,
+                            addr
+                        ),
+                    )
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        }
+    };
+    Ok(response)
+}
+
+/// Bearer token obtained from the registry token server in response to a challenge.
+#[::tokio::test]
+async fn test_oci_registry() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let token_requests = Arc::new(AtomicUsize::new(0));
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let counter = token_requests.clone();
+    let server = Server::from_tcp(listener)?.serve(make_service_fn(move |_| {
+        let counter = counter.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                registry(addr, counter.clone(), request)
+            }))
+        }
+    }));
+    ::tokio::spawn(server);
+
+    let client = Client::new();
+
+    let credential = Arc::new(OciRegistryCredentials::new(Arc::new(
+        UsernamePasswordCredential::new("username", "password"),
+    )));
+
+    for expected_status_codes in [
+        [StatusCode::UNAUTHORIZED, StatusCode::OK],
+        [StatusCode::UNAUTHORIZED, StatusCode::OK],
+    ] {
+        let mut authentication = OciRegistryAuthentication::new(credential.clone());
+
+        let mut status_codes = Vec::new();
+
+        let _response = loop {
+            while let Some(auth_step) = authentication.step()? {
+                match auth_step {
+                    AuthenticationStep::Request(request) => {
+                        let auth_response = buffer_response(client.request(request).await).await;
+                        authentication.respond(auth_response);
+                    }
+                    AuthenticationStep::WaitFor(duration) => {
+                        ::tokio::time::sleep(duration).await;
+                    }
+                    AuthenticationStep::WaitForRenewal(waiter) => {
+                        let _ = ::tokio::time::timeout(waiter.timeout(), waiter).await;
+                    }
+                }
+            }
+            let request = ::hyper::Request::get(format!("http://{}/v2/", addr))
+                .with_authentication(&authentication)?
+                .body(::hyper::Body::empty())?;
+
+            let response = client.request(request).await?;
+
+            status_codes.push(response.status());
+
+            if authentication.has_completed(&response)? {
+                break response;
+            }
+        };
+
+        assert_eq!(status_codes, expected_status_codes);
+    }
+
+    // The second request uses the token cached for the scope.
+    assert_eq!(token_requests.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
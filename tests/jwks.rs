@@ -0,0 +1,200 @@
+#![cfg(all(feature = "jwt", feature = "step"))]
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use authentic::credential::{AuthenticationCredential, JwksCache, ManualClock};
+use authentic::{AuthenticError, AuthenticationStep};
+
+const JWKS: &str = r#"{"keys": [
+    {"kty": "oct", "kid": "first", "alg": "HS256", "k": "Zmlyc3Qtc2VjcmV0"},
+    {"kty": "oct", "kid": "encryption", "use": "enc", "k": "Zmlyc3Qtc2VjcmV0"},
+    {"kty": "unknown", "kid": "unsupported"}
+]}"#;
+
+const ROTATED_JWKS: &str = r#"{"keys": [
+    {"kty": "oct", "kid": "first", "alg": "HS256", "k": "Zmlyc3Qtc2VjcmV0"},
+    {"kty": "oct", "kid": "second", "alg": "HS256", "k": "c2Vjb25kc2VjcmV0"}
+]}"#;
+
+fn jwks_response(body: &str, cache_control: Option<&str>) -> http::Response<Vec<u8>> {
+    let mut response = http::Response::builder();
+    if let Some(cache_control) = cache_control {
+        response = response.header(http::header::CACHE_CONTROL, cache_control);
+    }
+    response.body(body.as_bytes().to_vec()).unwrap()
+}
+
+/// Return the request made by the next step, if any.
+fn next_request(cache: &JwksCache) -> Result<Option<http::Request<Vec<u8>>>, AuthenticError> {
+    match cache.auth_step()? {
+        None => Ok(None),
+        Some(AuthenticationStep::Request(request)) => Ok(Some(request)),
+        Some(_) => panic!("Unexpected wait"),
+    }
+}
+
+#[test]
+fn test_max_age() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let cache = JwksCache::new("https://issuer.example.com/jwks.json").with_clock(clock.clone());
+    assert!(cache.fetch().is_err());
+
+    let request = next_request(&cache)?.expect("initial request");
+    assert_eq!(request.uri(), "https://issuer.example.com/jwks.json");
+    // Another caller waits for the outstanding request.
+    assert!(matches!(
+        cache.auth_step()?,
+        Some(AuthenticationStep::WaitForRenewal(_))
+    ));
+    cache.auth_respond(Ok(jwks_response(JWKS, Some("public, max-age=300"))));
+
+    let keys = cache.fetch()?;
+    assert_eq!(keys.len(), 1);
+    assert!(keys.key(Some("first")).is_some());
+    assert!(keys.key(Some("encryption")).is_none());
+    assert!(keys.key(None).is_none());
+
+    clock.advance(Duration::from_secs(299));
+    assert!(next_request(&cache)?.is_none());
+    clock.advance(Duration::from_secs(1));
+    assert!(next_request(&cache)?.is_some());
+    Ok(())
+}
+
+/// A very large `max-age` keeps the key set for at most a day.
+#[test]
+fn test_max_age_limit() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let cache = JwksCache::new("https://issuer.example.com/jwks.json").with_clock(clock.clone());
+    next_request(&cache)?.expect("initial request");
+    cache.auth_respond(Ok(jwks_response(
+        JWKS,
+        Some("max-age=18446744073709551615"),
+    )));
+    assert!(cache.fetch()?.key(Some("first")).is_some());
+
+    clock.advance(Duration::from_secs(24 * 60 * 60 - 1));
+    assert!(next_request(&cache)?.is_none());
+    clock.advance(Duration::from_secs(1));
+    assert!(next_request(&cache)?.is_some());
+    Ok(())
+}
+
+#[test]
+fn test_unknown_key_rate_limited() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let cache = JwksCache::new("https://issuer.example.com/jwks.json")
+        .with_clock(clock.clone())
+        .with_min_refresh_interval(Duration::from_secs(30));
+    next_request(&cache)?.expect("initial request");
+    cache.auth_respond(Ok(jwks_response(JWKS, None)));
+    assert!(cache.fetch()?.key(Some("second")).is_none());
+
+    // An unknown key does not cause a request within the minimum interval.
+    cache.unknown_key();
+    assert!(next_request(&cache)?.is_none());
+    clock.advance(Duration::from_secs(30));
+    next_request(&cache)?.expect("request for unknown key");
+    cache.auth_respond(Ok(jwks_response(ROTATED_JWKS, None)));
+    assert!(cache.fetch()?.key(Some("second")).is_some());
+
+    // Without an unknown key, the key set is kept for the default max age.
+    clock.advance(Duration::from_secs(60 * 30));
+    assert!(next_request(&cache)?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_error() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let cache = JwksCache::new("https://issuer.example.com/jwks.json").with_clock(clock.clone());
+    next_request(&cache)?.expect("initial request");
+    let response = http::Response::builder()
+        .status(http::StatusCode::SERVICE_UNAVAILABLE)
+        .body(Vec::new())
+        .unwrap();
+    cache.auth_respond(Ok(response));
+    assert!(matches!(
+        cache.auth_step(),
+        Err(AuthenticError::TokenEndpoint { .. })
+    ));
+    // The request is retried after the minimum refresh interval.
+    assert!(next_request(&cache)?.is_none());
+    clock.advance(Duration::from_secs(60));
+    assert!(next_request(&cache)?.is_some());
+    Ok(())
+}
+
+/// A failed refresh keeps the current key set, without returning the error to callers.
+#[test]
+fn test_error_keeps_key_set() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let cache = JwksCache::new("https://issuer.example.com/jwks.json")
+        .with_clock(clock.clone())
+        .with_min_refresh_interval(Duration::from_secs(30));
+    next_request(&cache)?.expect("initial request");
+    cache.auth_respond(Ok(jwks_response(JWKS, Some("max-age=300"))));
+
+    clock.advance(Duration::from_secs(300));
+    next_request(&cache)?.expect("refresh request");
+    let response = http::Response::builder()
+        .status(http::StatusCode::SERVICE_UNAVAILABLE)
+        .body(Vec::new())
+        .unwrap();
+    cache.auth_respond(Ok(response));
+    assert!(next_request(&cache)?.is_none());
+    assert!(cache.fetch()?.key(Some("first")).is_some());
+
+    // The refresh is retried after the minimum refresh interval.
+    clock.advance(Duration::from_secs(29));
+    assert!(next_request(&cache)?.is_none());
+    clock.advance(Duration::from_secs(1));
+    assert!(next_request(&cache)?.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "verifier")]
+#[tokio::test]
+async fn test_verifier() -> Result<(), AuthenticError> {
+    use authentic::verifier::{JwtVerifier, Verifier};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+    let cache = Arc::new(
+        JwksCache::new("https://issuer.example.com/jwks.json")
+            .with_min_refresh_interval(Duration::ZERO),
+    );
+    let verifier: JwtVerifier = JwtVerifier::from_jwks("api", cache.clone(), Algorithm::HS256);
+
+    let body = std::sync::Mutex::new(JWKS);
+    let send = |_request| {
+        let response = jwks_response(*body.lock().unwrap(), None);
+        async move { Ok(response) }
+    };
+    cache.refresh_with(&send).await?;
+
+    let token = |kid: &str, secret: &[u8]| {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(kid.to_owned());
+        let claims = serde_json::json!({"exp": 4_000_000_000u64});
+        let token = jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(secret))?;
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", token).parse()?,
+        );
+        Ok::<_, AuthenticError>(headers)
+    };
+
+    assert!(verifier.verify(&token("first", b"first-secret")?).is_ok());
+    assert!(verifier.verify(&token("first", b"wrong")?).is_err());
+
+    // A token using a new key is rejected until the key set is fetched again.
+    let rotated = token("second", b"secondsecret")?;
+    let rejection = verifier.verify(&rotated).unwrap_err();
+    assert_eq!(rejection.description(), r#"Unknown key ID "second""#);
+    *body.lock().unwrap() = ROTATED_JWKS;
+    cache.refresh_with(&send).await?;
+    assert!(verifier.verify(&rotated).is_ok());
+    Ok(())
+}
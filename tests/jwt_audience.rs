@@ -0,0 +1,194 @@
+#![cfg(all(feature = "jwt", feature = "step"))]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use authentic::credential::{JsonWebTokenCredential, JwtAudienceCredentials, ManualClock};
+use authentic::AuthenticError;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+
+/// Create credentials with an audience claim, counting the credentials created.
+fn credentials(
+    capacity: usize,
+    clock: Arc<ManualClock>,
+) -> (JwtAudienceCredentials, Arc<AtomicUsize>) {
+    let created = Arc::new(AtomicUsize::new(0));
+    let counter = created.clone();
+    let credentials = JwtAudienceCredentials::new(capacity, move |audience| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(JsonWebTokenCredential::new(
+            Header::new(Algorithm::HS256),
+            EncodingKey::from_secret(b"secret"),
+            Duration::from_secs(60),
+        )
+        .with_clock(clock.clone())
+        .with_audience(audience.to_owned()))
+    });
+    (credentials, created)
+}
+
+fn claims(token: &[u8]) -> serde_json::Value {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.validate_exp = false;
+    jsonwebtoken::decode::<serde_json::Value>(
+        std::str::from_utf8(token).unwrap(),
+        &DecodingKey::from_secret(b"secret"),
+        &validation,
+    )
+    .unwrap()
+    .claims
+}
+
+#[test]
+fn test_audience_per_origin() -> Result<(), AuthenticError> {
+    use authentic::credential::FetchedToken;
+
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let (credentials, created) = credentials(10, clock.clone());
+
+    let first = credentials.token("https://first.example.com/v1/items?page=2")?;
+    assert_eq!(claims(first.token())["aud"], "https://first.example.com/");
+    let second = credentials.token("https://second.example.com:8443/")?;
+    assert_eq!(
+        claims(second.token())["aud"],
+        "https://second.example.com:8443/"
+    );
+
+    // Requests to the same origin share a token until it is renewed.
+    let again = credentials.token("https://first.example.com/v2/other")?;
+    assert_eq!(again.token(), first.token());
+    assert_eq!(credentials.len(), 2);
+    assert_eq!(created.load(Ordering::SeqCst), 2);
+
+    clock.advance(Duration::from_secs(30));
+    let renewed = credentials.token("https://first.example.com/")?;
+    assert_ne!(renewed.token(), first.token());
+    assert_eq!(claims(renewed.token())["aud"], "https://first.example.com/");
+
+    assert!(matches!(
+        credentials.token("/relative"),
+        Err(AuthenticError::Configuration(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_least_recently_used() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let (credentials, created) = credentials(2, clock);
+
+    credentials.token("https://a.example.com/")?;
+    credentials.token("https://b.example.com/")?;
+    credentials.token("https://a.example.com/")?;
+    // Adding a third audience removes `b`, the least recently used.
+    credentials.token("https://c.example.com/")?;
+    assert_eq!(credentials.len(), 2);
+    assert_eq!(created.load(Ordering::SeqCst), 3);
+    credentials.token("https://a.example.com/")?;
+    assert_eq!(created.load(Ordering::SeqCst), 3);
+    credentials.token("https://b.example.com/")?;
+    assert_eq!(created.load(Ordering::SeqCst), 4);
+    Ok(())
+}
+
+/// Creating a credential for one audience does not block requests to other audiences.
+#[test]
+fn test_slow_create() -> Result<(), AuthenticError> {
+    let (created, slow_created) = std::sync::mpsc::channel::<()>();
+    let slow_created = std::sync::Mutex::new(slow_created);
+    let credentials = Arc::new(JwtAudienceCredentials::new(10, move |audience| {
+        if audience == "https://slow.example.com/" {
+            // Wait until a credential for another audience has been created.
+            slow_created
+                .lock()
+                .unwrap()
+                .recv_timeout(Duration::from_secs(5))
+                .map_err(|_| AuthenticError::Other("Blocked by slow audience".to_owned()))?;
+        }
+        Ok(JsonWebTokenCredential::new(
+            Header::new(Algorithm::HS256),
+            EncodingKey::from_secret(b"secret"),
+            Duration::from_secs(60),
+        )
+        .with_audience(audience.to_owned()))
+    }));
+
+    let slow = std::thread::spawn({
+        let credentials = credentials.clone();
+        move || credentials.token("https://slow.example.com/").map(|_| ())
+    });
+    // Give the slow audience time to start creating its credential.
+    std::thread::sleep(Duration::from_millis(50));
+    credentials.token("https://fast.example.com/")?;
+    created.send(()).unwrap();
+    slow.join().unwrap()?;
+    assert_eq!(credentials.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_audience_resolver() -> Result<(), AuthenticError> {
+    use authentic::credential::FetchedToken;
+
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let (credentials, _) = credentials(10, clock);
+    let credentials = credentials.with_audience_resolver(|url| {
+        url.strip_prefix("https://api.example.com/")
+            .and_then(|path| path.split('/').next())
+            .map(|service| format!("api://{}", service))
+    });
+
+    let token = credentials.token("https://api.example.com/storage/buckets")?;
+    assert_eq!(claims(token.token())["aud"], "api://storage");
+    assert!(credentials.token("https://other.example.com/").is_err());
+    Ok(())
+}
+
+#[cfg(feature = "hyper")]
+#[test]
+fn test_hyper_configure() -> Result<(), AuthenticError> {
+    use authentic::hyper::JwtAudienceAuthentication;
+    use authentic::{AuthenticationProtocol, WithAuthentication};
+
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let (credentials, _) = credentials(10, clock);
+    let authentication = JwtAudienceAuthentication::new(Arc::new(credentials));
+    assert!(authentication.step()?.is_none());
+
+    let request = http::Request::get("https://api.example.com/items")
+        .with_authentication(&authentication)?
+        .body(())?;
+    let authorization = request.headers()[http::header::AUTHORIZATION]
+        .to_str()
+        .unwrap();
+    let token = authorization.strip_prefix("Bearer ").unwrap();
+    assert_eq!(claims(token.as_bytes())["aud"], "https://api.example.com/");
+    Ok(())
+}
+
+#[cfg(feature = "reqwest-async")]
+#[test]
+fn test_reqwest_configure() -> Result<(), AuthenticError> {
+    use authentic::reqwest::JwtAudienceAuthentication;
+    use authentic::WithAuthentication;
+
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let (credentials, _) = credentials(10, clock);
+    let authentication =
+        JwtAudienceAuthentication::new(Arc::new(credentials)).with_auth_scheme("JWT");
+
+    let request = reqwest::Client::new()
+        .get("https://api.example.com:8443/items")
+        .with_authentication(&authentication)?
+        .build()?;
+    let authorization = request.headers()[reqwest::header::AUTHORIZATION]
+        .to_str()
+        .unwrap();
+    let token = authorization.strip_prefix("JWT ").unwrap();
+    assert_eq!(
+        claims(token.as_bytes())["aud"],
+        "https://api.example.com:8443/"
+    );
+    Ok(())
+}
@@ -0,0 +1,165 @@
+#![cfg(all(feature = "jwt", feature = "step"))]
+
+use std::time::Duration;
+
+use authentic::credential::{
+    AuthenticationCredential, FetchedToken, JsonWebTokenCredential, JwtPrivateKey,
+};
+use authentic::AuthenticError;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+
+fn key_path(name: &str) -> String {
+    format!("{}/tests/keys/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn public_key(name: &str) -> DecodingKey {
+    let pem = std::fs::read(key_path(name)).unwrap();
+    match name {
+        name if name.starts_with("rsa") => DecodingKey::from_rsa_pem(&pem),
+        name if name.starts_with("ec") => DecodingKey::from_ec_pem(&pem),
+        _ => DecodingKey::from_ed_pem(&pem),
+    }
+    .unwrap()
+}
+
+/// Create a token using `key`, and check it using the public key in file `public`.
+fn assert_signs(key: JwtPrivateKey, public: &str) -> Result<jsonwebtoken::Header, AuthenticError> {
+    let algorithm = key.algorithm();
+    let credential = JsonWebTokenCredential::from_private_key(key, Duration::from_secs(60));
+    assert!(credential.auth_step()?.is_none());
+    let fetched = credential.fetch()?;
+    let token = std::str::from_utf8(fetched.token()).unwrap();
+    let decoded = jsonwebtoken::decode::<serde_json::Value>(
+        token,
+        &public_key(public),
+        &Validation::new(algorithm),
+    )?;
+    Ok(decoded.header)
+}
+
+#[test]
+fn test_pem() -> Result<(), AuthenticError> {
+    let cases = [
+        ("rsa.pem", Algorithm::RS256, "rsa_public.pem"),
+        ("rsa_pkcs1.pem", Algorithm::RS256, "rsa_public.pem"),
+        ("ec_p256.pem", Algorithm::ES256, "ec_p256_public.pem"),
+        ("ec_p384.pem", Algorithm::ES384, "ec_p384_public.pem"),
+        ("ed25519.pem", Algorithm::EdDSA, "ed25519_public.pem"),
+    ];
+    for (name, algorithm, public) in &cases {
+        let key = JwtPrivateKey::from_file(key_path(name))?;
+        assert_eq!(key.algorithm(), *algorithm, "{}", name);
+        assert_eq!(key.key_id(), None);
+        assert_signs(key, public)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_der() -> Result<(), AuthenticError> {
+    let der = std::fs::read(key_path("ec_p256.der"))?;
+    let key = JwtPrivateKey::from_bytes(&der)?.with_key_id("ec");
+    assert_eq!(key.algorithm(), Algorithm::ES256);
+    let header = assert_signs(key, "ec_p256_public.pem")?;
+    assert_eq!(header.kid.as_deref(), Some("ec"));
+    Ok(())
+}
+
+/// Encrypted PKCS#8 keys are decrypted using a password.
+#[test]
+fn test_encrypted() -> Result<(), AuthenticError> {
+    let pem = std::fs::read(key_path("encrypted.pem"))?;
+    let key = JwtPrivateKey::from_encrypted_pem(&pem, "password")?;
+    assert_eq!(key.algorithm(), Algorithm::ES256);
+    assert_signs(key, "ec_p256_public.pem")?;
+    Ok(())
+}
+
+#[test]
+fn test_jwk() -> Result<(), AuthenticError> {
+    // The algorithm is taken from the JWK.
+    let key = JwtPrivateKey::from_file(key_path("rsa.jwk"))?;
+    assert_eq!(key.algorithm(), Algorithm::PS256);
+    let header = assert_signs(key, "rsa_public.pem")?;
+    assert_eq!(header.alg, Algorithm::PS256);
+    assert_eq!(header.kid.as_deref(), Some("rsa"));
+
+    let jwks = std::fs::read(key_path("jwks.json"))?;
+    let cases = [
+        ("rsa", Algorithm::RS256, "rsa_public.pem"),
+        ("ec", Algorithm::ES256, "ec_p256_public.pem"),
+        ("ed", Algorithm::EdDSA, "ed25519_public.pem"),
+    ];
+    for (kid, algorithm, public) in &cases {
+        let key = JwtPrivateKey::from_jwks(&jwks, kid)?;
+        assert_eq!(key.algorithm(), *algorithm, "{}", kid);
+        let header = assert_signs(key, public)?;
+        assert_eq!(header.kid.as_deref(), Some(*kid));
+    }
+
+    let key = JwtPrivateKey::from_jwk(br#"{"kty": "oct", "kid": "hmac", "k": "c2VjcmV0"}"#)?;
+    assert_eq!(key.algorithm(), Algorithm::HS256);
+    assert_eq!(key.key_id(), Some("hmac"));
+    Ok(())
+}
+
+#[test]
+fn test_credential() -> Result<(), AuthenticError> {
+    let credential =
+        JsonWebTokenCredential::from_key_file(key_path("ed25519.pem"), Duration::from_secs(60))?;
+    assert!(credential.auth_step()?.is_none());
+    let fetched = credential.fetch()?;
+    let token = std::str::from_utf8(fetched.token()).unwrap();
+    assert_eq!(jsonwebtoken::decode_header(token)?.alg, Algorithm::EdDSA);
+
+    let pem = std::fs::read(key_path("rsa.pem"))?;
+    assert!(JsonWebTokenCredential::from_key_bytes(&pem, Duration::from_secs(60)).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_errors() -> Result<(), AuthenticError> {
+    let error = |result: Result<JwtPrivateKey, AuthenticError>| match result {
+        Err(AuthenticError::Configuration(message)) => message,
+        Err(err) => panic!("Unexpected error {:?}", err),
+        Ok(_) => panic!("Key loaded"),
+    };
+
+    assert_eq!(
+        error(JwtPrivateKey::from_file(key_path("encrypted.pem"))),
+        "Private key is encrypted, load it using JwtPrivateKey::from_encrypted_pem or \
+         JwtPrivateKey::from_encrypted_der"
+    );
+    let encrypted = std::fs::read(key_path("encrypted.pem"))?;
+    assert!(
+        error(JwtPrivateKey::from_encrypted_pem(&encrypted, "wrong"))
+            .starts_with("Cannot decrypt private key")
+    );
+    let pem = std::fs::read(key_path("ec_p256.pem"))?;
+    assert_eq!(
+        error(JwtPrivateKey::from_encrypted_pem(&pem, "password")),
+        "PEM EC PRIVATE KEY is not an encrypted private key"
+    );
+    assert_eq!(
+        error(JwtPrivateKey::from_file(key_path("rsa_public.pem"))),
+        "PEM PUBLIC KEY is not a private key"
+    );
+    let jwks = std::fs::read(key_path("jwks.json"))?;
+    assert_eq!(
+        error(JwtPrivateKey::from_jwks(&jwks, "public")),
+        "JWK is a public key, a private key is required"
+    );
+    assert_eq!(
+        error(JwtPrivateKey::from_jwk(&jwks)),
+        "JWKS contains several private keys, use JwtPrivateKey::from_jwks to select one"
+    );
+    assert_eq!(
+        error(JwtPrivateKey::from_file(key_path("ec_p256.pem"))?.with_algorithm(Algorithm::RS256)),
+        "Algorithm RS256 cannot be used with a P-256 EC key"
+    );
+    assert!(matches!(
+        JwtPrivateKey::from_file(key_path("missing.pem")),
+        Err(AuthenticError::Io(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,100 @@
+#![cfg(all(feature = "jwt", feature = "step"))]
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use authentic::credential::{
+    AuthenticationCredential, Clock, FetchedToken, JsonWebTokenCredential, JwtKeySet, ManualClock,
+};
+use authentic::AuthenticError;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+
+fn key_set() -> Arc<JwtKeySet> {
+    Arc::new(JwtKeySet::new(
+        "first",
+        Header::new(Algorithm::HS256),
+        EncodingKey::from_secret(b"first-secret"),
+    ))
+}
+
+/// Create a token if due, returning the current token.
+fn token(credential: &JsonWebTokenCredential) -> Result<String, AuthenticError> {
+    assert!(credential.auth_step()?.is_none());
+    let fetched = credential.fetch()?;
+    Ok(String::from_utf8(fetched.token().to_vec()).unwrap())
+}
+
+/// Check the `kid` header and signature of `token`.
+fn assert_signed(token: &str, kid: &str, secret: &[u8]) {
+    let header = jsonwebtoken::decode_header(token).unwrap();
+    assert_eq!(header.kid.as_deref(), Some(kid));
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.validate_exp = false;
+    jsonwebtoken::decode::<serde_json::Value>(
+        token,
+        &DecodingKey::from_secret(secret),
+        &validation,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_rotate_to() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let keys = key_set();
+    let credential = JsonWebTokenCredential::from_key_set(keys.clone(), Duration::from_secs(60))
+        .with_clock(clock.clone());
+    let first = token(&credential)?;
+    assert_signed(&first, "first", b"first-secret");
+
+    keys.add_key(
+        "second",
+        Header::new(Algorithm::HS256),
+        EncodingKey::from_secret(b"secondsecret"),
+    )?;
+    keys.rotate_to("second")?;
+    assert_eq!(keys.current_key_id(clock.now())?, "second");
+
+    // The current token is used until it is renewed.
+    assert_eq!(token(&credential)?, first);
+    clock.advance(Duration::from_secs(30));
+    let second = token(&credential)?;
+    assert_signed(&second, "second", b"secondsecret");
+
+    // The current key cannot be removed.
+    assert!(keys.remove_key("second").is_err());
+    keys.remove_key("first")?;
+    assert!(keys.rotate_to("first").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_schedule_rotation() -> Result<(), AuthenticError> {
+    let start = SystemTime::now();
+    let clock = Arc::new(ManualClock::new(start));
+    let keys = key_set();
+    let credential = JsonWebTokenCredential::from_key_set(keys.clone(), Duration::from_secs(60))
+        .with_clock(clock.clone());
+    let day = Duration::from_secs(24 * 60 * 60);
+    keys.add_key(
+        "second",
+        Header::new(Algorithm::HS256),
+        EncodingKey::from_secret(b"secondsecret"),
+    )?;
+    keys.schedule_rotation("second", start + day * 30)?;
+    // A key with a scheduled rotation cannot be removed.
+    assert!(keys.remove_key("second").is_err());
+    assert!(keys.schedule_rotation("third", start + day * 60).is_err());
+
+    assert_signed(&token(&credential)?, "first", b"first-secret");
+    clock.advance(day * 30 - Duration::from_secs(1));
+    assert_signed(&token(&credential)?, "first", b"first-secret");
+    // The rotation takes effect when the token is next renewed.
+    clock.advance(Duration::from_secs(1));
+    assert_signed(&token(&credential)?, "first", b"first-secret");
+    assert_eq!(keys.current_key_id(clock.now())?, "second");
+    clock.advance(Duration::from_secs(29));
+    assert_signed(&token(&credential)?, "second", b"secondsecret");
+    keys.remove_key("first")?;
+    Ok(())
+}
@@ -0,0 +1,181 @@
+#![cfg(all(feature = "jwt", feature = "step"))]
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use authentic::credential::{
+    AuthenticationCredential, EncodingKeySigner, FetchedToken, JsonWebTokenCredential, JwtKeySet,
+    JwtSigner, ManualClock, RenewalPolicy,
+};
+use authentic::AuthenticError;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+
+/// Signer standing in for a remote signing service, counting signatures.
+struct RemoteSigner {
+    inner: EncodingKeySigner,
+    available: AtomicBool,
+    signatures: AtomicUsize,
+}
+
+impl RemoteSigner {
+    fn new(secret: &[u8], available: bool) -> Self {
+        Self {
+            inner: EncodingKeySigner::new(Algorithm::HS256, EncodingKey::from_secret(secret)),
+            available: AtomicBool::new(available),
+            signatures: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl JwtSigner for RemoteSigner {
+    fn algorithm(&self) -> Algorithm {
+        self.inner.algorithm()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, AuthenticError> {
+        if !self.available.load(Ordering::SeqCst) {
+            return Err(AuthenticError::CredentialUnavailable(
+                "Signing service unavailable".to_owned(),
+            ));
+        }
+        self.signatures.fetch_add(1, Ordering::SeqCst);
+        self.inner.sign(message)
+    }
+}
+
+fn token(credential: &JsonWebTokenCredential) -> Result<String, AuthenticError> {
+    assert!(credential.auth_step()?.is_none());
+    let fetched = credential.fetch()?;
+    Ok(String::from_utf8(fetched.token().to_vec()).unwrap())
+}
+
+#[test]
+fn test_external_signer() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let signer = Arc::new(RemoteSigner::new(b"secret", true));
+    // The algorithm is set by the signer.
+    let credential = JsonWebTokenCredential::from_signer(
+        Header::new(Algorithm::HS512),
+        signer.clone(),
+        Duration::from_secs(60),
+    )
+    .with_clock(clock.clone());
+    let external = token(&credential)?;
+    assert_eq!(signer.signatures.load(Ordering::SeqCst), 1);
+
+    // The token is the same as one signed in memory.
+    let credential = JsonWebTokenCredential::new(
+        Header::new(Algorithm::HS256),
+        EncodingKey::from_secret(b"secret"),
+        Duration::from_secs(60),
+    )
+    .with_clock(clock);
+    assert_eq!(external, token(&credential)?);
+
+    let claims = jsonwebtoken::decode::<serde_json::Value>(
+        &external,
+        &DecodingKey::from_secret(b"secret"),
+        &Validation::new(Algorithm::HS256),
+    )?;
+    assert!(claims.claims["exp"].is_u64());
+    Ok(())
+}
+
+#[test]
+fn test_signer_unavailable() {
+    let signer = Arc::new(RemoteSigner::new(b"secret", false));
+    let credential =
+        JsonWebTokenCredential::from_signer(Header::default(), signer, Duration::from_secs(60));
+    let err = match credential.auth_step() {
+        Err(err) => err,
+        Ok(_) => panic!("Token signed by unavailable signer"),
+    };
+    assert!(matches!(err, AuthenticError::CredentialUnavailable(_)));
+    assert!(err.is_retryable());
+    assert!(credential.fetch().is_err());
+}
+
+/// A signing failure is reported as a signing error.
+/// A signer failure while the current token is valid does not affect callers, and signing is
+/// retried after the retry interval.
+#[test]
+fn test_signer_failure_keeps_token() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let signer = Arc::new(RemoteSigner::new(b"secret", true));
+    let credential = JsonWebTokenCredential::from_signer(
+        Header::default(),
+        signer.clone(),
+        Duration::from_secs(60),
+    )
+    .with_renewal_policy(RenewalPolicy::new().with_retry_interval(Duration::from_secs(5)))
+    .with_clock(clock.clone());
+    let first = token(&credential)?;
+
+    signer.available.store(false, Ordering::SeqCst);
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(token(&credential)?, first);
+    assert_eq!(signer.signatures.load(Ordering::SeqCst), 1);
+
+    signer.available.store(true, Ordering::SeqCst);
+    clock.advance(Duration::from_secs(4));
+    assert_eq!(token(&credential)?, first);
+    clock.advance(Duration::from_secs(1));
+    assert_ne!(token(&credential)?, first);
+    assert_eq!(signer.signatures.load(Ordering::SeqCst), 2);
+
+    // Once the token expires, the error is returned.
+    signer.available.store(false, Ordering::SeqCst);
+    clock.advance(Duration::from_secs(60));
+    assert!(matches!(
+        credential.auth_step(),
+        Err(AuthenticError::CredentialUnavailable(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_key_set_signer() -> Result<(), AuthenticError> {
+    let keys = Arc::new(JwtKeySet::new(
+        "memory",
+        Header::new(Algorithm::HS256),
+        EncodingKey::from_secret(b"first-secret"),
+    ));
+    let signer = Arc::new(RemoteSigner::new(b"secondsecret", true));
+    keys.add_signer("remote", Header::default(), signer.clone())?;
+    keys.rotate_to("remote")?;
+    let credential = JsonWebTokenCredential::from_key_set(keys, Duration::from_secs(60));
+    let token = token(&credential)?;
+    assert_eq!(signer.signatures.load(Ordering::SeqCst), 1);
+    let header = jsonwebtoken::decode_header(&token)?;
+    assert_eq!(header.kid.as_deref(), Some("remote"));
+    jsonwebtoken::decode::<serde_json::Value>(
+        &token,
+        &DecodingKey::from_secret(b"secondsecret"),
+        &Validation::new(Algorithm::HS256),
+    )?;
+    Ok(())
+}
+
+/// Token times that cannot be represented are reported as configuration errors.
+#[test]
+fn test_time_overflow() {
+    for credential in [
+        JsonWebTokenCredential::new(
+            Header::default(),
+            EncodingKey::from_secret(b"secret"),
+            Duration::MAX,
+        ),
+        JsonWebTokenCredential::new(
+            Header::default(),
+            EncodingKey::from_secret(b"secret"),
+            Duration::from_secs(60),
+        )
+        .with_not_before(Duration::MAX),
+    ] {
+        assert!(matches!(
+            credential.auth_step(),
+            Err(AuthenticError::Configuration(_))
+        ));
+    }
+}
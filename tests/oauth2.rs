@@ -0,0 +1,370 @@
+#![cfg(feature = "oauth2")]
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use authentic::credential::{
+    AuthenticationCredential, FetchedToken, ManualClock, OAuth2ClientCredential, RenewalPolicy,
+};
+use authentic::{AuthenticError, AuthenticationStep};
+
+const TOKEN_URL: &str = "https://login.example.com/oauth2/token";
+
+/// Get the token request made by `credential`.
+fn token_request(credential: &OAuth2ClientCredential) -> http::Request<Vec<u8>> {
+    match credential.auth_step() {
+        Ok(Some(AuthenticationStep::Request(request))) => request,
+        Ok(_) => panic!("No token request"),
+        Err(err) => panic!("Unexpected error {:?}", err),
+    }
+}
+
+/// The form parameters of a token request.
+fn form(request: &http::Request<Vec<u8>>) -> Vec<(String, String)> {
+    form_urlencoded::parse(request.body())
+        .into_owned()
+        .collect()
+}
+
+fn param<'a>(form: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    form.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn token_response(body: &str) -> Result<http::Response<Vec<u8>>, AuthenticError> {
+    Ok(http::Response::new(body.as_bytes().to_vec()))
+}
+
+#[test]
+fn test_client_secret_basic() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let credential = OAuth2ClientCredential::client_secret_basic(TOKEN_URL, "client", "p@ss word")
+        .with_scope("read write")
+        .with_parameter("resource", "https://api.example.com/")
+        .with_clock(clock.clone());
+
+    let request = token_request(&credential);
+    assert_eq!(request.method(), http::Method::POST);
+    assert_eq!(request.uri(), TOKEN_URL);
+    assert_eq!(
+        request.headers()[http::header::CONTENT_TYPE],
+        "application/x-www-form-urlencoded"
+    );
+    // The secret is form-encoded before Basic encoding.
+    assert_eq!(
+        request.headers()[http::header::AUTHORIZATION],
+        http_auth::basic::encode_credentials("client", "p%40ss+word").as_str()
+    );
+    let form = form(&request);
+    assert_eq!(param(&form, "grant_type"), Some("client_credentials"));
+    assert_eq!(param(&form, "scope"), Some("read write"));
+    assert_eq!(param(&form, "resource"), Some("https://api.example.com/"));
+    assert_eq!(param(&form, "client_secret"), None);
+
+    credential.auth_respond(token_response(
+        r#"{"access_token": "token", "token_type": "Bearer", "expires_in": 600}"#,
+    ));
+    assert!(credential.auth_step()?.is_none());
+    assert_eq!(credential.fetch()?.token(), b"token");
+
+    // The token is renewed after half its lifetime.
+    clock.advance(Duration::from_secs(300));
+    assert!(matches!(
+        credential.auth_step()?,
+        Some(AuthenticationStep::Request(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_client_secret_post() {
+    let credential = OAuth2ClientCredential::client_secret_post(TOKEN_URL, "client", "secret");
+    let request = token_request(&credential);
+    assert!(request.headers().get(http::header::AUTHORIZATION).is_none());
+    let form = form(&request);
+    assert_eq!(param(&form, "client_id"), Some("client"));
+    assert_eq!(param(&form, "client_secret"), Some("secret"));
+}
+
+#[test]
+fn test_token_errors() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let credential = OAuth2ClientCredential::client_secret_post(TOKEN_URL, "client", "wrong")
+        .with_clock(clock.clone());
+    token_request(&credential);
+    let response = http::Response::builder()
+        .status(http::StatusCode::UNAUTHORIZED)
+        .body(br#"{"error": "invalid_client"}"#.to_vec())?;
+    credential.auth_respond(Ok(response));
+    match credential.auth_step() {
+        Err(AuthenticError::TokenEndpoint {
+            status,
+            oauth_error,
+        }) => {
+            assert_eq!(status, http::StatusCode::UNAUTHORIZED);
+            assert_eq!(oauth_error.as_deref(), Some("invalid_client"));
+        }
+        Err(err) => panic!("Unexpected error {:?}", err),
+        Ok(_) => panic!("Error not returned"),
+    }
+
+    // The request is retried after the retry interval.
+    assert!(credential.auth_step()?.is_none());
+    clock.advance(Duration::from_secs(10));
+    token_request(&credential);
+    credential.auth_respond(token_response(r#"{"token_type": "Bearer"}"#));
+    assert!(matches!(
+        credential.auth_step(),
+        Err(AuthenticError::TokenResponse(_))
+    ));
+
+    // An expiry that cannot be represented is an invalid response.
+    clock.advance(Duration::from_secs(10));
+    token_request(&credential);
+    credential.auth_respond(token_response(
+        r#"{"access_token": "token", "expires_in": 18446744073709551615}"#,
+    ));
+    assert!(matches!(
+        credential.auth_step(),
+        Err(AuthenticError::TokenResponse(_))
+    ));
+    Ok(())
+}
+
+/// A failed renewal does not affect callers while the current token is valid, and is retried
+/// after the retry interval.
+#[test]
+fn test_renewal_failure() -> Result<(), AuthenticError> {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let credential = OAuth2ClientCredential::client_secret_post(TOKEN_URL, "client", "secret")
+        .with_renewal_policy(RenewalPolicy::default().with_retry_interval(Duration::from_secs(30)))
+        .with_clock(clock.clone());
+    token_request(&credential);
+    credential.auth_respond(token_response(
+        r#"{"access_token": "token", "expires_in": 3600}"#,
+    ));
+
+    clock.advance(Duration::from_secs(1800));
+    token_request(&credential);
+    let response = http::Response::builder()
+        .status(http::StatusCode::SERVICE_UNAVAILABLE)
+        .body(Vec::new())?;
+    credential.auth_respond(Ok(response));
+    assert!(credential.auth_step()?.is_none());
+    assert_eq!(credential.fetch()?.token(), b"token");
+
+    clock.advance(Duration::from_secs(29));
+    assert!(credential.auth_step()?.is_none());
+    clock.advance(Duration::from_secs(1));
+    token_request(&credential);
+    Ok(())
+}
+
+/// Callers waiting for a token request that never receives a response retry after the request
+/// times out.
+#[::tokio::test]
+async fn test_abandoned_request() {
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let credential = OAuth2ClientCredential::client_secret_post(TOKEN_URL, "client", "secret")
+        .with_clock(clock.clone());
+    // The first caller makes a token request, and is dropped before the response.
+    token_request(&credential);
+
+    clock.advance(Duration::from_millis(59_950));
+    let waiter = match credential.auth_step() {
+        Ok(Some(AuthenticationStep::WaitForRenewal(waiter))) => waiter,
+        _ => panic!("Expected to wait for renewal"),
+    };
+    assert!(waiter.timeout() <= Duration::from_millis(50));
+    // No response arrives, so the caller stops waiting at the timeout.
+    let _ = ::tokio::time::timeout(waiter.timeout(), waiter).await;
+
+    clock.advance(Duration::from_millis(50));
+    token_request(&credential);
+}
+
+/// Stopping a refresher during a token request lets the request complete, so that waiting
+/// callers receive the token.
+#[cfg(feature = "tokio")]
+#[::tokio::test]
+async fn test_refresher_stop_during_request() -> Result<(), AuthenticError> {
+    use authentic::credential::CredentialRefresher;
+
+    let credential = Arc::new(OAuth2ClientCredential::client_secret_post(
+        TOKEN_URL, "client", "secret",
+    ));
+    let (requested, mut request_made) = ::tokio::sync::mpsc::channel(1);
+    let respond = Arc::new(::tokio::sync::Notify::new());
+    let refresher = CredentialRefresher::new(credential.clone()).spawn_task({
+        let respond = respond.clone();
+        move |_request| {
+            let requested = requested.clone();
+            let respond = respond.clone();
+            async move {
+                let _ = requested.send(()).await;
+                respond.notified().await;
+                token_response(r#"{"access_token": "token", "expires_in": 3600}"#)
+            }
+        }
+    });
+    request_made.recv().await;
+
+    // Another caller waits for the request made by the refresher.
+    let waiter = match credential.auth_step()? {
+        Some(AuthenticationStep::WaitForRenewal(waiter)) => waiter,
+        _ => panic!("Expected to wait for renewal"),
+    };
+    let stopped = refresher.stop();
+    respond.notify_one();
+    ::tokio::time::timeout(Duration::from_secs(5), waiter)
+        .await
+        .expect("waiter not woken");
+    assert_eq!(credential.fetch()?.token(), b"token");
+    ::tokio::time::timeout(Duration::from_secs(5), stopped)
+        .await
+        .expect("refresher did not stop")
+        .unwrap();
+    Ok(())
+}
+
+/// Dropping a refresher thread handle waits for a token request in progress to complete.
+#[cfg(feature = "refresh")]
+#[test]
+fn test_refresher_drop_during_request() -> Result<(), AuthenticError> {
+    use authentic::credential::CredentialRefresher;
+
+    let credential = Arc::new(OAuth2ClientCredential::client_secret_post(
+        TOKEN_URL, "client", "secret",
+    ));
+    let (requested, request_made) = std::sync::mpsc::sync_channel(1);
+    let refresher = CredentialRefresher::new(credential.clone()).spawn_thread(move |_request| {
+        let _ = requested.send(());
+        std::thread::sleep(Duration::from_millis(200));
+        token_response(r#"{"access_token": "token", "expires_in": 3600}"#)
+    });
+    request_made
+        .recv_timeout(Duration::from_secs(5))
+        .expect("no token request");
+    drop(refresher);
+    assert_eq!(credential.fetch()?.token(), b"token");
+    Ok(())
+}
+
+#[cfg(feature = "jwt")]
+mod jwt {
+    use super::*;
+
+    use authentic::credential::JsonWebTokenCredential;
+    use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+
+    fn key_path(name: &str) -> String {
+        format!("{}/tests/keys/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// Check the client assertion in a token request, returning its claims.
+    fn assertion_claims(
+        request: &http::Request<Vec<u8>>,
+        algorithm: Algorithm,
+        key: &DecodingKey,
+    ) -> serde_json::Value {
+        let form = form(request);
+        assert_eq!(param(&form, "grant_type"), Some("client_credentials"));
+        assert_eq!(param(&form, "client_id"), Some("client"));
+        assert_eq!(
+            param(&form, "client_assertion_type"),
+            Some("urn:ietf:params:oauth:client-assertion-type:jwt-bearer")
+        );
+        assert_eq!(param(&form, "client_secret"), None);
+        assert!(request.headers().get(http::header::AUTHORIZATION).is_none());
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[TOKEN_URL]);
+        validation.set_issuer(&["client"]);
+        validation.sub = Some("client".to_owned());
+        jsonwebtoken::decode::<serde_json::Value>(
+            param(&form, "client_assertion").unwrap(),
+            key,
+            &validation,
+        )
+        .unwrap()
+        .claims
+    }
+
+    #[test]
+    fn test_private_key_jwt() -> Result<(), AuthenticError> {
+        let clock = Arc::new(ManualClock::new(SystemTime::now()));
+        let assertion =
+            JsonWebTokenCredential::from_key_file(key_path("rsa.pem"), Duration::from_secs(60))?;
+        let credential = OAuth2ClientCredential::private_key_jwt(TOKEN_URL, "client", assertion)
+            .with_clock(clock.clone());
+        let public_key = DecodingKey::from_rsa_pem(&std::fs::read(key_path("rsa_public.pem"))?)?;
+
+        let first = assertion_claims(&token_request(&credential), Algorithm::RS256, &public_key);
+        assert!(first["exp"].as_u64().unwrap() <= first["iat"].as_u64().unwrap() + 60);
+        credential.auth_respond(token_response(
+            r#"{"access_token": "token", "expires_in": 60}"#,
+        ));
+        assert_eq!(credential.fetch()?.token(), b"token");
+
+        // Each token request uses a new assertion, with a unique JWT ID.
+        clock.advance(Duration::from_secs(30));
+        let second = assertion_claims(&token_request(&credential), Algorithm::RS256, &public_key);
+        assert!(first["jti"].is_string());
+        assert_ne!(first["jti"], second["jti"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_secret_jwt() {
+        let credential = OAuth2ClientCredential::client_secret_jwt(TOKEN_URL, "client", "secret");
+        let claims = assertion_claims(
+            &token_request(&credential),
+            Algorithm::HS256,
+            &DecodingKey::from_secret(b"secret"),
+        );
+        assert!(claims["jti"].is_string());
+    }
+}
+
+/// Access token from the test server's token endpoint, used as a bearer token.
+#[cfg(all(feature = "hyper", feature = "test-server"))]
+#[::tokio::test]
+async fn test_hyper_test_server() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>
+{
+    use authentic::hyper::{buffer_response, BearerAuthentication};
+    use authentic::test_server::TestServer;
+    use authentic::{AuthenticationProtocol, WithAuthentication};
+
+    let server = TestServer::builder()
+        .with_oauth2_client("client", "secret", "access-token")
+        .start()?;
+    let client = hyper::Client::new();
+    let credentials = [
+        OAuth2ClientCredential::client_secret_basic(server.token_url(), "client", "secret"),
+        OAuth2ClientCredential::client_secret_post(server.token_url(), "client", "secret"),
+    ];
+    for credential in credentials {
+        let mut authentication = BearerAuthentication::new(Arc::new(credential));
+        while let Some(auth_step) = authentication.step()? {
+            match auth_step {
+                AuthenticationStep::Request(request) => {
+                    let auth_response = buffer_response(client.request(request).await).await;
+                    authentication.respond(auth_response);
+                }
+                AuthenticationStep::WaitFor(duration) => {
+                    ::tokio::time::sleep(duration).await;
+                }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.await;
+                }
+            }
+        }
+        let request = hyper::Request::get(server.url("/bearer"))
+            .with_authentication(&authentication)?
+            .body(hyper::Body::empty())?;
+        let response = client.request(request).await?;
+        assert!(authentication.has_completed(&response)?);
+        assert_eq!(response.status(), http::StatusCode::OK);
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+#![cfg(feature = "loop")]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use authentic::credential::{
+    AuthenticationCredential, FetchedUsernamePassword, HttpRealmCredentials, RealmCallback,
+    RealmFallback, RealmPatterns, UsernamePasswordCredential,
+};
+use authentic::HttpScheme;
+
+fn credential(username: &str) -> Arc<UsernamePasswordCredential> {
+    Arc::new(UsernamePasswordCredential::new(
+        username.to_owned(),
+        "password",
+    ))
+}
+
+fn username(
+    credentials: &HttpRealmCredentials<UsernamePasswordCredential>,
+    origin: Option<&str>,
+    realm: &str,
+) -> Option<String> {
+    credentials
+        .fetch()
+        .unwrap()
+        .resolve(origin, realm, HttpScheme::Basic)
+        .map(|credential| credential.fetch().unwrap().username().to_owned())
+}
+
+/// Realms in a map are matched exactly.
+#[test]
+fn test_realm_map() {
+    let mut realm_credentials = HashMap::new();
+    realm_credentials.insert("Fake Realm".into(), credential("fake"));
+    let credentials = HttpRealmCredentials::new(realm_credentials);
+    assert_eq!(
+        username(&credentials, None, "Fake Realm").as_deref(),
+        Some("fake")
+    );
+    assert_eq!(username(&credentials, None, "Fake Realm 2"), None);
+}
+
+/// Patterns are tried in order, with wildcards matching any text.
+#[test]
+fn test_realm_patterns() {
+    let credentials = HttpRealmCredentials::from_resolver(
+        RealmPatterns::new()
+            .with_pattern("tenant-admin", credential("admin"))
+            .with_pattern("tenant-*", credential("tenant"))
+            .with_pattern("region-?", credential("region")),
+    );
+    assert_eq!(
+        username(&credentials, None, "tenant-admin").as_deref(),
+        Some("admin")
+    );
+    assert_eq!(
+        username(&credentials, None, "tenant-42").as_deref(),
+        Some("tenant")
+    );
+    assert_eq!(
+        username(&credentials, None, "tenant-").as_deref(),
+        Some("tenant")
+    );
+    assert_eq!(
+        username(&credentials, None, "region-a").as_deref(),
+        Some("region")
+    );
+    assert_eq!(username(&credentials, None, "region-ab"), None);
+    assert_eq!(username(&credentials, None, "other"), None);
+}
+
+/// Regular expressions and glob patterns are tried in the order they were added.
+#[cfg(feature = "regex")]
+#[test]
+fn test_realm_regex() {
+    let credentials = HttpRealmCredentials::from_resolver(
+        RealmPatterns::new()
+            .with_pattern("tenant-admin", credential("admin"))
+            .with_regex(
+                regex::Regex::new(r"^tenant-\d+$").unwrap(),
+                credential("tenant"),
+            )
+            .with_regex(regex::Regex::new("staging").unwrap(), credential("staging")),
+    );
+    assert_eq!(
+        username(&credentials, None, "tenant-admin").as_deref(),
+        Some("admin")
+    );
+    assert_eq!(
+        username(&credentials, None, "tenant-42").as_deref(),
+        Some("tenant")
+    );
+    assert_eq!(username(&credentials, None, "tenant-42a"), None);
+    assert_eq!(
+        username(&credentials, None, "eu-staging-1").as_deref(),
+        Some("staging")
+    );
+}
+
+/// The deprecated lookup by realm name is equivalent to resolving a Basic challenge.
+#[test]
+#[allow(deprecated)]
+fn test_realm_credential() {
+    let mut realm_credentials = HashMap::new();
+    realm_credentials.insert("Fake Realm".into(), credential("fake"));
+    let credentials = HttpRealmCredentials::new(realm_credentials);
+    let fetched = credentials.fetch().unwrap();
+    let found = fetched.credential("Fake Realm").unwrap();
+    assert_eq!(found.fetch().unwrap().username(), "fake");
+    assert!(fetched.credential("Other Realm").is_none());
+}
+
+/// A fallback credential is used for unknown realms.
+#[test]
+fn test_realm_fallback() {
+    let mut realm_credentials = HashMap::new();
+    realm_credentials.insert("Fake Realm".into(), credential("fake"));
+    let credentials = HttpRealmCredentials::from_resolver(RealmFallback::new(
+        realm_credentials,
+        credential("default"),
+    ));
+    assert_eq!(
+        username(&credentials, None, "Fake Realm").as_deref(),
+        Some("fake")
+    );
+    assert_eq!(
+        username(&credentials, None, "Other Realm").as_deref(),
+        Some("default")
+    );
+}
+
+/// Callbacks receive the origin, realm and scheme of the challenge.
+#[test]
+fn test_realm_callback() {
+    let credentials = HttpRealmCredentials::from_resolver(RealmCallback::new(
+        |origin: Option<&str>, realm: &str, scheme: HttpScheme| {
+            assert_eq!(scheme, HttpScheme::Basic);
+            match origin {
+                Some("https://example.com") => Some(credential(realm)),
+                _ => None,
+            }
+        },
+    ));
+    assert_eq!(
+        username(&credentials, Some("https://example.com"), "tenant-7").as_deref(),
+        Some("tenant-7")
+    );
+    assert_eq!(
+        username(&credentials, Some("https://example.org"), "tenant-7"),
+        None
+    );
+}
@@ -0,0 +1,166 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use authentic::credential::{RenewalNotifier, RenewalPolicy};
+use authentic::AuthenticError;
+
+/// Blocking waiters wake when notified, rather than waiting for the timeout.
+#[test]
+fn test_renewal_wait() {
+    let notifier = RenewalNotifier::new();
+    let waiters = (0..4)
+        .map(|_| notifier.waiter(Duration::from_secs(60)))
+        .collect::<Vec<_>>();
+    let start = Instant::now();
+    let threads = waiters
+        .into_iter()
+        .map(|waiter| std::thread::spawn(move || waiter.wait()))
+        .collect::<Vec<_>>();
+    std::thread::sleep(Duration::from_millis(50));
+    notifier.notify();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_secs(60));
+}
+
+/// A notification before waiting still wakes a waiter created before the notification.
+#[test]
+fn test_renewal_notified_before_wait() {
+    let notifier = RenewalNotifier::new();
+    let waiter = notifier.waiter(Duration::from_secs(60));
+    notifier.notify();
+    let start = Instant::now();
+    waiter.wait();
+    assert!(start.elapsed() < Duration::from_secs(60));
+}
+
+/// Blocking waiters return after the timeout if no notification occurs.
+#[test]
+fn test_renewal_wait_timeout() {
+    let notifier = RenewalNotifier::new();
+    let start = Instant::now();
+    notifier.waiter(Duration::from_millis(20)).wait();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+}
+
+/// Async waiters wake when notified.
+#[::tokio::test]
+async fn test_renewal_await() {
+    let notifier = RenewalNotifier::new();
+    let tasks = (0..4)
+        .map(|_| ::tokio::spawn(notifier.waiter(Duration::from_secs(60))))
+        .collect::<Vec<_>>();
+    ::tokio::time::sleep(Duration::from_millis(50)).await;
+    notifier.notify();
+    for task in tasks {
+        ::tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("waiter not woken")
+            .unwrap();
+    }
+}
+
+/// Async waiters do not start a timer, so callers stop waiting at the timeout using the timer of
+/// their runtime, for example when the renewing caller never receives a response.
+#[::tokio::test]
+async fn test_renewal_await_timeout() {
+    let notifier = RenewalNotifier::new();
+    let waiter = notifier.waiter(Duration::from_millis(20));
+    let start = Instant::now();
+    let _ = ::tokio::time::timeout(waiter.timeout(), waiter).await;
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    // A waiter polled after its timeout completes.
+    let waiter = notifier.waiter(Duration::from_millis(20));
+    ::tokio::time::sleep(Duration::from_millis(20)).await;
+    ::tokio::time::timeout(Duration::from_secs(5), waiter)
+        .await
+        .expect("waiter did not complete");
+}
+
+/// Renew times for different policies.
+#[test]
+fn test_renewal_policy() -> Result<(), AuthenticError> {
+    let issued = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let expiry = issued + Duration::from_secs(3600);
+
+    // By default, renew after half the lifetime.
+    assert_eq!(
+        RenewalPolicy::default().renew_time(issued, expiry),
+        issued + Duration::from_secs(1800)
+    );
+
+    let policy = RenewalPolicy::new().with_fraction(0.9)?;
+    assert_eq!(
+        policy.renew_time(issued, expiry),
+        issued + Duration::from_secs(3240)
+    );
+
+    // The margin applies when it is earlier than the fraction.
+    let policy = policy.with_margin(Duration::from_secs(600));
+    assert_eq!(
+        policy.renew_time(issued, expiry),
+        issued + Duration::from_secs(3000)
+    );
+
+    // A margin longer than the lifetime renews immediately.
+    let policy = policy.with_margin(Duration::from_secs(7200));
+    assert_eq!(policy.renew_time(issued, expiry), issued);
+    Ok(())
+}
+
+/// Fractions above 1.0 renew at expiry.
+#[test]
+fn test_renewal_policy_fraction_limit() -> Result<(), AuthenticError> {
+    let issued = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let expiry = issued + Duration::from_secs(3600);
+    let policy = RenewalPolicy::new().with_fraction(2.0)?;
+    assert_eq!(policy.renew_time(issued, expiry), expiry);
+    Ok(())
+}
+
+/// Fractions that would renew on every request are rejected.
+#[test]
+fn test_renewal_policy_invalid_fraction() {
+    for fraction in [0.0, -0.5, f64::NAN] {
+        assert!(
+            matches!(
+                RenewalPolicy::new().with_fraction(fraction),
+                Err(AuthenticError::Configuration(_))
+            ),
+            "{} accepted",
+            fraction
+        );
+    }
+}
+
+/// Jitter brings the renew time forward by a random amount.
+#[test]
+fn test_renewal_policy_jitter() {
+    let issued = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let expiry = issued + Duration::from_secs(3600);
+    let policy = RenewalPolicy::new().with_jitter(Duration::from_secs(300));
+    let renew_times = (0..20)
+        .map(|_| policy.renew_time(issued, expiry))
+        .collect::<Vec<_>>();
+    for renew in &renew_times {
+        assert!(*renew <= issued + Duration::from_secs(1800));
+        assert!(*renew >= issued + Duration::from_secs(1500));
+    }
+    assert!(renew_times.iter().any(|renew| *renew != renew_times[0]));
+}
+
+/// A failed renewal is retried after the retry interval, but no later than the expiry of a
+/// current token.
+#[test]
+fn test_renewal_policy_retry() {
+    let failed = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let policy = RenewalPolicy::new().with_retry_interval(Duration::from_secs(30));
+    let retry = failed + Duration::from_secs(30);
+    assert_eq!(policy.retry_time(failed, None), retry);
+    let expiry = failed + Duration::from_secs(10);
+    assert_eq!(policy.retry_time(failed, Some(expiry)), expiry);
+    // An expired token does not bring the retry forward.
+    let expired = failed - Duration::from_secs(10);
+    assert_eq!(policy.retry_time(failed, Some(expired)), retry);
+}
@@ -1,16 +1,25 @@
-#![cfg(feature = "reqwest-async")]
+#![cfg(all(feature = "reqwest-async", feature = "test-server"))]
 
 use std::sync::Arc;
 
 use authentic::credential::UsernamePasswordCredential;
 use authentic::reqwest::BasicAuthentication;
-use authentic::{AuthenticationProtocol, AuthenticationStep, WithAuthentication};
+use authentic::test_server::TestServer;
+use authentic::{AuthenticError, AuthenticationProtocol, AuthenticationStep, WithAuthentication};
 use http::StatusCode;
 
+/// Local server accepting the credentials `username` and `password` for the realm `Fake Realm`.
+fn server() -> Result<TestServer, AuthenticError> {
+    TestServer::builder()
+        .with_basic("Fake Realm", "username", "password")
+        .start()
+}
+
 /// Direct basic authentication, passing the username and password on the first request.
 /// In this test, the authentication is added to the RequestBuilder.
 #[::tokio::test]
 async fn test_basic_builder() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let server = server()?;
     let client = reqwest::Client::new();
 
     let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
@@ -28,10 +37,13 @@ async fn test_basic_builder() -> Result<(), Box<dyn std::error::Error + Send + S
                 AuthenticationStep::WaitFor(duration) => {
                     ::tokio::time::sleep(duration).await;
                 }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.await;
+                }
             }
         }
         let response = client
-            .get("https://httpbin.org/basic-auth/username/password")
+            .get(server.url("/basic-auth/username/password"))
             .with_authentication(&authentication)?
             .send()
             .await?;
@@ -55,6 +67,7 @@ async fn test_basic_builder() -> Result<(), Box<dyn std::error::Error + Send + S
 /// be created separately to the client.
 #[::tokio::test]
 async fn test_basic_request() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let server = server()?;
     let client = reqwest::Client::new();
 
     let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
@@ -72,11 +85,14 @@ async fn test_basic_request() -> Result<(), Box<dyn std::error::Error + Send + S
                 AuthenticationStep::WaitFor(duration) => {
                     ::tokio::time::sleep(duration).await;
                 }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.await;
+                }
             }
         }
         let request = reqwest::Request::new(
             reqwest::Method::GET,
-            reqwest::Url::parse("https://httpbin.org/basic-auth/username/password")?,
+            reqwest::Url::parse(&server.url("/basic-auth/username/password"))?,
         )
         .with_authentication(&authentication)?;
 
@@ -98,12 +114,33 @@ async fn test_basic_request() -> Result<(), Box<dyn std::error::Error + Send + S
     Ok(())
 }
 
+/// Configuring a request again, for example to retry it, replaces the `Authorization` header.
+#[test]
+fn test_basic_reconfigure() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
+    let authentication = BasicAuthentication::new(credential);
+    let request = reqwest::Request::new(
+        reqwest::Method::GET,
+        reqwest::Url::parse("https://example.com/")?,
+    )
+    .with_authentication(&authentication)?
+    .with_authentication(&authentication)?;
+    let values = request
+        .headers()
+        .get_all(reqwest::header::AUTHORIZATION)
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(values, ["Basic dXNlcm5hbWU6cGFzc3dvcmQ="]);
+    Ok(())
+}
+
 /// Basic authentication passing the username and password in response to a 401 challenge.
 ///
 /// `HttpAuthentication` is only supported with the `loop` feature.
 #[cfg(feature = "loop")]
 #[::tokio::test]
 async fn test_basic_challenge() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let server = server()?;
     let client = reqwest::Client::new();
 
     let mut realm_credentials = std::collections::HashMap::new();
@@ -128,10 +165,13 @@ async fn test_basic_challenge() -> Result<(), Box<dyn std::error::Error + Send +
                 AuthenticationStep::WaitFor(duration) => {
                     ::tokio::time::sleep(duration).await;
                 }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.await;
+                }
             }
         }
         let request = client
-            .get("https://httpbin.org/basic-auth/username/password")
+            .get(server.url("/basic-auth/username/password"))
             .build()?
             .with_authentication(&authentication)?;
 
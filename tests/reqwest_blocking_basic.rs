@@ -1,16 +1,25 @@
-#![cfg(feature = "reqwest-blocking")]
+#![cfg(all(feature = "reqwest-blocking", feature = "test-server"))]
 
 use std::sync::Arc;
 
 use authentic::credential::UsernamePasswordCredential;
 use authentic::reqwest::blocking::BasicAuthentication;
-use authentic::{AuthenticationProtocol, AuthenticationStep, WithAuthentication};
+use authentic::test_server::TestServer;
+use authentic::{AuthenticError, AuthenticationProtocol, AuthenticationStep, WithAuthentication};
 use http::StatusCode;
 
+/// Local server accepting the credentials `username` and `password` for the realm `Fake Realm`.
+fn server() -> Result<TestServer, AuthenticError> {
+    TestServer::builder()
+        .with_basic("Fake Realm", "username", "password")
+        .start()
+}
+
 /// Direct basic authentication, passing the username and password on the first request.
 /// In this test, the authentication is added to the RequestBuilder.
 #[test]
 fn test_basic_builder() -> Result<(), Box<dyn std::error::Error>> {
+    let server = server()?;
     let client = reqwest::blocking::Client::new();
 
     let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
@@ -25,10 +34,13 @@ fn test_basic_builder() -> Result<(), Box<dyn std::error::Error>> {
             AuthenticationStep::WaitFor(duration) => {
                 std::thread::sleep(duration);
             }
+            AuthenticationStep::WaitForRenewal(waiter) => {
+                waiter.wait();
+            }
         }
     }
     let response = client
-        .get("https://httpbin.org/basic-auth/username/password")
+        .get(server.url("/basic-auth/username/password"))
         .with_authentication(&authentication)?
         .send()?;
 
@@ -46,6 +58,7 @@ fn test_basic_builder() -> Result<(), Box<dyn std::error::Error>> {
 /// be created separately to the client.
 #[test]
 fn test_basic_request() -> Result<(), Box<dyn std::error::Error>> {
+    let server = server()?;
     let client = reqwest::blocking::Client::new();
 
     let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
@@ -63,11 +76,14 @@ fn test_basic_request() -> Result<(), Box<dyn std::error::Error>> {
                 AuthenticationStep::WaitFor(duration) => {
                     std::thread::sleep(duration);
                 }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.wait();
+                }
             }
         }
         let request = reqwest::blocking::Request::new(
             reqwest::Method::GET,
-            reqwest::Url::parse("https://httpbin.org/basic-auth/username/password")?,
+            reqwest::Url::parse(&server.url("/basic-auth/username/password"))?,
         )
         .with_authentication(&authentication)?;
 
@@ -89,12 +105,33 @@ fn test_basic_request() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Configuring a request again, for example to retry it, replaces the `Authorization` header.
+#[test]
+fn test_basic_reconfigure() -> Result<(), Box<dyn std::error::Error>> {
+    let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
+    let authentication = BasicAuthentication::new(credential);
+    let request = reqwest::blocking::Request::new(
+        reqwest::Method::GET,
+        reqwest::Url::parse("https://example.com/")?,
+    )
+    .with_authentication(&authentication)?
+    .with_authentication(&authentication)?;
+    let values = request
+        .headers()
+        .get_all(reqwest::header::AUTHORIZATION)
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(values, ["Basic dXNlcm5hbWU6cGFzc3dvcmQ="]);
+    Ok(())
+}
+
 /// Basic authentication passing the username and password in response to a 401 challenge.
 ///
 /// `HttpAuthentication` is only supported with the `loop` feature.
 #[cfg(feature = "loop")]
 #[test]
 fn test_basic_challenge() -> Result<(), Box<dyn std::error::Error>> {
+    let server = server()?;
     let client = reqwest::blocking::Client::new();
 
     let mut realm_credentials = std::collections::HashMap::new();
@@ -119,10 +156,13 @@ fn test_basic_challenge() -> Result<(), Box<dyn std::error::Error>> {
                 AuthenticationStep::WaitFor(duration) => {
                     std::thread::sleep(duration);
                 }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.wait();
+                }
             }
         }
         let request = client
-            .get("https://httpbin.org/basic-auth/username/password")
+            .get(server.url("/basic-auth/username/password"))
             .build()?
             .with_authentication(&authentication)?;
 
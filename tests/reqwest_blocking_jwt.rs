@@ -3,7 +3,7 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use authentic::credential::JsonWebTokenCredential;
+use authentic::credential::{JsonWebTokenCredential, ManualClock};
 use authentic::reqwest::blocking::BearerAuthentication;
 use authentic::{AuthenticationProtocol, AuthenticationStep, WithAuthentication};
 
@@ -41,15 +41,18 @@ uhFkBJn+pPZu8NiWCEDGq6xd3s/93jLWMTaS5PncRcMHpKpO72M=
 fn test_jwt() -> Result<(), Box<dyn std::error::Error>> {
     let client = reqwest::blocking::Client::new();
 
-    // Expiration time resolution is 1 second, so we need to wait at least that
-    // long to get a different JWT.
-    let expiration = Duration::from_secs(1);
+    let clock = Arc::new(ManualClock::default());
 
-    let credential = Arc::new(JsonWebTokenCredential::new(
-        jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
-        jsonwebtoken::EncodingKey::from_rsa_pem(PRIVATE_KEY)?,
-        expiration,
-    ));
+    let expiration = Duration::from_secs(10 * 60);
+
+    let credential = Arc::new(
+        JsonWebTokenCredential::new(
+            jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+            jsonwebtoken::EncodingKey::from_rsa_pem(PRIVATE_KEY)?,
+            expiration,
+        )
+        .with_clock(clock.clone()),
+    );
 
     let mut authentication = BearerAuthentication::new(credential.clone());
 
@@ -62,6 +65,9 @@ fn test_jwt() -> Result<(), Box<dyn std::error::Error>> {
             AuthenticationStep::WaitFor(duration) => {
                 std::thread::sleep(duration);
             }
+            AuthenticationStep::WaitForRenewal(waiter) => {
+                waiter.wait();
+            }
         }
     }
     let request = client
@@ -86,6 +92,9 @@ fn test_jwt() -> Result<(), Box<dyn std::error::Error>> {
             AuthenticationStep::WaitFor(duration) => {
                 std::thread::sleep(duration);
             }
+            AuthenticationStep::WaitForRenewal(waiter) => {
+                waiter.wait();
+            }
         }
     }
     let request = client
@@ -101,8 +110,8 @@ fn test_jwt() -> Result<(), Box<dyn std::error::Error>> {
 
     assert_eq!(auth1, auth2);
 
-    // Wait for JWT to expire
-    std::thread::sleep(expiration);
+    // Move the clock until the JWT expires
+    clock.advance(expiration);
 
     let mut authentication = BearerAuthentication::new(credential.clone());
 
@@ -115,6 +124,9 @@ fn test_jwt() -> Result<(), Box<dyn std::error::Error>> {
             AuthenticationStep::WaitFor(duration) => {
                 std::thread::sleep(duration);
             }
+            AuthenticationStep::WaitForRenewal(waiter) => {
+                waiter.wait();
+            }
         }
     }
     let request = client
@@ -132,3 +144,129 @@ fn test_jwt() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// JWT with registered and custom claims.
+#[test]
+fn test_jwt_claims() -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::blocking::Client::new();
+
+    #[derive(serde::Serialize)]
+    struct Extra {
+        scope: &'static str,
+    }
+
+    let credential = Arc::new(
+        JsonWebTokenCredential::new(
+            jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+            jsonwebtoken::EncodingKey::from_rsa_pem(PRIVATE_KEY)?,
+            Duration::from_secs(60),
+        )
+        .with_issuer("issuer")
+        .with_subject("subject")
+        .with_audiences(["audience1", "audience2"])
+        .with_not_before(Duration::from_secs(30))
+        .with_jwt_id()
+        .with_claim("email", "user@example.com")?
+        .with_claims(Extra { scope: "read" })?,
+    );
+
+    let mut authentication = BearerAuthentication::new(credential);
+
+    while let Some(auth_step) = authentication.step()? {
+        match auth_step {
+            AuthenticationStep::Request(request) => {
+                let auth_response = client.execute(request);
+                authentication.respond(auth_response);
+            }
+            AuthenticationStep::WaitFor(duration) => {
+                std::thread::sleep(duration);
+            }
+            AuthenticationStep::WaitForRenewal(waiter) => {
+                waiter.wait();
+            }
+        }
+    }
+    let request = client
+        .get("https://example.com")
+        .build()?
+        .with_authentication(&authentication)?;
+
+    let auth = request
+        .headers()
+        .get(reqwest::header::AUTHORIZATION)
+        .unwrap()
+        .to_str()?;
+
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+    validation.insecure_disable_signature_validation();
+    validation.set_audience(&["audience1"]);
+    let claims = jsonwebtoken::decode::<serde_json::Value>(
+        auth.strip_prefix("Bearer ").unwrap(),
+        &jsonwebtoken::DecodingKey::from_secret(b""),
+        &validation,
+    )?
+    .claims;
+
+    assert_eq!(claims["iss"], "issuer");
+    assert_eq!(claims["sub"], "subject");
+    assert_eq!(claims["aud"], serde_json::json!(["audience1", "audience2"]));
+    assert_eq!(
+        claims["nbf"].as_u64().unwrap() + 30,
+        claims["iat"].as_u64().unwrap()
+    );
+    assert_eq!(claims["jti"].as_str().unwrap().len(), 32);
+    assert_eq!(claims["email"], "user@example.com");
+    assert_eq!(claims["scope"], "read");
+
+    assert!(JsonWebTokenCredential::new(
+        jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+        jsonwebtoken::EncodingKey::from_rsa_pem(PRIVATE_KEY)?,
+        Duration::from_secs(60),
+    )
+    .with_claim("exp", 0)
+    .is_err());
+
+    Ok(())
+}
+
+/// Tokens renewed by a background refresher before requests need them.
+#[cfg(feature = "refresh")]
+#[test]
+fn test_jwt_refresher() -> Result<(), Box<dyn std::error::Error>> {
+    use authentic::credential::{AuthenticationCredential, CredentialRefresher, FetchedToken};
+    use authentic::AuthenticError;
+
+    let credential = Arc::new(
+        JsonWebTokenCredential::new(
+            jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+            jsonwebtoken::EncodingKey::from_secret(b"secret"),
+            Duration::from_secs(2),
+        )
+        .with_jwt_id(),
+    );
+
+    let refresher = CredentialRefresher::new(credential.clone())
+        .with_lead(Duration::from_millis(500))
+        .spawn_thread(|_| Err(AuthenticError::Other("Unexpected request".to_owned())));
+
+    // The first refresh creates a token.
+    refresher
+        .outcomes()
+        .recv_timeout(Duration::from_secs(5))??;
+    let first = credential.fetch()?.token().to_vec();
+
+    // The token is due for renewal after 1 second, and is renewed 500ms before then.
+    refresher
+        .outcomes()
+        .recv_timeout(Duration::from_secs(5))??;
+    let second = credential.fetch()?.token().to_vec();
+    assert_ne!(first, second);
+
+    // Requests use the renewed token without renewing it.
+    assert!(credential.auth_step()?.is_none());
+    assert_eq!(credential.fetch()?.token(), &second[..]);
+
+    refresher.stop();
+
+    Ok(())
+}
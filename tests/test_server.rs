@@ -0,0 +1,237 @@
+#![cfg(all(feature = "hyper", feature = "test-server"))]
+
+use std::sync::Arc;
+
+use authentic::credential::TokenCredential;
+use authentic::hyper::BearerAuthentication;
+use authentic::test_server::TestServer;
+use authentic::{AuthenticationProtocol, AuthenticationStep, WithAuthentication};
+use http::StatusCode;
+use hyper::{Body, Client, Request, Response};
+
+type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Send `request` authenticated by `authentication`, retrying until it completes.
+async fn send<Protocol>(
+    authentication: &mut Protocol,
+    url: &str,
+) -> Result<(Vec<StatusCode>, Response<Body>), Error>
+where
+    Protocol: AuthenticationProtocol<
+            Request = Request<Body>,
+            Response = Response<Body>,
+            Error = hyper::Error,
+        > + authentic::AuthenticationProtocolConfigure<http::request::Builder>,
+{
+    let client = Client::new();
+    let mut status_codes = Vec::new();
+    loop {
+        while let Some(auth_step) = authentication.step()? {
+            match auth_step {
+                AuthenticationStep::Request(request) => {
+                    let auth_response = client.request(request).await;
+                    authentication.respond(auth_response);
+                }
+                AuthenticationStep::WaitFor(duration) => {
+                    ::tokio::time::sleep(duration).await;
+                }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.await;
+                }
+            }
+        }
+        let request = Request::get(url)
+            .with_authentication(authentication)?
+            .body(Body::empty())?;
+        let response = client.request(request).await?;
+        status_codes.push(response.status());
+        if authentication.has_completed(&response)? {
+            return Ok((status_codes, response));
+        }
+    }
+}
+
+async fn body(response: Response<Body>) -> Result<String, Error> {
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+#[::tokio::test]
+async fn test_bearer_token() -> Result<(), Error> {
+    let server = TestServer::builder().with_bearer_token("token").start()?;
+
+    let credential = Arc::new(TokenCredential::new(b"wrong".to_vec()));
+    let mut authentication = BearerAuthentication::new(credential);
+    let (status_codes, _) = send(&mut authentication, &server.url("/")).await?;
+    assert_eq!(status_codes, [StatusCode::UNAUTHORIZED]);
+
+    let credential = Arc::new(TokenCredential::new(b"token".to_vec()));
+    let mut authentication = BearerAuthentication::new(credential);
+    let (status_codes, response) = send(&mut authentication, &server.url("/")).await?;
+    assert_eq!(status_codes, [StatusCode::OK]);
+    assert_eq!(body(response).await?, "Bearer");
+    Ok(())
+}
+
+/// The server sends a challenge for each realm, and the client chooses Digest.
+#[cfg(feature = "loop")]
+#[::tokio::test]
+async fn test_multiple_challenges() -> Result<(), Error> {
+    use authentic::credential::{HttpRealmCredentials, UsernamePasswordCredential};
+    use authentic::hyper::HttpAuthentication;
+
+    let server = TestServer::builder()
+        .with_basic("basic", "username", "password")
+        .with_digest("digest", "username", "secret")
+        .start()?;
+
+    let client = Client::new();
+    let response = client.get(server.url("/").parse()?).await?;
+    let challenges = response
+        .headers()
+        .get_all(http::header::WWW_AUTHENTICATE)
+        .iter()
+        .map(|value| value.to_str().map(|value| value.split(' ').next()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(challenges, [Some("Basic"), Some("Digest")]);
+
+    let mut realm_credentials = std::collections::HashMap::new();
+    realm_credentials.insert(
+        "basic".into(),
+        Arc::new(UsernamePasswordCredential::new("username", "password")),
+    );
+    realm_credentials.insert(
+        "digest".into(),
+        Arc::new(UsernamePasswordCredential::new("username", "secret")),
+    );
+    let credential = Arc::new(HttpRealmCredentials::new(realm_credentials));
+    let mut authentication = HttpAuthentication::new(credential);
+    let (status_codes, response) = send(&mut authentication, &server.url("/path?q=1")).await?;
+    assert_eq!(status_codes, [StatusCode::UNAUTHORIZED, StatusCode::OK]);
+    assert_eq!(body(response).await?, "Digest");
+    Ok(())
+}
+
+/// A Digest response is only accepted for the URI it was computed for.
+#[::tokio::test]
+async fn test_digest_uri_mismatch() -> Result<(), Error> {
+    use md5::{Digest, Md5};
+
+    let md5_hex = |data: String| -> String {
+        Md5::digest(data.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    };
+
+    let server = TestServer::builder()
+        .with_digest("digest", "username", "secret")
+        .start()?;
+    let client = Client::new();
+
+    let response = client.get(server.url("/").parse()?).await?;
+    let challenge = response.headers()[http::header::WWW_AUTHENTICATE].to_str()?;
+    let nonce = challenge
+        .split("nonce=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .ok_or("missing nonce")?
+        .to_owned();
+
+    let authorization = |uri: &str| {
+        let ha1 = md5_hex("username:digest:secret".to_owned());
+        let ha2 = md5_hex(format!("GET:{}", uri));
+        let response = md5_hex(format!("{}:{}:00000001:cnonce:auth:{}", ha1, nonce, ha2));
+        format!(
+            "Digest username=\"username\", realm=\"digest\", nonce=\"{}\", uri=\"{}\", \
+             qop=auth, nc=00000001, cnonce=\"cnonce\", response=\"{}\"",
+            nonce, uri, response
+        )
+    };
+
+    for (uri, expected) in [
+        ("/path?q=1", StatusCode::OK),
+        ("/other", StatusCode::UNAUTHORIZED),
+    ] {
+        let request = Request::get(server.url("/path?q=1"))
+            .header(http::header::AUTHORIZATION, authorization(uri))
+            .body(Body::empty())?;
+        let response = client.request(request).await?;
+        assert_eq!(response.status(), expected);
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+#[::tokio::test]
+async fn test_jwt() -> Result<(), Error> {
+    use authentic::credential::JsonWebTokenCredential;
+
+    let server = TestServer::builder()
+        .with_jwt(
+            jsonwebtoken::DecodingKey::from_secret(b"secret"),
+            jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256),
+        )
+        .start()?;
+
+    for (key, expected) in [
+        (&b"secret"[..], StatusCode::OK),
+        (&b"other"[..], StatusCode::UNAUTHORIZED),
+    ] {
+        let credential = Arc::new(JsonWebTokenCredential::new(
+            jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+            jsonwebtoken::EncodingKey::from_secret(key),
+            std::time::Duration::from_secs(60),
+        ));
+        let mut authentication = BearerAuthentication::new(credential);
+        let (status_codes, _) = send(&mut authentication, &server.url("/")).await?;
+        assert_eq!(status_codes, [expected]);
+    }
+    Ok(())
+}
+
+#[::tokio::test]
+async fn test_oauth2_token_endpoint() -> Result<(), Error> {
+    let server = TestServer::builder()
+        .with_oauth2_client("client", "secret", "access-token")
+        .start()?;
+    let client = Client::new();
+
+    let token_request = |authorization: &str, body: &'static str| {
+        Request::post(server.token_url())
+            .header(http::header::AUTHORIZATION, authorization)
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(Body::from(body))
+    };
+
+    let basic = http_auth::basic::encode_credentials("client", "secret");
+    let response = client
+        .request(token_request(&basic, "grant_type=client_credentials")?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let token: serde_json::Value = serde_json::from_str(&body(response).await?)?;
+    assert_eq!(token["access_token"], "access-token");
+    assert_eq!(token["token_type"], "Bearer");
+
+    let response = client
+        .request(token_request(
+            "",
+            "grant_type=client_credentials&client_id=client&client_secret=wrong",
+        )?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .request(token_request(&basic, "grant_type=password")?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let credential = Arc::new(TokenCredential::new(b"access-token".to_vec()));
+    let mut authentication = BearerAuthentication::new(credential);
+    let (status_codes, _) = send(&mut authentication, &server.url("/")).await?;
+    assert_eq!(status_codes, [StatusCode::OK]);
+    Ok(())
+}
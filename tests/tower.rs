@@ -0,0 +1,150 @@
+#![cfg(all(feature = "hyper", feature = "tower", feature = "loop"))]
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use authentic::credential::{HttpRealmCredentials, TokenCredential, UsernamePasswordCredential};
+use authentic::hyper::{BearerAuthentication, HttpAuthentication};
+use authentic::tower::{Authenticated, AuthenticationLayer};
+use authentic::verifier::{BasicVerifier, TokenVerifier};
+use authentic::{AuthenticationProtocol, AuthenticationStep, WithAuthentication};
+use http::StatusCode;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Request, Response, Server};
+use tower_layer::Layer;
+
+type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Respond with the authenticated principal.
+async fn handler(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let extensions = request.extensions();
+    let principal = if let Some(Authenticated(username)) = extensions.get::<Authenticated<String>>()
+    {
+        format!("user {}", username)
+    } else if let Some(Authenticated(index)) = extensions.get::<Authenticated<usize>>() {
+        format!("token {}", index)
+    } else {
+        "anonymous".to_owned()
+    };
+    Ok(Response::new(Body::from(principal)))
+}
+
+/// Start a server accepting Basic credentials or a bearer token.
+fn spawn_server() -> SocketAddr {
+    let mut users = HashMap::new();
+    users.insert("username", "password");
+    let layer = AuthenticationLayer::new()
+        .with_verifier(BasicVerifier::new("Fake Realm", users))
+        .with_verifier(TokenVerifier::new("api").with_token(&b"token"[..]));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let make_service = make_service_fn(move |_| {
+        let service = layer.layer(service_fn(handler));
+        async move { Ok::<_, Infallible>(service) }
+    });
+    let server = Server::from_tcp(listener).unwrap().serve(make_service);
+    tokio::spawn(server);
+    addr
+}
+
+/// Send a request authenticated by `authentication`, retrying until it completes.
+async fn send<Protocol>(
+    authentication: &mut Protocol,
+    url: &str,
+) -> Result<(Vec<StatusCode>, String), Error>
+where
+    Protocol: AuthenticationProtocol<
+            Request = Request<Body>,
+            Response = Response<Body>,
+            Error = hyper::Error,
+        > + authentic::AuthenticationProtocolConfigure<http::request::Builder>,
+{
+    let client = Client::new();
+    let mut status_codes = Vec::new();
+    loop {
+        while let Some(auth_step) = authentication.step()? {
+            match auth_step {
+                AuthenticationStep::Request(request) => {
+                    let auth_response = client.request(request).await;
+                    authentication.respond(auth_response);
+                }
+                AuthenticationStep::WaitFor(duration) => {
+                    ::tokio::time::sleep(duration).await;
+                }
+                AuthenticationStep::WaitForRenewal(waiter) => {
+                    waiter.await;
+                }
+            }
+        }
+        let request = Request::get(url)
+            .with_authentication(authentication)?
+            .body(Body::empty())?;
+        let response = client.request(request).await?;
+        status_codes.push(response.status());
+        if authentication.has_completed(&response)? {
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            return Ok((status_codes, String::from_utf8(body.to_vec())?));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_challenges() -> Result<(), Error> {
+    let addr = spawn_server();
+
+    let response = Client::new()
+        .get(format!("http://{}/", addr).parse()?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let challenges = response
+        .headers()
+        .get_all(http::header::WWW_AUTHENTICATE)
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        challenges,
+        [
+            r#"Basic realm="Fake Realm", charset="UTF-8""#,
+            r#"Bearer realm="api""#
+        ]
+    );
+    Ok(())
+}
+
+/// `HttpAuthentication` responds to the challenge from the layer.
+#[tokio::test]
+async fn test_http_authentication() -> Result<(), Error> {
+    let addr = spawn_server();
+
+    let mut realm_credentials = HashMap::new();
+    realm_credentials.insert(
+        "Fake Realm".into(),
+        Arc::new(UsernamePasswordCredential::new("username", "password")),
+    );
+    let credential = Arc::new(HttpRealmCredentials::new(realm_credentials));
+    let mut authentication = HttpAuthentication::new(credential);
+    let (status_codes, body) = send(&mut authentication, &format!("http://{}/", addr)).await?;
+    assert_eq!(status_codes, [StatusCode::UNAUTHORIZED, StatusCode::OK]);
+    assert_eq!(body, "user username");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bearer_authentication() -> Result<(), Error> {
+    let addr = spawn_server();
+
+    let credential = Arc::new(TokenCredential::new(&b"token"[..]));
+    let mut authentication = BearerAuthentication::new(credential);
+    let (status_codes, body) = send(&mut authentication, &format!("http://{}/", addr)).await?;
+    assert_eq!(status_codes, [StatusCode::OK]);
+    assert_eq!(body, "token 0");
+
+    let credential = Arc::new(TokenCredential::new(&b"wrong"[..]));
+    let mut authentication = BearerAuthentication::new(credential);
+    let (status_codes, _) = send(&mut authentication, &format!("http://{}/", addr)).await?;
+    assert_eq!(status_codes, [StatusCode::UNAUTHORIZED]);
+    Ok(())
+}
@@ -0,0 +1,171 @@
+#![cfg(all(feature = "hyper", feature = "verifier"))]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use authentic::credential::{TokenCredential, UsernamePasswordCredential};
+use authentic::hyper::{BasicAuthentication, BearerAuthentication, HeaderAuthentication};
+use authentic::verifier::{BasicVerifier, BearerError, TokenVerifier, Verifier};
+use authentic::{AuthenticError, AuthenticationProtocolConfigure, WithAuthentication};
+use http::{HeaderMap, StatusCode};
+
+/// The headers sent by a client using `protocol`.
+fn client_headers<Protocol>(protocol: &Protocol) -> Result<HeaderMap, AuthenticError>
+where
+    Protocol: AuthenticationProtocolConfigure<http::request::Builder>,
+{
+    let request = http::Request::get("https://example.com/")
+        .with_authentication(protocol)?
+        .body(())?;
+    Ok(request.headers().clone())
+}
+
+#[test]
+fn test_basic() -> Result<(), AuthenticError> {
+    let mut users = HashMap::new();
+    users.insert("username", "password");
+    let verifier = BasicVerifier::new("Fake Realm", users);
+
+    let credential = Arc::new(UsernamePasswordCredential::new("username", "password"));
+    let headers = client_headers(&BasicAuthentication::new(credential))?;
+    assert_eq!(verifier.verify(&headers).unwrap(), "username");
+
+    let credential = Arc::new(UsernamePasswordCredential::new("username", "wrong"));
+    let headers = client_headers(&BasicAuthentication::new(credential))?;
+    let rejection = verifier.verify(&headers).unwrap_err();
+    assert_eq!(rejection.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(rejection.error(), None);
+    assert_eq!(
+        rejection.www_authenticate()?.unwrap(),
+        r#"Basic realm="Fake Realm", charset="UTF-8""#
+    );
+
+    let rejection = verifier.verify(&HeaderMap::new()).unwrap_err();
+    assert_eq!(rejection.status(), StatusCode::UNAUTHORIZED);
+    Ok(())
+}
+
+#[test]
+fn test_token() -> Result<(), AuthenticError> {
+    let verifier = TokenVerifier::new("api")
+        .with_token(&b"first"[..])
+        .with_token(&b"second"[..]);
+
+    let credential = Arc::new(TokenCredential::new(&b"second"[..]));
+    let headers = client_headers(&BearerAuthentication::new(credential))?;
+    assert_eq!(verifier.verify(&headers).unwrap(), 1);
+
+    let credential = Arc::new(TokenCredential::new(&b"third"[..]));
+    let headers = client_headers(&BearerAuthentication::new(credential.clone()))?;
+    let rejection = verifier.verify(&headers).unwrap_err();
+    assert_eq!(rejection.error(), Some(BearerError::InvalidToken));
+    assert_eq!(
+        rejection.www_authenticate()?.unwrap(),
+        r#"Bearer realm="api", error="invalid_token""#
+    );
+
+    // A token using another scheme is treated as missing.
+    let headers = client_headers(&BearerAuthentication::new(credential).with_auth_scheme("Token"))?;
+    let rejection = verifier.verify(&headers).unwrap_err();
+    assert_eq!(rejection.error(), None);
+    assert_eq!(
+        rejection.www_authenticate()?.unwrap(),
+        r#"Bearer realm="api""#
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.append(http::header::AUTHORIZATION, "Bearer first".parse()?);
+    headers.append(http::header::AUTHORIZATION, "Bearer second".parse()?);
+    let rejection = verifier.verify(&headers).unwrap_err();
+    assert_eq!(rejection.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(rejection.error(), Some(BearerError::InvalidRequest));
+    Ok(())
+}
+
+#[test]
+fn test_token_header() -> Result<(), AuthenticError> {
+    let verifier = TokenVerifier::new("api")
+        .with_token(&b"token"[..])
+        .with_header("x-api-key");
+
+    let credential = Arc::new(TokenCredential::new(&b"token"[..]));
+    let headers = client_headers(&HeaderAuthentication::new("x-api-key", credential))?;
+    assert_eq!(verifier.verify(&headers).unwrap(), 0);
+
+    let rejection = verifier.verify(&HeaderMap::new()).unwrap_err();
+    assert!(rejection.www_authenticate()?.is_none());
+    Ok(())
+}
+
+#[cfg(all(feature = "jwt", feature = "step"))]
+#[test]
+fn test_jwt() -> Result<(), AuthenticError> {
+    use std::time::Duration;
+
+    use authentic::credential::{JsonWebTokenCredential, ManualClock};
+    use authentic::verifier::JwtVerifier;
+    use authentic::AuthenticationProtocol;
+    use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header};
+
+    // The token is created by the first step.
+    let jwt_headers = |credential| -> Result<HeaderMap, AuthenticError> {
+        let authentication = BearerAuthentication::new(credential);
+        assert!(authentication.step()?.is_none());
+        client_headers(&authentication)
+    };
+
+    let verifier: JwtVerifier =
+        JwtVerifier::new("api", DecodingKey::from_secret(b"secret"), Algorithm::HS256)
+            .with_leeway(Duration::from_secs(5));
+
+    let credential = Arc::new(JsonWebTokenCredential::new(
+        Header::new(Algorithm::HS256),
+        EncodingKey::from_secret(b"secret"),
+        Duration::from_secs(60),
+    ));
+    let headers = jwt_headers(credential)?;
+    let claims = verifier.verify(&headers).unwrap();
+    assert!(claims["exp"].is_u64());
+
+    // An old token has expired.
+    let clock = Arc::new(ManualClock::new(
+        std::time::SystemTime::now() - Duration::from_secs(3600),
+    ));
+    let credential = Arc::new(
+        JsonWebTokenCredential::new(
+            Header::new(Algorithm::HS256),
+            EncodingKey::from_secret(b"secret"),
+            Duration::from_secs(60),
+        )
+        .with_clock(clock),
+    );
+    let headers = jwt_headers(credential)?;
+    let rejection = verifier.verify(&headers).unwrap_err();
+    assert_eq!(rejection.error(), Some(BearerError::InvalidToken));
+    assert_eq!(rejection.description(), "Token has expired");
+
+    // The issuer is required once configured.
+    let verifier = verifier.with_issuer(&["issuer"]);
+    let credential = Arc::new(JsonWebTokenCredential::new(
+        Header::new(Algorithm::HS256),
+        EncodingKey::from_secret(b"secret"),
+        Duration::from_secs(60),
+    ));
+    let headers = jwt_headers(credential)?;
+    let rejection = verifier.verify(&headers).unwrap_err();
+    assert_eq!(
+        rejection.description(),
+        "Invalid token: Missing required claim: iss"
+    );
+    let credential = Arc::new(
+        JsonWebTokenCredential::new(
+            Header::new(Algorithm::HS256),
+            EncodingKey::from_secret(b"secret"),
+            Duration::from_secs(60),
+        )
+        .with_issuer("issuer"),
+    );
+    let headers = jwt_headers(credential)?;
+    assert_eq!(verifier.verify(&headers).unwrap()["iss"], "issuer");
+    Ok(())
+}